@@ -19,6 +19,16 @@ pub struct Character {
     value: char,
 }
 
+impl ItemName for Character {
+    fn item_name() -> String {
+        "Character".to_string()
+    }
+
+    fn item_schema() -> String {
+        "Character { id: Id, value: char }".to_string()
+    }
+}
+
 impl Document {
     pub fn from_str(value: &str) -> Self {
         let mut document = Self {
@@ -74,6 +84,12 @@ impl Mergeable for Document {
     }
 }
 
+impl Tagged for Document {
+    fn type_tag() -> TypeTag {
+        TypeTag::new("Document")
+    }
+}
+
 impl Serialize for Document {
     async fn serialize(&self, cx: SerializeCx<'_>) -> Result<Vec<Ref>> {
         self.contents.serialize(cx).await
@@ -93,12 +109,12 @@ async fn main() -> Result<()> {
 
     const INITIAL_TEXT: &str = "-";
 
-    let hostname = std::env::var("SCYLLA_URL").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let config = QuarkStoreConfig::from_env()?;
 
     println!("Setting up datastores...");
     let mut stores = Vec::with_capacity(REPLICAS);
     for _ in 0..REPLICAS + 1 {
-        let store = QuarkStore::setup(hostname.clone(), "test").await.unwrap();
+        let store = QuarkStore::connect(config.clone()).await.unwrap();
         stores.push(Some(store));
     }
 
@@ -19,6 +19,16 @@ pub struct Character {
     value: char,
 }
 
+impl ItemName for Character {
+    fn item_name() -> String {
+        "Character".to_string()
+    }
+
+    fn item_schema() -> String {
+        "Character { id: Id, value: char }".to_string()
+    }
+}
+
 impl Document {
     pub fn from_str(value: &str) -> Self {
         let mut document = Self::default();
@@ -61,6 +71,12 @@ impl Mergeable for Document {
     }
 }
 
+impl Tagged for Document {
+    fn type_tag() -> TypeTag {
+        TypeTag::new("Document")
+    }
+}
+
 impl Serialize for Document {
     async fn serialize(&self, cx: SerializeCx<'_>) -> Result<Vec<Ref>> {
         self.contents.serialize(cx).await
@@ -105,11 +121,11 @@ async fn main() -> Result<()> {
     env_logger::init();
 
     let args = Args::parse();
-    let hostname = std::env::var("SCYLLA_URL").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let config = QuarkStoreConfig::from_env()?;
 
     if args.setup {
         println!("Setting up database...");
-        let store = QuarkStore::setup(hostname.clone(), "test").await.unwrap();
+        let store = QuarkStore::connect(config).await.unwrap();
         println!("Connected to database!");
         let main_replica = Id::gen();
         let document = Document::from_str(include_str!("../data/text.txt"));
@@ -150,8 +166,8 @@ async fn main() -> Result<()> {
 
     let replica_id = replica_ids.remove(args.index);
 
-    let hostname = std::env::var("SCYLLA_URL").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
-    let store = QuarkStore::setup(hostname.clone(), "test").await.unwrap();
+    let config = QuarkStoreConfig::from_env()?;
+    let store = QuarkStore::connect(config).await.unwrap();
 
     let mut replica = Replica::clone(replica_id, store).await.unwrap();
 
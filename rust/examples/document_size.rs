@@ -17,6 +17,16 @@ pub struct Character {
     value: char,
 }
 
+impl ItemName for Character {
+    fn item_name() -> String {
+        "Character".to_string()
+    }
+
+    fn item_schema() -> String {
+        "Character { id: Id, value: char }".to_string()
+    }
+}
+
 impl Document {
     pub fn from_str(value: &str) -> Self {
         let mut document = Self {
@@ -68,6 +78,12 @@ impl Mergeable for Document {
     }
 }
 
+impl Tagged for Document {
+    fn type_tag() -> TypeTag {
+        TypeTag::new("Document")
+    }
+}
+
 impl Serialize for Document {
     async fn serialize(&self, cx: SerializeCx<'_>) -> Result<Vec<Ref>> {
         self.contents.serialize(cx).await
@@ -86,8 +102,8 @@ async fn main() -> Result<()> {
     env_logger::init();
 
     async fn setup_replica() -> Result<Replica> {
-        let hostname = std::env::var("SCYLLA_URL").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
-        let store = QuarkStore::setup(hostname.clone(), "test").await.unwrap();
+        let config = QuarkStoreConfig::from_env()?;
+        let store = QuarkStore::connect(config).await.unwrap();
 
         let main_replica = Id::gen();
         let base_set_ref = store.insert(&Document::from_str(".")).await?;
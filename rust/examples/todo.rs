@@ -15,20 +15,20 @@ pub struct TodoItem {
 
 impl PartialOrd for TodoItem {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        for (a, b) in self.text.iter().zip(other.text.iter()) {
-            match a.partial_cmp(b) {
-                Some(Ordering::Less) => return Some(Ordering::Less),
-                Some(Ordering::Greater) => return Some(Ordering::Greater),
-                _ => continue,
-            }
-        }
-        self.done.partial_cmp(&other.done)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for TodoItem {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+        for (a, b) in self.text.iter().zip(other.text.iter()) {
+            match a.cmp(b) {
+                Ordering::Less => return Ordering::Less,
+                Ordering::Greater => return Ordering::Greater,
+                Ordering::Equal => continue,
+            }
+        }
+        self.done.cmp(&other.done)
     }
 }
 
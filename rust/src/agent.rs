@@ -0,0 +1,395 @@
+use std::{cmp::Ordering, marker::PhantomData, time::Duration};
+
+use anyhow::{anyhow, Result};
+use rand::seq::SliceRandom;
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::{
+    Commit, Deserialize, Mergeable, Replica, ReplicaId, Serialize, VectorClock, VersionedStore,
+};
+
+/// Determines with which peers a [`SyncAgent`] merges in every round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerSelection {
+    /// Merges with every peer.
+    AllToAll,
+    /// Merges with the next replica when ordering the replica and its peers by id, so changes
+    /// travel around the ring.
+    Ring,
+    /// Merges with the given number of randomly chosen peers.
+    Gossip { fanout: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncAgentConfig {
+    pub peers: Vec<ReplicaId>,
+    pub selection: PeerSelection,
+    /// The time between two sync rounds.
+    pub interval: Duration,
+    /// The upper bound for the delay after failed rounds, which doubles with every failure.
+    pub max_backoff: Duration,
+}
+
+impl SyncAgentConfig {
+    pub fn new(peers: Vec<ReplicaId>, selection: PeerSelection) -> Self {
+        Self {
+            peers,
+            selection,
+            interval: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+}
+
+/// The state of a [`SyncAgent`] after its latest sync round.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncStatus {
+    pub rounds: u64,
+    pub version: VectorClock,
+    /// Peers whose latest commit contains changes which have not been merged yet.
+    pub behind: Vec<ReplicaId>,
+    /// True if the replica contains the changes of all of its peers.
+    pub converged: bool,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+enum Command<T> {
+    Commit(T, oneshot::Sender<Result<Commit>>),
+    Latest(oneshot::Sender<Result<Option<T>>>),
+    Notify,
+    Shutdown,
+}
+
+/// Owns a [`Replica`] and merges it with its peers in the background, so the application only
+/// has to commit its local changes through the [`SyncAgentHandle`].
+///
+/// The agent does its work in [`SyncAgent::run`]. The future is not `Send`, as the futures of the
+/// store are not, so it has to be driven on the current task, e.g. with `tokio::join!`, or
+/// spawned on a [`tokio::task::LocalSet`] with [`SyncAgent::spawn_local`].
+pub struct SyncAgent<T> {
+    replica: Replica,
+    config: SyncAgentConfig,
+    commands: mpsc::UnboundedReceiver<Command<T>>,
+    status: watch::Sender<SyncStatus>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+/// Controls a running [`SyncAgent`].
+pub struct SyncAgentHandle<T> {
+    commands: mpsc::UnboundedSender<Command<T>>,
+    status: watch::Receiver<SyncStatus>,
+}
+
+impl<T> Clone for SyncAgentHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            commands: self.commands.clone(),
+            status: self.status.clone(),
+        }
+    }
+}
+
+impl<T: Serialize + Deserialize + Mergeable> SyncAgent<T> {
+    pub fn new(replica: Replica, config: SyncAgentConfig) -> (Self, SyncAgentHandle<T>) {
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+        let (status_tx, status_rx) = watch::channel(SyncStatus {
+            version: replica.latest_version().clone(),
+            ..Default::default()
+        });
+        let agent = Self {
+            replica,
+            config,
+            commands: commands_rx,
+            status: status_tx,
+            _marker: PhantomData,
+        };
+        let handle = SyncAgentHandle {
+            commands: commands_tx,
+            status: status_rx,
+        };
+        (agent, handle)
+    }
+
+    /// Runs sync rounds until the agent is shut down or all handles are dropped, and returns the
+    /// replica afterwards.
+    pub async fn run(mut self) -> Replica {
+        let mut next_round = Instant::now() + self.config.interval;
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(Command::Commit(object, reply)) => {
+                        let _ = reply.send(self.replica.commit_object(&object).await);
+                    }
+                    Some(Command::Latest(reply)) => {
+                        let _ = reply.send(self.replica.latest_object().await);
+                    }
+                    Some(Command::Notify) => {
+                        next_round = Instant::now() + self.sync_round().await;
+                    }
+                    Some(Command::Shutdown) | None => break,
+                },
+                _ = tokio::time::sleep_until(next_round) => {
+                    next_round = Instant::now() + self.sync_round().await;
+                }
+            }
+        }
+        self.replica
+    }
+
+    /// Spawns [`SyncAgent::run`] on the current [`tokio::task::LocalSet`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a local task set.
+    pub fn spawn_local(self) -> JoinHandle<Replica>
+    where
+        T: 'static,
+    {
+        tokio::task::spawn_local(self.run())
+    }
+
+    /// Runs a single sync round and returns the delay until the next one.
+    async fn sync_round(&mut self) -> Duration {
+        let result = self.merge_with_peers().await;
+
+        let mut status = self.status.borrow().clone();
+        status.rounds += 1;
+        status.version = self.replica.latest_version().clone();
+        let delay = match result {
+            Ok(behind) => {
+                status.converged = behind.is_empty();
+                status.behind = behind;
+                status.consecutive_failures = 0;
+                status.last_error = None;
+                self.config.interval
+            }
+            Err(err) => {
+                log::warn!(
+                    "Sync round of replica {} failed: {err:#}",
+                    self.replica.id()
+                );
+                status.consecutive_failures += 1;
+                status.last_error = Some(format!("{err:#}"));
+                let factor = 2u32.saturating_pow(status.consecutive_failures);
+                self.config
+                    .interval
+                    .saturating_mul(factor)
+                    .min(self.config.max_backoff)
+            }
+        };
+        self.status.send_replace(status);
+        delay
+    }
+
+    /// Merges with the selected peers and returns all peers which still have unmerged changes.
+    /// Retired peers are skipped.
+    async fn merge_with_peers(&mut self) -> Result<Vec<ReplicaId>> {
+        let retired = self.replica.store().retired_replicas().await?;
+        for peer in self.select_peers() {
+            if retired.contains(&peer) {
+                continue;
+            }
+            let Some(head) = self.replica.store().latest_commit_for_replica(peer).await? else {
+                continue;
+            };
+            if !has_unmerged_changes(&head.version, self.replica.latest_version()) {
+                continue;
+            }
+            log::debug!("Replica {} merges with {peer}", self.replica.id());
+            self.replica.merge_with::<T>(peer).await?;
+        }
+
+        let mut behind = Vec::new();
+        for peer in self
+            .config
+            .peers
+            .iter()
+            .filter(|peer| !retired.contains(peer))
+        {
+            let head = self
+                .replica
+                .store()
+                .latest_commit_for_replica(*peer)
+                .await?;
+            if head.is_some_and(|head| {
+                has_unmerged_changes(&head.version, self.replica.latest_version())
+            }) {
+                behind.push(*peer);
+            }
+        }
+        Ok(behind)
+    }
+
+    fn select_peers(&self) -> Vec<ReplicaId> {
+        let peers = &self.config.peers;
+        match self.config.selection {
+            PeerSelection::AllToAll => peers.clone(),
+            PeerSelection::Ring => {
+                let mut ring = peers.clone();
+                ring.push(self.replica.id());
+                ring.sort();
+                ring.dedup();
+                let ix = ring
+                    .iter()
+                    .position(|id| *id == self.replica.id())
+                    .unwrap_or_default();
+                let next = ring[(ix + 1) % ring.len()];
+                if next == self.replica.id() {
+                    Vec::new()
+                } else {
+                    vec![next]
+                }
+            }
+            PeerSelection::Gossip { fanout } => peers
+                .choose_multiple(&mut rand::thread_rng(), fanout)
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+impl<T> SyncAgentHandle<T> {
+    /// Commits the object on the replica of the agent.
+    pub async fn commit(&self, object: T) -> Result<Commit> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Commit(object, reply))?;
+        response.await.map_err(|_| agent_stopped())?
+    }
+
+    /// Returns the object of the latest commit of the replica.
+    pub async fn latest(&self) -> Result<Option<T>> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::Latest(reply))?;
+        response.await.map_err(|_| agent_stopped())?
+    }
+
+    /// Starts a sync round right away instead of waiting for the next interval.
+    pub fn notify(&self) -> Result<()> {
+        self.send(Command::Notify)
+    }
+
+    pub fn shutdown(&self) -> Result<()> {
+        self.send(Command::Shutdown)
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Waits until the replica contains the changes of all of its peers.
+    pub async fn converged(&mut self) -> Result<SyncStatus> {
+        let status = self
+            .status
+            .wait_for(|status| status.converged)
+            .await
+            .map_err(|_| agent_stopped())?;
+        Ok(status.clone())
+    }
+
+    fn send(&self, command: Command<T>) -> Result<()> {
+        self.commands.send(command).map_err(|_| agent_stopped())
+    }
+}
+
+/// Returns true if the version of a peer is ahead of or concurrent to the given version.
+fn has_unmerged_changes(peer_version: &VectorClock, version: &VectorClock) -> bool {
+    !matches!(
+        peer_version.partial_cmp(version),
+        Some(Ordering::Less | Ordering::Equal)
+    )
+}
+
+fn agent_stopped() -> anyhow::Error {
+    anyhow!("Sync agent has stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashSet, Id, QuarkStore, RefStore};
+
+    #[tokio::test]
+    async fn test_agent_merges_with_peers() {
+        let store = QuarkStore::test();
+        let (main, peer_a, peer_b) = (Id::gen(), Id::gen(), Id::gen());
+
+        let mut base_version = VectorClock::default();
+        base_version.inc(main);
+        let base = HashSet::from_iter([1u64]);
+        let root = store.insert(&base).await.unwrap();
+        store
+            .commit(main, base_version.clone(), root)
+            .await
+            .unwrap();
+
+        for (peer, item) in [(peer_a, 2u64), (peer_b, 3u64)] {
+            let mut version = base_version.clone();
+            version.inc(peer);
+            let root = store.insert(&HashSet::from_iter([1, item])).await.unwrap();
+            store.commit(peer, version, root).await.unwrap();
+        }
+
+        let replica = Replica::clone(main, store).await.unwrap();
+        let config = SyncAgentConfig::new(vec![peer_a, peer_b], PeerSelection::AllToAll)
+            .with_interval(Duration::from_secs(3600));
+        let (agent, mut handle) = SyncAgent::<HashSet<u64>>::new(replica, config);
+        assert!(!handle.status().converged);
+
+        let driver = async {
+            handle.notify()?;
+            let status = handle.converged().await?;
+            assert_eq!(status.rounds, 1);
+            assert!(status.behind.is_empty());
+
+            let latest = handle.latest().await?.unwrap();
+            assert_eq!(latest, HashSet::from_iter([1, 2, 3]));
+            handle.commit(HashSet::from_iter([1, 2, 3, 4])).await?;
+            handle.shutdown()
+        };
+
+        let (replica, result) = tokio::join!(agent.run(), driver);
+        result.unwrap();
+        assert_eq!(replica.latest_version().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_agent_runs_on_local_set() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let replica = Replica::clone(replica_id, store).await.unwrap();
+        let config = SyncAgentConfig::new(Vec::new(), PeerSelection::AllToAll);
+        let (agent, handle) = SyncAgent::<HashSet<u64>>::new(replica, config);
+
+        let local = tokio::task::LocalSet::new();
+        let replica = local
+            .run_until(async {
+                let agent = agent.spawn_local();
+                handle.commit(HashSet::from_iter([1, 2])).await.unwrap();
+                handle.shutdown().unwrap();
+                agent.await.unwrap()
+            })
+            .await;
+        let latest: HashSet<u64> = replica.latest_object().await.unwrap().unwrap();
+        assert_eq!(latest, HashSet::from_iter([1, 2]));
+    }
+}
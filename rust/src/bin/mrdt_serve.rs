@@ -0,0 +1,25 @@
+use mrdt_rs::*;
+use tokio::net::TcpListener;
+
+const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0:7878";
+
+/// Exposes a store to sync clients. The store is configured via the `SCYLLA_*` environment
+/// variables, the address to listen on is passed as the first argument.
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_LISTEN_ADDR.to_string());
+
+    let config = QuarkStoreConfig::from_env()?;
+    let store = QuarkStore::connect(config).await?;
+
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to listen on {addr}"))?;
+    log::info!("Serving store on {}", listener.local_addr()?);
+
+    serve(&store, listener).await
+}
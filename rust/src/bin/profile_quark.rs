@@ -1,13 +1,12 @@
 use mrdt_rs::*;
-use std::env;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
-    let hostname = env::var("SCYLLA_URL").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let config = QuarkStoreConfig::from_env()?;
 
-    let store = QuarkStore::setup(hostname, "test").await.unwrap();
+    let store = QuarkStore::connect(config).await.unwrap();
 
     store.dump_table_counts().await?;
 
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    quark::sign_commit, Commit, CommitId, CommitOptions, Id, QuarkStore, ReplicaId, VectorClock,
+    VersionedStore,
+};
+
+/// The branch every replica starts on, whose head is the head of the replica itself.
+pub const DEFAULT_BRANCH: &str = "main";
+
+/// Returns the id under which commits on the named branch of the replica are counted in vector
+/// clocks, so commits on different branches never end up with the same version.
+pub fn branch_clock_id(replica_id: ReplicaId, branch: &str) -> Result<Id> {
+    if branch == DEFAULT_BRANCH {
+        return Ok(replica_id);
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(replica_id.as_str());
+    hasher.update([0]);
+    hasher.update(branch);
+    let digest = hasher.finalize();
+    Id::try_from(format!(
+        "{:016x}",
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    ))
+}
+
+impl QuarkStore {
+    /// Creates a named branch of the replica, which starts at the given commit.
+    pub async fn create_branch(
+        &self,
+        replica_id: ReplicaId,
+        name: &str,
+        commit_id: CommitId,
+    ) -> Result<()> {
+        if self.branch_head(replica_id, name).await?.is_some() {
+            bail!("Branch {name} of replica {replica_id} already exists");
+        }
+        self.resolve_commit(commit_id).await?;
+        self.set_branch_head(replica_id, name, commit_id).await
+    }
+
+    /// Deletes a named branch. Its commits are kept, as they can still be tagged or merged.
+    pub async fn delete_branch(&self, replica_id: ReplicaId, name: &str) -> Result<()> {
+        if name == DEFAULT_BRANCH {
+            bail!("The default branch of a replica cannot be deleted");
+        }
+        self.remove_branch_head(replica_id, name).await
+    }
+
+    /// Returns the latest commit on the branch of the replica, if the branch exists.
+    pub async fn branch_head(&self, replica_id: ReplicaId, name: &str) -> Result<Option<Commit>> {
+        if name == DEFAULT_BRANCH {
+            return self.latest_commit_for_replica(replica_id).await;
+        }
+        let head = self
+            .branch_heads(replica_id)
+            .await?
+            .into_iter()
+            .find(|(branch, _)| branch == name);
+        match head {
+            Some((_, commit_id)) => Ok(Some(self.resolve_commit(commit_id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the heads of all branches of the replica, including its default branch.
+    pub async fn branches(&self, replica_id: ReplicaId) -> Result<BTreeMap<String, CommitId>> {
+        let mut branches: BTreeMap<_, _> =
+            self.branch_heads(replica_id).await?.into_iter().collect();
+        if let Some(head) = self.latest_commit_for_replica(replica_id).await? {
+            branches.insert(DEFAULT_BRANCH.to_string(), head.id);
+        }
+        Ok(branches)
+    }
+
+    /// Like [`VersionedStore::commit_with`], but adds the commit to a branch of the replica.
+    pub async fn commit_to_branch(
+        &self,
+        replica_id: ReplicaId,
+        branch: &str,
+        version: VectorClock,
+        root_ref: u64,
+        options: CommitOptions<'_>,
+    ) -> Result<Commit> {
+        if branch == DEFAULT_BRANCH {
+            return self
+                .commit_with(replica_id, version, root_ref, options)
+                .await;
+        }
+        let Some(head) = self.branch_head(replica_id, branch).await? else {
+            bail!("Branch {branch} of replica {replica_id} does not exist");
+        };
+        let commit = Commit {
+            id: Id::gen(),
+            version,
+            root_ref,
+            parent_commit_id: Some(head.id),
+            merge_parent_ids: options.merge_parent_ids,
+            type_tag: options.type_tag,
+            metadata: options.metadata,
+            signature: None,
+        };
+        let commit = sign_commit(commit, replica_id, options.signing_key)?;
+        self.insert_commit(&commit).await?;
+        self.set_branch_head(replica_id, branch, commit.id).await?;
+        Ok(commit)
+    }
+
+    /// Marks the commit with a name. Tags never move, creating an existing tag again only
+    /// succeeds if it points to the same commit.
+    pub async fn create_tag(&self, name: &str, commit_id: CommitId) -> Result<()> {
+        self.resolve_commit(commit_id).await?;
+        self.insert_tag(self.namespace_key(), name, commit_id)
+            .await?;
+        match self.tag(name).await? {
+            Some(commit) if commit.id == commit_id => Ok(()),
+            _ => bail!("Tag {name} already points to a different commit"),
+        }
+    }
+
+    /// Returns the commit the tag points to.
+    pub async fn tag(&self, name: &str) -> Result<Option<Commit>> {
+        match self.tag_target(self.namespace_key(), name).await? {
+            Some(commit_id) => Ok(Some(self.resolve_commit(commit_id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the tags of the namespace of the store.
+    pub async fn tags(&self) -> Result<BTreeMap<String, CommitId>> {
+        Ok(self
+            .tag_targets(self.namespace_key())
+            .await?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashSet, RefStore, Replica};
+
+    #[tokio::test]
+    async fn test_branches_and_tags() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut replica = Replica::clone(replica_id, store).await.unwrap();
+
+        replica.create_branch("draft").await.unwrap();
+        assert!(replica.create_branch("draft").await.is_err());
+        replica.checkout("draft").await.unwrap();
+        let draft = replica
+            .commit_object(&HashSet::from_iter([1u64, 2]))
+            .await
+            .unwrap();
+        replica.tag("v1").await.unwrap();
+
+        replica.checkout(DEFAULT_BRANCH).await.unwrap();
+        replica
+            .commit_object(&HashSet::from_iter([1u64, 3]))
+            .await
+            .unwrap();
+        assert!(replica.tag("v1").await.is_err());
+
+        let (_, merged) = replica.merge_branch::<HashSet<u64>>("draft").await.unwrap();
+        assert_eq!(merged, HashSet::from_iter([1, 2, 3]));
+
+        let store = replica.store();
+        let branches = store.branches(replica_id).await.unwrap();
+        assert_eq!(branches.get("draft"), Some(&draft.id));
+        assert_eq!(
+            branches.get(DEFAULT_BRANCH),
+            Some(&replica.latest_commit().id)
+        );
+        assert_eq!(store.tag("v1").await.unwrap(), Some(draft));
+    }
+
+    #[tokio::test]
+    async fn test_tags_of_namespaces_do_not_collide() {
+        let store = QuarkStore::test();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        let first = store
+            .commit(Id::gen(), VectorClock::default(), root)
+            .await
+            .unwrap();
+        store.create_tag("a/v1", first.id).await.unwrap();
+
+        let store = store.with_namespace("a");
+        let second = store
+            .commit(Id::gen(), VectorClock::default(), root)
+            .await
+            .unwrap();
+        store.create_tag("v1", second.id).await.unwrap();
+        assert_eq!(store.tag("v1").await.unwrap(), Some(second.clone()));
+        assert_eq!(
+            store.tags().await.unwrap(),
+            BTreeMap::from([("v1".to_string(), second.id)])
+        );
+    }
+}
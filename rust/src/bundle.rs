@@ -0,0 +1,730 @@
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use musli::{Decode, Encode};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    quark::ENCODING, Commit, CommitId, DocumentMap, HashMap, HashSet, ObjectRef, ObjectStore,
+    QuarkStore, Ref, ReplicaId, Tagged, VectorClock,
+};
+
+const BUNDLE_MAGIC: &[u8; 8] = b"MRDTBNDL";
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+/// Bundles are read into memory completely, so larger payloads are rejected before reading them.
+const MAX_BUNDLE_PAYLOAD_LEN: u64 = 4 << 30;
+
+/// The head of a replica at the time a [`Bundle`] was exported.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct BundleHead {
+    pub replica_id: ReplicaId,
+    pub commit_id: CommitId,
+}
+
+/// An object in its encoded form, stored under its content hash.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct BundleObject {
+    pub id: ObjectRef,
+    pub bytes: Vec<u8>,
+}
+
+/// A self-contained part of the history of a [`QuarkStore`], which can be moved to another
+/// store, similar to a git bundle.
+///
+/// When the bundle was exported relative to a `base` version, the destination store needs to
+/// contain the commits up to that version before the bundle can be imported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Bundle {
+    pub heads: Vec<BundleHead>,
+    pub base: Option<VectorClock>,
+    pub commits: Vec<Commit>,
+    pub refs: Vec<Ref>,
+    pub objects: Vec<BundleObject>,
+    /// The roots of documents which have been left out, because only some documents were
+    /// selected. They can only be missing in stores which replicate a subset of the documents.
+    pub omitted_roots: Vec<ObjectRef>,
+}
+
+/// Selects which part of the history is exported into a [`Bundle`].
+#[derive(Debug, Clone, Default)]
+pub struct BundleSelection {
+    /// The replicas whose history is exported, all replicas when `None`.
+    pub heads: Option<Vec<ReplicaId>>,
+    /// Commits which are causally before or equal to this version are left out.
+    pub base: Option<VectorClock>,
+    /// The keys of the documents which are exported from commits of a [`DocumentMap`], all
+    /// documents when `None`.
+    pub documents: Option<BTreeSet<String>>,
+}
+
+impl BundleSelection {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn heads(replica_ids: impl IntoIterator<Item = ReplicaId>) -> Self {
+        Self {
+            heads: Some(replica_ids.into_iter().collect()),
+            ..Default::default()
+        }
+    }
+
+    pub fn since(mut self, base: VectorClock) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    pub fn documents(mut self, keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.documents = Some(keys.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+/// What happened to the heads of a [`Bundle`] during [`QuarkStore::import_bundle`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ImportSummary {
+    pub commits_imported: usize,
+    pub refs_imported: usize,
+    pub objects_imported: usize,
+    /// Replicas whose head was created or fast-forwarded to the head in the bundle.
+    pub updated_heads: Vec<ReplicaId>,
+    /// Replicas whose head was not updated, because the bundle contains commits of them which
+    /// are forged or violate the signature policy of the store. The commits are imported, but
+    /// are not part of the history of any replica.
+    pub quarantined_heads: Vec<ReplicaId>,
+    /// Replicas whose head in the destination is concurrent to the head in the bundle. These
+    /// heads are left untouched and have to be merged explicitly.
+    pub diverged_heads: Vec<ReplicaId>,
+}
+
+impl Bundle {
+    /// Writes the bundle to the given writer.
+    ///
+    /// The file starts with a magic number, the format version and the length of the payload,
+    /// followed by the encoded payload and a SHA-256 checksum of everything before it.
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        let mut payload = Vec::new();
+        ENCODING
+            .encode(&mut payload, self)
+            .with_context(|| "Failed to encode bundle")?;
+
+        let mut data = Vec::with_capacity(BUNDLE_MAGIC.len() + 12 + payload.len() + 32);
+        data.extend_from_slice(BUNDLE_MAGIC);
+        data.extend_from_slice(&BUNDLE_FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        data.extend_from_slice(&payload);
+        let checksum = Sha256::digest(&data);
+        data.extend_from_slice(&checksum);
+
+        writer.write_all(&data)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a bundle written by [`Bundle::write_to`] and verifies its checksum.
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let header_len = BUNDLE_MAGIC.len() + 12;
+        let mut data = vec![0; header_len];
+        if reader.read_exact(&mut data).is_err() || &data[..BUNDLE_MAGIC.len()] != BUNDLE_MAGIC {
+            bail!("Not a bundle file");
+        }
+        let format_version = u32::from_le_bytes(data[8..12].try_into()?);
+        if format_version != BUNDLE_FORMAT_VERSION {
+            bail!("Unsupported bundle format version {format_version}");
+        }
+        let payload_len = u64::from_le_bytes(data[12..20].try_into()?);
+        if payload_len > MAX_BUNDLE_PAYLOAD_LEN {
+            bail!("Bundle payload of {payload_len} bytes exceeds the limit of {MAX_BUNDLE_PAYLOAD_LEN} bytes");
+        }
+        let total_len = usize::try_from(payload_len)
+            .ok()
+            .and_then(|len| len.checked_add(header_len))
+            .and_then(|len| len.checked_add(32))
+            .with_context(|| "Bundle is too large")?;
+        reader
+            .take((total_len - header_len) as u64 + 1)
+            .read_to_end(&mut data)?;
+        if data.len() != total_len {
+            bail!("Bundle is truncated");
+        }
+
+        let (content, checksum) = data.split_at(total_len - 32);
+        if Sha256::digest(content).as_slice() != checksum {
+            bail!("Bundle checksum mismatch");
+        }
+
+        ENCODING
+            .from_slice(&content[header_len..])
+            .with_context(|| "Failed to decode bundle")
+    }
+
+    /// Returns the heads of the bundle and every commit its commits contain or refer to, e.g. to
+    /// keep the history of an offline snapshot alive in [`QuarkStore::collect_garbage`].
+    pub fn referenced_commits(&self) -> Vec<CommitId> {
+        let mut commit_ids: Vec<CommitId> = self.heads.iter().map(|head| head.commit_id).collect();
+        for commit in &self.commits {
+            commit_ids.push(commit.id);
+            commit_ids.extend(commit.parent_commit_id);
+            commit_ids.extend(commit.merge_parent_ids.iter().copied());
+        }
+        commit_ids.sort();
+        commit_ids.dedup();
+        commit_ids
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create bundle {}", path.display()))?;
+        self.write_to(std::io::BufWriter::new(file))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open bundle {}", path.display()))?;
+        Self::read_from(std::io::BufReader::new(file))
+    }
+}
+
+impl QuarkStore {
+    /// Exports the selected commits together with all refs and objects they need into a bundle.
+    pub async fn export_bundle(&self, selection: &BundleSelection) -> Result<Bundle> {
+        let mut bundle = self
+            .pack(
+                selection.heads.as_deref(),
+                selection.documents.as_ref(),
+                |commit| {
+                    selection
+                        .base
+                        .as_ref()
+                        .is_some_and(|base| commit.version <= *base)
+                },
+            )
+            .await?;
+        bundle.base = selection.base.clone();
+        Ok(bundle)
+    }
+
+    /// Packs the history of the given replicas, or of all replicas, into a bundle. Commits for
+    /// which `is_known` returns true are left out, together with the refs reachable from them.
+    /// Only the given documents are packed, and never more than this store replicates.
+    pub(crate) async fn pack(
+        &self,
+        replica_ids: Option<&[ReplicaId]>,
+        documents: Option<&BTreeSet<String>>,
+        is_known: impl Fn(&Commit) -> bool,
+    ) -> Result<Bundle> {
+        let documents = match (documents, self.partial_documents()) {
+            (Some(selected), Some(replicated)) => {
+                Some(selected.intersection(replicated).cloned().collect())
+            }
+            (selected, replicated) => selected.or(replicated).cloned(),
+        };
+        let heads = self
+            .replica_heads()
+            .await?
+            .into_iter()
+            .filter(|(replica_id, _)| replica_ids.is_none_or(|ids| ids.contains(replica_id)))
+            .map(|(replica_id, commit_id)| BundleHead {
+                replica_id,
+                commit_id,
+            })
+            .collect::<Vec<_>>();
+
+        // Walk the history of the heads and of the branches of their replicas back to the first
+        // known commits. Everything reachable from those is already in the destination.
+        let mut stack = Vec::new();
+        for head in &heads {
+            stack.push(head.commit_id);
+            stack.extend(
+                self.branch_heads(head.replica_id)
+                    .await?
+                    .into_iter()
+                    .map(|(_, commit_id)| commit_id),
+            );
+        }
+        let mut visited_commits = HashSet::default();
+        let mut known_roots = Vec::new();
+        let mut selected_commits = Vec::new();
+        while let Some(commit_id) = stack.pop() {
+            if !visited_commits.insert(commit_id) {
+                continue;
+            }
+            let commit = self
+                .find_commit(commit_id)
+                .await?
+                .with_context(|| format!("Commit {commit_id} not found"))?;
+            if is_known(&commit) {
+                known_roots.push(commit.root_ref);
+                continue;
+            }
+            stack.extend(commit.parent_commit_id);
+            stack.extend(commit.merge_parent_ids.iter().copied());
+            selected_commits.push(commit);
+        }
+        // Parents are imported before their children.
+        selected_commits.reverse();
+
+        let mut visited = HashSet::default();
+        let mut omitted_roots = Vec::new();
+        self.collect_document_refs(
+            known_roots,
+            documents.as_ref(),
+            &mut visited,
+            &mut omitted_roots,
+        )
+        .await?;
+
+        let refs = self
+            .collect_document_refs(
+                selected_commits.iter().map(|commit| commit.root_ref),
+                documents.as_ref(),
+                &mut visited,
+                &mut omitted_roots,
+            )
+            .await?;
+        omitted_roots.retain(|root| !visited.contains(root));
+        omitted_roots.sort_unstable();
+        omitted_roots.dedup();
+
+        let mut objects = Vec::new();
+        let mut object_ids = HashSet::default();
+        for reference in &refs {
+            if !object_ids.insert(reference.object_ref) {
+                continue;
+            }
+            let bytes = self
+                .resolve_object_bytes(reference.object_ref)
+                .await?
+                .with_context(|| format!("Object {} not found", reference.object_ref))?;
+            objects.push(BundleObject {
+                id: reference.object_ref,
+                bytes,
+            });
+        }
+
+        log::debug!(
+            "Packed bundle with {} commits, {} refs and {} objects",
+            selected_commits.len(),
+            refs.len(),
+            objects.len()
+        );
+
+        Ok(Bundle {
+            heads,
+            base: None,
+            commits: selected_commits,
+            refs,
+            objects,
+            omitted_roots,
+        })
+    }
+
+    /// Imports the history contained in the bundle.
+    ///
+    /// The bundle is validated completely before anything is written: every ref has to match its
+    /// content hash and everything it references has to be part of the bundle or this store.
+    pub async fn import_bundle(&self, bundle: &Bundle) -> Result<ImportSummary> {
+        // Only the commits the bundle refers to are looked up, the history of the store can be
+        // much larger than the bundle.
+        let mut existing_commits = HashMap::default();
+        for commit_id in bundle
+            .commits
+            .iter()
+            .flat_map(|commit| {
+                std::iter::once(&commit.id)
+                    .chain(&commit.parent_commit_id)
+                    .chain(&commit.merge_parent_ids)
+            })
+            .chain(bundle.heads.iter().map(|head| &head.commit_id))
+        {
+            if existing_commits.contains_key(commit_id) {
+                continue;
+            }
+            if let Some(commit) = self.find_commit(*commit_id).await? {
+                existing_commits.insert(*commit_id, commit);
+            }
+        }
+        let bundle_commits = bundle
+            .commits
+            .iter()
+            .map(|commit| (commit.id, commit))
+            .collect::<HashMap<_, _>>();
+        let bundle_refs = bundle
+            .refs
+            .iter()
+            .map(|reference| reference.id)
+            .collect::<HashSet<_>>();
+        let bundle_objects = bundle
+            .objects
+            .iter()
+            .map(|object| object.id)
+            .collect::<HashSet<_>>();
+        // Object ids are not derived from the encoded bytes, so the bytes cannot be verified.
+        // Objects no ref of the bundle points to are rejected, and stored objects are never
+        // replaced, so a bundle cannot change the content of existing history.
+        let referenced_objects = bundle
+            .refs
+            .iter()
+            .map(|reference| reference.object_ref)
+            .collect::<HashSet<_>>();
+        if let Some(object) = bundle
+            .objects
+            .iter()
+            .find(|object| !referenced_objects.contains(&object.id))
+        {
+            bail!(
+                "Bundle contains object {}, which no ref references",
+                object.id
+            );
+        }
+        let omitted_roots = bundle
+            .omitted_roots
+            .iter()
+            .copied()
+            .filter(|_| self.partial_documents().is_some())
+            .collect::<HashSet<_>>();
+
+        for reference in &bundle.refs {
+            let computed = Ref::compute(reference.object_ref, reference.left, reference.right);
+            if computed.id != reference.id {
+                bail!("Ref {} does not match its content hash", reference.id);
+            }
+            for child in reference.left.iter().chain(reference.right.iter()) {
+                if !bundle_refs.contains(child)
+                    && !omitted_roots.contains(child)
+                    && self.resolve_ref(Some(*child)).await?.is_none()
+                {
+                    bail!("Bundle requires ref {child}, which is missing");
+                }
+            }
+            if !bundle_objects.contains(&reference.object_ref)
+                && self
+                    .resolve_object_bytes(reference.object_ref)
+                    .await?
+                    .is_none()
+            {
+                bail!(
+                    "Bundle requires object {}, which is missing",
+                    reference.object_ref
+                );
+            }
+        }
+        for commit in &bundle.commits {
+            if !bundle_refs.contains(&commit.root_ref)
+                && self.resolve_ref(Some(commit.root_ref)).await?.is_none()
+            {
+                bail!("Bundle requires ref {}, which is missing", commit.root_ref);
+            }
+            for parent in commit
+                .parent_commit_id
+                .iter()
+                .chain(&commit.merge_parent_ids)
+            {
+                if !bundle_commits.contains_key(parent) && !existing_commits.contains_key(parent) {
+                    bail!("Bundle requires commit {parent}, which is missing");
+                }
+            }
+        }
+        for head in &bundle.heads {
+            if !bundle_commits.contains_key(&head.commit_id)
+                && !existing_commits.contains_key(&head.commit_id)
+            {
+                bail!(
+                    "Bundle requires commit {}, which is missing",
+                    head.commit_id
+                );
+            }
+        }
+
+        let mut summary = ImportSummary::default();
+
+        let mut objects = Vec::new();
+        for object in &bundle.objects {
+            if self.resolve_object_bytes(object.id).await?.is_none() {
+                objects.push((object.id, object.bytes.clone()));
+            }
+        }
+        self.insert_object_bytes(&objects).await?;
+        summary.objects_imported = objects.len();
+
+        self.insert_refs(&bundle.refs).await?;
+        summary.refs_imported = bundle.refs.len();
+
+        for commit in &bundle.commits {
+            if !existing_commits.contains_key(&commit.id) {
+                self.insert_commit(commit).await?;
+                summary.commits_imported += 1;
+            }
+        }
+
+        let current_heads = self
+            .replica_heads()
+            .await?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        for current in current_heads.values() {
+            if !existing_commits.contains_key(current) {
+                if let Some(commit) = self.find_commit(*current).await? {
+                    existing_commits.insert(*current, commit);
+                }
+            }
+        }
+        for head in &bundle.heads {
+            let version = |commit_id: &CommitId| {
+                bundle_commits
+                    .get(commit_id)
+                    .copied()
+                    .or_else(|| existing_commits.get(commit_id))
+                    .map(|commit| &commit.version)
+            };
+            let fast_forward = match current_heads.get(&head.replica_id) {
+                None => true,
+                Some(current) if *current == head.commit_id => false,
+                Some(current) => {
+                    let current_version = version(current);
+                    let head_version = version(&head.commit_id);
+                    match (current_version, head_version) {
+                        (Some(current), Some(head)) if current <= head => true,
+                        (Some(current), Some(head)) if head <= current => false,
+                        _ => {
+                            summary.diverged_heads.push(head.replica_id);
+                            false
+                        }
+                    }
+                }
+            };
+            if fast_forward
+                && !self
+                    .check_new_commits(
+                        head,
+                        current_heads.get(&head.replica_id).copied(),
+                        &bundle_commits,
+                    )
+                    .await?
+            {
+                summary.quarantined_heads.push(head.replica_id);
+            } else if fast_forward {
+                self.set_replica_head(head.replica_id, head.commit_id)
+                    .await?;
+                summary.updated_heads.push(head.replica_id);
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Checks the commits which the head adds to the history of the replica in this store, see
+    /// [`QuarkStore::check_commit`]. Commits which are already stored are checked as well, as
+    /// they can stem from a head which was quarantined before.
+    async fn check_new_commits(
+        &self,
+        head: &BundleHead,
+        current: Option<CommitId>,
+        bundle_commits: &HashMap<CommitId, &Commit>,
+    ) -> Result<bool> {
+        let mut next = Some(head.commit_id);
+        while let Some(commit_id) = next.filter(|commit_id| Some(*commit_id) != current) {
+            let commit = match bundle_commits.get(&commit_id) {
+                Some(commit) => (*commit).clone(),
+                None => self
+                    .find_commit(commit_id)
+                    .await?
+                    .with_context(|| format!("Commit {commit_id} not found"))?,
+            };
+            if let Err(err) = self.check_commit(head.replica_id, &commit).await {
+                log::warn!("Quarantining head of replica {}: {err:#}", head.replica_id);
+                return Ok(false);
+            }
+            next = commit.parent_commit_id;
+        }
+        Ok(true)
+    }
+
+    /// Collects all refs reachable from the given roots, which have not been visited yet.
+    pub(crate) async fn collect_refs(
+        &self,
+        roots: impl IntoIterator<Item = u64>,
+        visited: &mut HashSet<u64>,
+    ) -> Result<Vec<Ref>> {
+        self.collect_document_refs(roots, self.partial_documents(), visited, &mut Vec::new())
+            .await
+    }
+
+    /// Like [`QuarkStore::collect_refs`], but only follows the documents with the given keys in
+    /// roots of a [`DocumentMap`]. The roots of all other documents are added to `omitted`.
+    async fn collect_document_refs(
+        &self,
+        roots: impl IntoIterator<Item = u64>,
+        documents: Option<&BTreeSet<String>>,
+        visited: &mut HashSet<u64>,
+        omitted: &mut Vec<u64>,
+    ) -> Result<Vec<Ref>> {
+        let mut references = Vec::new();
+        let mut stack = roots.into_iter().collect::<Vec<_>>();
+        let mut map_refs = HashSet::default();
+        if documents.is_some() {
+            for root in &stack {
+                let tags = self.root_type_tags(*root).await?;
+                if tags.contains(&DocumentMap::type_tag()) {
+                    map_refs.insert(*root);
+                }
+            }
+        }
+
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let reference = self
+                .resolve_ref(Some(id))
+                .await?
+                .with_context(|| format!("Ref {id} not found"))?;
+            stack.extend(reference.left);
+            match (documents, reference.right) {
+                (Some(documents), Some(document_root)) if map_refs.contains(&id) => {
+                    map_refs.extend(reference.left);
+                    let key = self
+                        .resolve_object::<String>(reference.object_ref)
+                        .await?
+                        .with_context(|| format!("Document key of ref {id} not found"))?;
+                    if documents.contains(&key) {
+                        stack.push(document_root);
+                    } else {
+                        omitted.push(document_root);
+                    }
+                }
+                _ => {
+                    if map_refs.contains(&id) {
+                        map_refs.extend(reference.left);
+                    }
+                    stack.extend(reference.right);
+                }
+            }
+            references.push(reference);
+        }
+        Ok(references)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, RefStore, VersionedStore};
+
+    #[tokio::test]
+    async fn test_bundle_roundtrip() {
+        let source = QuarkStore::test();
+        let replica_id = Id::gen();
+
+        let mut version = VectorClock::default();
+        version.inc(replica_id);
+        let first_root = source.insert(&vec![1u64, 2, 3]).await.unwrap();
+        let first = source
+            .commit(replica_id, version.clone(), first_root)
+            .await
+            .unwrap();
+
+        version.inc(replica_id);
+        let second_root = source.insert(&vec![1u64, 2, 3, 4]).await.unwrap();
+        source
+            .commit(replica_id, version, second_root)
+            .await
+            .unwrap();
+
+        let mut file = Vec::new();
+        let bundle = source.export_bundle(&BundleSelection::all()).await.unwrap();
+        bundle.write_to(&mut file).unwrap();
+        let bundle = Bundle::read_from(file.as_slice()).unwrap();
+
+        let destination = QuarkStore::test();
+        let summary = destination.import_bundle(&bundle).await.unwrap();
+        assert_eq!(summary.commits_imported, 2);
+        assert_eq!(summary.updated_heads, vec![replica_id]);
+
+        let head = destination
+            .latest_commit_for_replica(replica_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let list: Vec<u64> = destination.resolve(head.root_ref).await.unwrap().unwrap();
+        assert_eq!(list, vec![1, 2, 3, 4]);
+
+        let incremental = source
+            .export_bundle(&BundleSelection::heads([replica_id]).since(first.version))
+            .await
+            .unwrap();
+        assert_eq!(incremental.commits.len(), 1);
+        assert!(incremental.refs.len() < bundle.refs.len());
+        assert!(QuarkStore::test()
+            .import_bundle(&incremental)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_bundle_checksum_mismatch() {
+        let mut file = Vec::new();
+        Bundle::default().write_to(&mut file).unwrap();
+        assert!(Bundle::read_from(file.as_slice()).is_ok());
+
+        let last = file.len() - 1;
+        file[last] ^= 0xff;
+        assert!(Bundle::read_from(file.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_bundle_length_overflow() {
+        let mut file = Vec::new();
+        Bundle::default().write_to(&mut file).unwrap();
+        file[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        let error = Bundle::read_from(file.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("exceeds the limit"));
+
+        file[12..20].copy_from_slice(&1000u64.to_le_bytes());
+        let error = Bundle::read_from(file.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("truncated"));
+    }
+
+    #[tokio::test]
+    async fn test_import_keeps_stored_objects() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&vec![1u64, 2]).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut bundle = store
+            .export_bundle(&BundleSelection::default())
+            .await
+            .unwrap();
+
+        let target = QuarkStore::test();
+        target.import_bundle(&bundle).await.unwrap();
+        let original = bundle.objects[0].clone();
+        bundle.objects[0].bytes = store
+            .resolve_object_bytes(bundle.objects[1].id)
+            .await
+            .unwrap()
+            .unwrap();
+        let summary = target.import_bundle(&bundle).await.unwrap();
+        assert_eq!(summary.objects_imported, 0);
+        assert_eq!(
+            target.resolve_object_bytes(original.id).await.unwrap(),
+            Some(original.bytes)
+        );
+
+        bundle.objects.push(BundleObject {
+            id: 42,
+            bytes: vec![1, 2, 3],
+        });
+        assert!(target.import_bundle(&bundle).await.is_err());
+        assert!(target.resolve_object_bytes(42).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,243 @@
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+
+use crate::{ObjectRef, Ref};
+
+/// Configuration of the in-process cache of a [`crate::QuarkStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// The maximum amount of bytes the cached refs and objects may occupy.
+    pub max_bytes: usize,
+}
+
+impl CacheConfig {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { max_bytes }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self::new(64 * 1024 * 1024)
+    }
+}
+
+/// Hit/miss statistics of an [`ObjectCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl CacheStats {
+    /// Returns the fraction of lookups which were served from the cache.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Ref(u64),
+    Object(ObjectRef),
+    /// A root which has been verified to be readable as the type with the given fingerprint.
+    RootType(u64, u64),
+}
+
+#[derive(Clone)]
+enum CacheEntry {
+    Ref(Ref),
+    Object(Arc<[u8]>),
+    RootType,
+}
+
+impl CacheEntry {
+    fn size(&self) -> usize {
+        match self {
+            CacheEntry::Ref(_) => std::mem::size_of::<Ref>(),
+            CacheEntry::Object(bytes) => bytes.len(),
+            CacheEntry::RootType => std::mem::size_of::<CacheKey>(),
+        }
+    }
+}
+
+struct CacheState {
+    entries: LruCache<CacheKey, CacheEntry>,
+    stats: CacheStats,
+}
+
+/// A bounded least-recently-used cache for refs and objects, keyed by their content hash.
+///
+/// As refs and objects are content addressed, cached entries only become stale when they are
+/// removed by the garbage collection of a namespace, or rewritten in a newer version by
+/// [`crate::QuarkStore::rewrite_objects`].
+///
+/// Objects are cached in their encoded form rather than decoded, for three reasons: the same
+/// entry serves typed reads as well as [`crate::QuarkStore::resolve_object_bytes`], which bundles
+/// and rewrites use; the types objects are decoded to need not be `'static` or `Send`, so they
+/// cannot be kept type-erased without tightening the bounds of [`crate::ObjectStore`] and
+/// [`crate::MrdtItem`]; and decoding applies the upgrades of [`crate::ObjectMigrations`], so a
+/// decoded value would depend on the migrations registered when it was cached. Decoding is cheap
+/// compared to the round trip to Scylla which a hit saves.
+pub struct ObjectCache {
+    config: CacheConfig,
+    state: Mutex<CacheState>,
+}
+
+impl ObjectCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(CacheState {
+                entries: LruCache::unbounded(),
+                stats: CacheStats::default(),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().unwrap().stats
+    }
+
+    pub fn get_ref(&self, id: u64) -> Option<Ref> {
+        match self.get(CacheKey::Ref(id))? {
+            CacheEntry::Ref(reference) => Some(reference),
+            _ => None,
+        }
+    }
+
+    pub fn insert_ref(&self, reference: Ref) {
+        self.insert(CacheKey::Ref(reference.id), CacheEntry::Ref(reference));
+    }
+
+    pub fn get_object(&self, id: ObjectRef) -> Option<Arc<[u8]>> {
+        match self.get(CacheKey::Object(id))? {
+            CacheEntry::Object(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn insert_object(&self, id: ObjectRef, bytes: &[u8]) {
+        self.insert(CacheKey::Object(id), CacheEntry::Object(bytes.into()));
+    }
+
+    /// Returns whether the root has been verified to be readable as the type with the given
+    /// fingerprint. Type tags are only ever added to a root, so a verification stays valid.
+    pub fn contains_root_type(&self, root: u64, fingerprint: u64) -> bool {
+        self.get(CacheKey::RootType(root, fingerprint)).is_some()
+    }
+
+    pub fn insert_root_type(&self, root: u64, fingerprint: u64) {
+        self.insert(CacheKey::RootType(root, fingerprint), CacheEntry::RootType);
+    }
+
+    pub fn remove_ref(&self, id: u64) {
+        self.remove(CacheKey::Ref(id));
+    }
+
+    pub fn remove_object(&self, id: ObjectRef) {
+        self.remove(CacheKey::Object(id));
+    }
+
+    fn get(&self, key: CacheKey) -> Option<CacheEntry> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get(&key).cloned();
+        if entry.is_some() {
+            state.stats.hits += 1;
+        } else {
+            state.stats.misses += 1;
+        }
+        entry
+    }
+
+    fn insert(&self, key: CacheKey, entry: CacheEntry) {
+        let size = entry.size();
+        if size > self.config.max_bytes {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(previous) = state.entries.put(key, entry) {
+            state.stats.bytes -= previous.size();
+        }
+        state.stats.bytes += size;
+
+        while state.stats.bytes > self.config.max_bytes {
+            let Some((_, evicted)) = state.entries.pop_lru() else {
+                break;
+            };
+            state.stats.bytes -= evicted.size();
+            state.stats.evictions += 1;
+        }
+        state.stats.entries = state.entries.len();
+    }
+
+    fn remove(&self, key: CacheKey) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(removed) = state.entries.pop(&key) {
+            state.stats.bytes -= removed.size();
+        }
+        state.stats.entries = state.entries.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_hits_and_misses() {
+        let cache = ObjectCache::new(CacheConfig::default());
+        let reference = Ref::compute(1, None, None);
+
+        assert!(cache.get_ref(reference.id).is_none());
+        cache.insert_ref(reference.clone());
+        cache.insert_object(1, &[1, 2, 3]);
+
+        assert_eq!(cache.get_ref(reference.id).unwrap().object_ref, 1);
+        assert_eq!(cache.get_object(1).unwrap().as_ref(), &[1, 2, 3]);
+        assert!(cache.get_object(reference.id).is_none());
+
+        assert!(!cache.contains_root_type(reference.id, 7));
+        cache.insert_root_type(reference.id, 7);
+        assert!(cache.contains_root_type(reference.id, 7));
+        assert!(cache.get_ref(reference.id).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 4);
+        assert_eq!(stats.misses, 3);
+        assert_eq!(stats.entries, 3);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used_by_bytes() {
+        let cache = ObjectCache::new(CacheConfig::new(10));
+        cache.insert_object(1, &[0; 4]);
+        cache.insert_object(2, &[0; 4]);
+        cache.get_object(1);
+        cache.insert_object(3, &[0; 4]);
+
+        assert!(cache.get_object(1).is_some());
+        assert!(cache.get_object(2).is_none());
+        assert!(cache.get_object(3).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.bytes, 8);
+
+        cache.insert_object(4, &[0; 11]);
+        assert!(cache.get_object(4).is_none());
+    }
+}
@@ -0,0 +1,515 @@
+use std::{collections::BTreeMap, num::NonZeroUsize, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use scylla::{statement::Consistency, transport::session::PoolSize};
+
+use crate::{CacheConfig, Replication, SessionConfig};
+
+const DEFAULT_NODE: &str = "127.0.0.1:9042";
+const DEFAULT_KEYSPACE: &str = "test";
+const ENV_PREFIX: &str = "SCYLLA_";
+
+/// Username and password used to authenticate against the cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Everything required to connect a [`crate::QuarkStore`] to a Scylla cluster.
+///
+/// Use [`QuarkStoreConfig::builder`] to create a configuration in code, or load it with
+/// [`QuarkStoreConfig::from_env`] or [`QuarkStoreConfig::from_toml_file`].
+#[derive(Debug, Clone)]
+pub struct QuarkStoreConfig {
+    pub nodes: Vec<String>,
+    pub keyspace: String,
+    pub credentials: Option<Credentials>,
+    /// Prefix which is prepended to the name of every table of the store.
+    pub table_prefix: String,
+    /// Isolates the replicas and commits of the store from other stores in the same keyspace,
+    /// see [`crate::QuarkStore::with_namespace`].
+    pub namespace: Option<String>,
+    /// Size of the connection pool, uses the default of the driver if `None`.
+    pub pool_size: Option<PoolSize>,
+    pub session: SessionConfig,
+    pub cache: Option<CacheConfig>,
+}
+
+impl QuarkStoreConfig {
+    /// Creates a configuration for a single node, using the defaults for everything else.
+    pub fn new(node: impl Into<String>, keyspace: impl Into<String>) -> Self {
+        Self {
+            nodes: vec![node.into()],
+            keyspace: keyspace.into(),
+            credentials: None,
+            table_prefix: String::new(),
+            namespace: None,
+            pool_size: None,
+            session: SessionConfig::default(),
+            cache: None,
+        }
+    }
+
+    pub fn builder() -> QuarkStoreConfigBuilder {
+        QuarkStoreConfigBuilder::default()
+    }
+
+    /// Loads the configuration from the `SCYLLA_*` environment variables.
+    ///
+    /// `SCYLLA_URL` may contain multiple comma separated contact points and defaults to
+    /// `127.0.0.1:9042`, `SCYLLA_KEYSPACE` defaults to `test`.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Loads the configuration from a TOML file.
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::from_toml_str(&contents)
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Self> {
+        let raw: RawConfig = toml::from_str(contents).with_context(|| "Failed to parse config")?;
+        raw.into_builder()?.build()
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| var(&format!("{ENV_PREFIX}{name}"));
+        let parse = |name: &str| -> Result<Option<u64>> {
+            var(name)
+                .map(|value| {
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid value for {ENV_PREFIX}{name}"))
+                })
+                .transpose()
+        };
+        let parse_i32 = |name: &str| -> Result<Option<i32>> {
+            parse(name)?
+                .map(|value| {
+                    i32::try_from(value)
+                        .with_context(|| format!("Value of {ENV_PREFIX}{name} is too large"))
+                })
+                .transpose()
+        };
+
+        let datacenters = var("DATACENTERS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|entry| {
+                        let (datacenter, factor) = entry
+                            .split_once(':')
+                            .with_context(|| format!("Invalid datacenter entry {entry}"))?;
+                        Ok((datacenter.trim().to_string(), factor.trim().parse()?))
+                    })
+                    .collect::<Result<BTreeMap<_, _>>>()
+            })
+            .transpose()?;
+
+        let raw = RawConfig {
+            nodes: Some(
+                var("URL")
+                    .unwrap_or_else(|| DEFAULT_NODE.to_string())
+                    .split(',')
+                    .map(|node| node.trim().to_string())
+                    .collect(),
+            ),
+            keyspace: Some(var("KEYSPACE").unwrap_or_else(|| DEFAULT_KEYSPACE.to_string())),
+            username: var("USERNAME"),
+            password: var("PASSWORD"),
+            table_prefix: var("TABLE_PREFIX"),
+            namespace: var("NAMESPACE"),
+            connections_per_host: parse("CONNECTIONS_PER_HOST")?.map(|value| value as usize),
+            connections_per_shard: parse("CONNECTIONS_PER_SHARD")?.map(|value| value as usize),
+            consistency: var("CONSISTENCY"),
+            replication_strategy: var("REPLICATION_STRATEGY"),
+            replication_factor: parse("REPLICATION_FACTOR")?
+                .map(u32::try_from)
+                .transpose()
+                .with_context(|| format!("Value of {ENV_PREFIX}REPLICATION_FACTOR is too large"))?,
+            datacenters,
+            page_size: parse_i32("PAGE_SIZE")?,
+            request_timeout_ms: parse("REQUEST_TIMEOUT_MS")?,
+            connection_timeout_ms: parse("CONNECTION_TIMEOUT_MS")?,
+            cache_max_bytes: parse("CACHE_MAX_BYTES")?.map(|value| value as usize),
+        };
+        raw.into_builder()?.build()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct QuarkStoreConfigBuilder {
+    nodes: Vec<String>,
+    keyspace: Option<String>,
+    credentials: Option<Credentials>,
+    table_prefix: String,
+    namespace: Option<String>,
+    pool_size: Option<PoolSize>,
+    session: SessionConfig,
+    cache: Option<CacheConfig>,
+}
+
+impl QuarkStoreConfigBuilder {
+    /// Adds a contact point of the cluster.
+    pub fn node(mut self, node: impl Into<String>) -> Self {
+        self.nodes.push(node.into());
+        self
+    }
+
+    pub fn nodes(mut self, nodes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.nodes.extend(nodes.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn keyspace(mut self, keyspace: impl Into<String>) -> Self {
+        self.keyspace = Some(keyspace.into());
+        self
+    }
+
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some(Credentials {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    pub fn table_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.table_prefix = prefix.into();
+        self
+    }
+
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    pub fn pool_size(mut self, pool_size: PoolSize) -> Self {
+        self.pool_size = Some(pool_size);
+        self
+    }
+
+    pub fn replication(mut self, replication: Replication) -> Self {
+        self.session.replication = replication;
+        self
+    }
+
+    pub fn consistency(mut self, consistency: Consistency) -> Self {
+        self.session.consistency = consistency;
+        self
+    }
+
+    pub fn page_size(mut self, page_size: i32) -> Self {
+        self.session.page_size = page_size;
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.session.request_timeout = Some(timeout);
+        self
+    }
+
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.session.connection_timeout = Some(timeout);
+        self
+    }
+
+    pub fn cache(mut self, cache: CacheConfig) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn build(self) -> Result<QuarkStoreConfig> {
+        if self.nodes.is_empty() {
+            bail!("At least one node is required");
+        }
+        let keyspace = self.keyspace.with_context(|| "Keyspace is required")?;
+        let is_identifier =
+            |name: &str| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if keyspace.is_empty() || !is_identifier(&keyspace) {
+            bail!("Invalid keyspace name {keyspace:?}");
+        }
+        if !is_identifier(&self.table_prefix) {
+            bail!("Invalid table prefix {:?}", self.table_prefix);
+        }
+        if self.namespace.as_deref().is_some_and(str::is_empty) {
+            bail!("Namespace must not be empty");
+        }
+        if self.session.page_size <= 0 {
+            bail!("Page size must be positive, got {}", self.session.page_size);
+        }
+        // The names of datacenters are part of the CQL which creates the keyspace.
+        if let Replication::Datacenters(datacenters) = &self.session.replication {
+            for (datacenter, _) in datacenters {
+                let is_valid = !datacenter.is_empty()
+                    && datacenter
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+                if !is_valid {
+                    bail!("Invalid datacenter name {datacenter:?}");
+                }
+            }
+        }
+
+        Ok(QuarkStoreConfig {
+            nodes: self.nodes,
+            keyspace,
+            credentials: self.credentials,
+            table_prefix: self.table_prefix,
+            namespace: self.namespace,
+            pool_size: self.pool_size,
+            session: self.session,
+            cache: self.cache,
+        })
+    }
+}
+
+/// The flat representation of a [`QuarkStoreConfig`], shared by the TOML and environment loaders.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    nodes: Option<Vec<String>>,
+    keyspace: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    table_prefix: Option<String>,
+    namespace: Option<String>,
+    connections_per_host: Option<usize>,
+    connections_per_shard: Option<usize>,
+    consistency: Option<String>,
+    replication_strategy: Option<String>,
+    replication_factor: Option<u32>,
+    datacenters: Option<BTreeMap<String, u32>>,
+    page_size: Option<i32>,
+    request_timeout_ms: Option<u64>,
+    connection_timeout_ms: Option<u64>,
+    cache_max_bytes: Option<usize>,
+}
+
+impl RawConfig {
+    fn into_builder(self) -> Result<QuarkStoreConfigBuilder> {
+        let mut builder = QuarkStoreConfig::builder().nodes(self.nodes.unwrap_or_default());
+        if let Some(keyspace) = self.keyspace {
+            builder = builder.keyspace(keyspace);
+        }
+        match (self.username, self.password) {
+            (Some(username), Some(password)) => builder = builder.credentials(username, password),
+            (None, None) => {}
+            _ => bail!("Username and password have to be set together"),
+        }
+        if let Some(prefix) = self.table_prefix {
+            builder = builder.table_prefix(prefix);
+        }
+        if let Some(namespace) = self.namespace {
+            builder = builder.namespace(namespace);
+        }
+
+        let pool_size = |size: usize| NonZeroUsize::new(size).context("Pool size must not be 0");
+        match (self.connections_per_host, self.connections_per_shard) {
+            (Some(size), None) => builder = builder.pool_size(PoolSize::PerHost(pool_size(size)?)),
+            (None, Some(size)) => builder = builder.pool_size(PoolSize::PerShard(pool_size(size)?)),
+            (None, None) => {}
+            _ => bail!("Only one of connections per host or per shard can be set"),
+        }
+
+        if let Some(consistency) = self.consistency {
+            builder = builder.consistency(parse_consistency(&consistency)?);
+        }
+        if let Some(replication) = parse_replication(
+            self.replication_strategy,
+            self.replication_factor,
+            self.datacenters,
+        )? {
+            builder = builder.replication(replication);
+        }
+        if let Some(page_size) = self.page_size {
+            builder = builder.page_size(page_size);
+        }
+        if let Some(timeout) = self.request_timeout_ms {
+            builder = builder.request_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(timeout) = self.connection_timeout_ms {
+            builder = builder.connection_timeout(Duration::from_millis(timeout));
+        }
+        if let Some(max_bytes) = self.cache_max_bytes {
+            builder = builder.cache(CacheConfig::new(max_bytes));
+        }
+        Ok(builder)
+    }
+}
+
+fn parse_consistency(value: &str) -> Result<Consistency> {
+    let consistency = match value.to_ascii_lowercase().as_str() {
+        "any" => Consistency::Any,
+        "one" => Consistency::One,
+        "two" => Consistency::Two,
+        "three" => Consistency::Three,
+        "quorum" => Consistency::Quorum,
+        "all" => Consistency::All,
+        "local_quorum" => Consistency::LocalQuorum,
+        "each_quorum" => Consistency::EachQuorum,
+        "local_one" => Consistency::LocalOne,
+        _ => return Err(anyhow!("Unknown consistency level {value}")),
+    };
+    Ok(consistency)
+}
+
+fn parse_replication(
+    strategy: Option<String>,
+    replication_factor: Option<u32>,
+    datacenters: Option<BTreeMap<String, u32>>,
+) -> Result<Option<Replication>> {
+    if let Some(datacenters) = datacenters {
+        if replication_factor.is_some() {
+            bail!("Replication factor and datacenters cannot be set together");
+        }
+        if strategy.is_some_and(|strategy| strategy != "NetworkTopologyStrategy") {
+            bail!("Datacenters require the NetworkTopologyStrategy");
+        }
+        return Ok(Some(Replication::Datacenters(
+            datacenters.into_iter().collect(),
+        )));
+    }
+
+    let replication_factor = replication_factor.unwrap_or(1);
+    let replication = match strategy.as_deref() {
+        Some("SimpleStrategy") => Replication::Simple { replication_factor },
+        Some("NetworkTopologyStrategy") | None => {
+            Replication::NetworkTopology { replication_factor }
+        }
+        Some(strategy) => bail!("Unknown replication strategy {strategy}"),
+    };
+    Ok(Some(replication))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashMap;
+
+    #[test]
+    fn test_builder_requires_nodes_and_keyspace() {
+        assert!(QuarkStoreConfig::builder()
+            .keyspace("test")
+            .build()
+            .is_err());
+        assert!(QuarkStoreConfig::builder()
+            .node(DEFAULT_NODE)
+            .build()
+            .is_err());
+        assert!(QuarkStoreConfig::builder()
+            .node(DEFAULT_NODE)
+            .keyspace("test; DROP")
+            .build()
+            .is_err());
+
+        let config = QuarkStoreConfig::builder()
+            .nodes(["10.0.0.1:9042", "10.0.0.2:9042"])
+            .keyspace("test")
+            .credentials("user", "secret")
+            .table_prefix("app_")
+            .build()
+            .unwrap();
+        assert_eq!(config.nodes.len(), 2);
+        assert_eq!(config.table_prefix, "app_");
+        assert_eq!(config.credentials.unwrap().username, "user");
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_session_options() {
+        let builder = || {
+            QuarkStoreConfig::builder()
+                .node(DEFAULT_NODE)
+                .keyspace("test")
+        };
+        assert!(builder().page_size(0).build().is_err());
+        assert!(builder().page_size(-1).build().is_err());
+        assert!(builder()
+            .replication(Replication::Datacenters(vec![(
+                "dc1' : 1}; DROP KEYSPACE test; --".to_string(),
+                1
+            )]))
+            .build()
+            .is_err());
+        assert!(builder()
+            .replication(Replication::Datacenters(vec![("us-east-1".to_string(), 3)]))
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = QuarkStoreConfig::from_toml_str(
+            r#"
+            nodes = ["10.0.0.1:9042", "10.0.0.2:9042"]
+            keyspace = "documents"
+            namespace = "tenant_a"
+            username = "user"
+            password = "secret"
+            connections_per_shard = 2
+            consistency = "quorum"
+            request_timeout_ms = 1500
+            cache_max_bytes = 1024
+
+            [datacenters]
+            dc1 = 3
+            dc2 = 2
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.nodes, vec!["10.0.0.1:9042", "10.0.0.2:9042"]);
+        assert_eq!(config.keyspace, "documents");
+        assert_eq!(config.namespace.as_deref(), Some("tenant_a"));
+        assert!(matches!(config.pool_size, Some(PoolSize::PerShard(size)) if size.get() == 2));
+        assert_eq!(config.session.consistency, Consistency::Quorum);
+        assert_eq!(
+            config.session.replication,
+            Replication::Datacenters(vec![("dc1".into(), 3), ("dc2".into(), 2)])
+        );
+        assert_eq!(
+            config.session.request_timeout,
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(config.cache, Some(CacheConfig::new(1024)));
+
+        assert!(QuarkStoreConfig::from_toml_str("keyspace = \"test\"\nunknown = 1").is_err());
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let mut vars = HashMap::default();
+        vars.insert("SCYLLA_URL", "10.0.0.1:9042, 10.0.0.2:9042");
+        vars.insert("SCYLLA_REPLICATION_STRATEGY", "SimpleStrategy");
+        vars.insert("SCYLLA_REPLICATION_FACTOR", "3");
+        let config =
+            QuarkStoreConfig::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+                .unwrap();
+
+        assert_eq!(config.nodes, vec!["10.0.0.1:9042", "10.0.0.2:9042"]);
+        assert_eq!(config.keyspace, DEFAULT_KEYSPACE);
+        assert_eq!(
+            config.session.replication,
+            Replication::Simple {
+                replication_factor: 3
+            }
+        );
+
+        vars.insert("SCYLLA_PAGE_SIZE", "4294967296");
+        assert!(
+            QuarkStoreConfig::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+                .is_err()
+        );
+        vars.remove("SCYLLA_PAGE_SIZE");
+
+        vars.insert("SCYLLA_USERNAME", "user");
+        assert!(
+            QuarkStoreConfig::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    Commit, CommitMetadata, Deserialize, DeserializeCx, Mergeable, ObjectRef, QuarkStore, Ref,
+    RefStore, Replica, ReplicaId, Serialize, SerializeCx, Tagged, TypeTag, VectorClock,
+    VersionedStore,
+};
+
+/// The first object of every serialized [`DocumentMap`], so an empty map still has a root.
+const DOCUMENT_MAP_HEADER: &str = "documents";
+
+/// Maps the keys of documents to the roots of their objects, so a single commit can hold many
+/// documents. The roots are the right children of the refs of the map, which keeps the
+/// documents reachable from the commit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentMap {
+    roots: BTreeMap<String, ObjectRef>,
+}
+
+impl DocumentMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<ObjectRef> {
+        self.roots.get(key).copied()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, root: ObjectRef) -> Option<ObjectRef> {
+        self.roots.insert(key.into(), root)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<ObjectRef> {
+        self.roots.remove(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.roots.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Merges the maps key by key. A document which has only been changed on one side takes the
+    /// root of that side, and changes win over removals. The keys of documents which have been
+    /// changed on both sides are returned, their objects have to be merged to get the new root.
+    pub fn merge_refs(lca: &Self, left: &Self, right: &Self) -> (Self, BTreeSet<String>) {
+        let mut merged = Self::default();
+        let mut conflicts = BTreeSet::new();
+        let keys = left.roots.keys().chain(right.roots.keys());
+        for key in keys.collect::<BTreeSet<_>>() {
+            let (base, ours, theirs) = (lca.get(key), left.get(key), right.get(key));
+            let root = if ours == theirs || theirs == base {
+                ours
+            } else if ours == base {
+                theirs
+            } else if let (Some(ours), Some(_)) = (ours, theirs) {
+                conflicts.insert(key.clone());
+                Some(ours)
+            } else {
+                ours.or(theirs)
+            };
+            if let Some(root) = root {
+                merged.insert(key.clone(), root);
+            }
+        }
+        (merged, conflicts)
+    }
+}
+
+impl Tagged for DocumentMap {
+    fn type_tag() -> TypeTag {
+        TypeTag::new("DocumentMap")
+    }
+}
+
+impl Serialize for DocumentMap {
+    async fn serialize(&self, cx: SerializeCx<'_>) -> Result<Vec<Ref>> {
+        let header = cx.insert_object(&DOCUMENT_MAP_HEADER.to_string()).await?;
+        let keys = self.roots.keys().collect::<Vec<_>>();
+        let key_refs = cx.insert_objects(&keys).await?;
+
+        let mut refs = vec![Ref::compute(header, None, None)];
+        for (key_ref, root) in key_refs.into_iter().zip(self.roots.values()) {
+            let prev = refs.last().map(|reference| reference.id);
+            refs.push(Ref::compute(key_ref, prev, Some(*root)));
+        }
+        Ok(refs)
+    }
+}
+
+impl Deserialize for DocumentMap {
+    async fn deserialize(root: Ref, cx: DeserializeCx<'_>) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut node = root;
+        while let Some(root) = node.right {
+            entries.push((node.object_ref, root));
+            node = cx
+                .resolve_ref(node.left)
+                .await?
+                .with_context(|| "Document map is missing its header")?;
+        }
+
+        let key_refs = entries.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        let keys = cx.resolve_objects::<String>(&key_refs).await?;
+        let mut roots = BTreeMap::new();
+        for (key, (_, root)) in keys.into_iter().zip(entries) {
+            roots.insert(key.with_context(|| "Document key not found")?, root);
+        }
+        Ok(Self { roots })
+    }
+}
+
+impl QuarkStore {
+    /// Replicates only the documents with the given keys into this store. Commits still contain
+    /// the roots of all documents, so their versions merge with those of full replicas, but the
+    /// objects of other documents are neither pulled nor packed.
+    pub fn with_partial_documents(
+        mut self,
+        keys: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let keys = keys.into_iter().map(Into::into).collect();
+        match &mut self {
+            QuarkStore::Scylla(session) => session.set_partial_documents(keys),
+            QuarkStore::Memory {
+                partial_documents, ..
+            } => *partial_documents = Some(keys),
+        }
+        self
+    }
+
+    /// Returns the keys of the documents this store replicates, or `None` if it replicates all.
+    pub fn partial_documents(&self) -> Option<&BTreeSet<String>> {
+        match self {
+            QuarkStore::Scylla(session) => session.partial_documents(),
+            QuarkStore::Memory {
+                partial_documents, ..
+            } => partial_documents.as_ref(),
+        }
+    }
+
+    pub fn replicates_document(&self, key: &str) -> bool {
+        self.partial_documents()
+            .is_none_or(|documents| documents.contains(key))
+    }
+
+    fn check_replicates_document(&self, key: &str) -> Result<()> {
+        if !self.replicates_document(key) {
+            bail!("Document {key} is not replicated to this store");
+        }
+        Ok(())
+    }
+}
+
+impl Replica {
+    /// Returns the documents of the latest commit, whose object has to be a [`DocumentMap`].
+    pub async fn documents(&self) -> Result<DocumentMap> {
+        self.latest_object::<DocumentMap>()
+            .await?
+            .with_context(|| "Replica has no documents")
+    }
+
+    pub async fn document<T: Deserialize>(&self, key: &str) -> Result<Option<T>> {
+        self.store().check_replicates_document(key)?;
+        match self.documents().await?.get(key) {
+            Some(root) => self.store().resolve(root).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Commits a new version of a single document, the other documents stay unchanged.
+    pub async fn commit_document<T: Serialize>(&mut self, key: &str, object: &T) -> Result<Commit> {
+        self.store().check_replicates_document(key)?;
+        let mut documents = self.documents().await?;
+        documents.insert(key, self.store().insert(object).await?);
+        self.commit_with(
+            &documents,
+            CommitMetadata::new().with_annotation("document", key),
+        )
+        .await
+    }
+
+    pub async fn remove_document(&mut self, key: &str) -> Result<Commit> {
+        self.store().check_replicates_document(key)?;
+        let mut documents = self.documents().await?;
+        documents
+            .remove(key)
+            .with_context(|| format!("Document {key} does not exist"))?;
+        self.commit_with(
+            &documents,
+            CommitMetadata::new().with_annotation("document", key),
+        )
+        .await
+    }
+
+    /// Merges the documents of another replica like [`Replica::merge_with`]. Only documents
+    /// which have been changed on both sides are resolved and merged, they all have to be of
+    /// type `T`. A document which has been added on both sides is merged with an empty base.
+    ///
+    /// A partial store cannot merge documents it does not replicate, so for those the root of
+    /// the other replica is carried forward.
+    pub async fn merge_documents<T: Serialize + Deserialize + Mergeable + Default>(
+        &mut self,
+        other_replica: ReplicaId,
+    ) -> Result<(Commit, DocumentMap)> {
+        let commit_to_merge_with = self.head_to_merge_with(other_replica).await?;
+        let store = self.store();
+        let current = self.documents().await?;
+        let other = store
+            .resolve::<DocumentMap>(commit_to_merge_with.root_ref)
+            .await?
+            .with_context(|| "Documents to merge with are empty")?;
+        let lca = VectorClock::lca(self.latest_version(), &commit_to_merge_with.version);
+        let lca_commit = store.resolve_commit_for_version(lca).await?;
+        let base = store
+            .resolve::<DocumentMap>(lca_commit.root_ref)
+            .await?
+            .with_context(|| "LCA documents are empty")?;
+
+        let (mut merged, conflicts) = DocumentMap::merge_refs(&base, &current, &other);
+        for key in conflicts {
+            if !store.replicates_document(&key) {
+                if let Some(theirs) = other.get(&key) {
+                    log::warn!(
+                        "Document {key} is not replicated to this store, keeping the version of \
+                        replica {other_replica}"
+                    );
+                    merged.insert(key, theirs);
+                }
+                continue;
+            }
+            let resolve = |documents: &DocumentMap| documents.get(&key);
+            let lca_object = match resolve(&base) {
+                Some(root) => store.resolve::<T>(root).await?,
+                None => None,
+            };
+            let (Some(ours), Some(theirs)) = (resolve(&current), resolve(&other)) else {
+                continue;
+            };
+            let current_object = store.resolve::<T>(ours).await?;
+            let other_object = store.resolve::<T>(theirs).await?;
+            let merged_object = T::merge(
+                &lca_object.unwrap_or_default(),
+                &current_object.with_context(|| format!("Document {key} is empty"))?,
+                &other_object.with_context(|| format!("Document {key} is empty"))?,
+            );
+            merged.insert(key, store.insert(&merged_object).await?);
+        }
+
+        let object_ref = store.insert(&merged).await?;
+        let commit = self
+            .commit_merge(
+                object_ref,
+                &commit_to_merge_with,
+                Some(DocumentMap::type_tag()),
+                format!("Merge documents with {other_replica}"),
+            )
+            .await?;
+        Ok((commit, merged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CommitOptions, HashSet, Id, QuarkStore};
+
+    #[tokio::test]
+    async fn test_merge_documents() {
+        let store = QuarkStore::test();
+        let (replica_a, replica_b) = (Id::gen(), Id::gen());
+        let root = store.insert(&DocumentMap::new()).await.unwrap();
+        store
+            .commit(replica_a, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut replica = Replica::clone(replica_a, store).await.unwrap();
+        replica
+            .commit_document("notes", &HashSet::from_iter([1u64, 2]))
+            .await
+            .unwrap();
+        replica
+            .commit_document("todo", &HashSet::from_iter([7u64]))
+            .await
+            .unwrap();
+
+        let store = replica.store();
+        let mut documents = DocumentMap::new();
+        let notes = store.insert(&HashSet::from_iter([1u64, 3])).await.unwrap();
+        documents.insert("notes", notes);
+        let mut version = VectorClock::default();
+        version.inc(replica_b);
+        let other_root = store.insert(&documents).await.unwrap();
+        let other_head = store.commit(replica_b, version, other_root).await.unwrap();
+
+        let (commit, merged) = replica
+            .merge_documents::<HashSet<u64>>(replica_b)
+            .await
+            .unwrap();
+        assert_eq!(merged.keys().collect::<Vec<_>>(), vec!["notes", "todo"]);
+        assert_eq!(commit.merge_parent_ids, vec![other_head.id]);
+        assert_eq!(
+            replica.document::<HashSet<u64>>("notes").await.unwrap(),
+            Some(HashSet::from_iter([1, 2, 3]))
+        );
+
+        replica.remove_document("todo").await.unwrap();
+        assert_eq!(replica.documents().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_partial_replication() {
+        let full = QuarkStore::test();
+        let (replica_a, replica_b) = (Id::gen(), Id::gen());
+        let mut documents = DocumentMap::new();
+        let notes = full.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        let photos = full.insert(&HashSet::from_iter([100u64])).await.unwrap();
+        documents.insert("notes", notes);
+        documents.insert("photos", photos);
+        let root = full.insert(&documents).await.unwrap();
+        full.commit_with(
+            replica_a,
+            VectorClock::default(),
+            root,
+            CommitOptions::new().with_type_tag(DocumentMap::type_tag()),
+        )
+        .await
+        .unwrap();
+        let mut replica = Replica::clone(replica_a, full).await.unwrap();
+
+        let edge = QuarkStore::test().with_partial_documents(["notes"]);
+        edge.pull("full", replica.store()).await.unwrap();
+        assert!(edge.resolve_ref(Some(notes)).await.unwrap().is_some());
+        assert!(edge.resolve_ref(Some(photos)).await.unwrap().is_none());
+
+        let mut edge_replica = Replica::clone(replica_b, edge).await.unwrap();
+        assert!(edge_replica
+            .commit_document("photos", &HashSet::from_iter([101u64]))
+            .await
+            .is_err());
+        edge_replica
+            .commit_document("notes", &HashSet::from_iter([1u64, 2]))
+            .await
+            .unwrap();
+        edge_replica
+            .store()
+            .push("full", replica.store())
+            .await
+            .unwrap();
+
+        replica
+            .commit_document("photos", &HashSet::from_iter([100u64, 101]))
+            .await
+            .unwrap();
+        replica
+            .merge_documents::<HashSet<u64>>(replica_b)
+            .await
+            .unwrap();
+        assert_eq!(
+            replica.document::<HashSet<u64>>("notes").await.unwrap(),
+            Some(HashSet::from_iter([1, 2]))
+        );
+        assert_eq!(
+            replica.document::<HashSet<u64>>("photos").await.unwrap(),
+            Some(HashSet::from_iter([100, 101]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_merge_keeps_unreplicated_documents() {
+        let store = QuarkStore::test().with_partial_documents(["notes"]);
+        let [replica_a, replica_b, replica_c] = [(); 3].map(|_| Id::gen());
+        let documents = |photos: ObjectRef| {
+            let mut documents = DocumentMap::new();
+            documents.insert("photos", photos);
+            documents
+        };
+        let root = store.insert(&documents(100)).await.unwrap();
+        store
+            .commit(replica_a, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut replica = Replica::clone(replica_a, store).await.unwrap();
+
+        // Both replicas change the photos, whose objects the partial store does not have.
+        for (other, photos) in [(replica_b, 101), (replica_c, 102)] {
+            let mut version = VectorClock::default();
+            version.inc(other);
+            let root = replica.store().insert(&documents(photos)).await.unwrap();
+            replica.store().commit(other, version, root).await.unwrap();
+        }
+        let (_, merged) = replica
+            .merge_documents::<HashSet<u64>>(replica_b)
+            .await
+            .unwrap();
+        assert_eq!(merged.get("photos"), Some(101));
+        let (_, merged) = replica
+            .merge_documents::<HashSet<u64>>(replica_c)
+            .await
+            .unwrap();
+        assert_eq!(merged.get("photos"), Some(102));
+    }
+}
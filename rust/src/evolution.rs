@@ -0,0 +1,339 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use anyhow::{bail, Context, Result};
+use musli::{de::DecodeOwned, mode::Binary, Encode};
+
+use crate::{quark::ENCODING, Deserialize, DeserializeCx, HashMap, HashSet, QuarkStore};
+
+/// Precedes the encoding of objects which have been written with a version other than the
+/// first one. Objects without the marker have been written with version 1.
+const VERSION_MARKER: [u8; 4] = [0xff, 0xfe, b'O', b'V'];
+
+/// Converts the encoding of an older version into the encoding of the current version.
+type Upgrade = Box<dyn Fn(&[u8]) -> Result<Vec<u8>> + Send + Sync>;
+
+/// The upgrades of the types stored in a [`QuarkStore`], which allow changing a type after
+/// objects of it have been written.
+///
+/// The version of a type starts at 1 and is one above the latest version an upgrade has been
+/// registered for. Objects are written with the current version of their type, and objects of
+/// older versions are upgraded transparently when they are resolved.
+#[derive(Default)]
+pub struct ObjectMigrations {
+    upgrades: HashMap<&'static str, BTreeMap<u32, Upgrade>>,
+}
+
+impl ObjectMigrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers how objects of `T`, which have been written with the given version as `Old`,
+    /// are converted to the current version of `T`.
+    pub fn register<T, Old>(
+        mut self,
+        version: u32,
+        upgrade: impl Fn(Old) -> T + Send + Sync + 'static,
+    ) -> Self
+    where
+        T: Encode<Binary>,
+        Old: DecodeOwned<Binary>,
+    {
+        let upgrade: Upgrade = Box::new(move |bytes| {
+            let old: Old = ENCODING
+                .decode(bytes)
+                .with_context(|| format!("Failed to deserialize object of version {version}"))?;
+            let mut data = Vec::new();
+            ENCODING
+                .encode(&mut data, &upgrade(old))
+                .with_context(|| "Failed to serialize upgraded object")?;
+            Ok(data)
+        });
+        self.upgrades
+            .entry(std::any::type_name::<T>())
+            .or_default()
+            .insert(version, upgrade);
+        self
+    }
+
+    /// Returns the version objects of `T` are written with.
+    pub fn current_version<T>(&self) -> u32 {
+        self.upgrades
+            .get(std::any::type_name::<T>())
+            .and_then(|upgrades| upgrades.last_key_value())
+            .map_or(1, |(version, _)| version + 1)
+    }
+
+    /// Encodes the object with the current version of its type.
+    pub(crate) fn encode<T: Encode<Binary>>(&self, object: &T) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let version = self.current_version::<T>();
+        if version > 1 {
+            data.extend_from_slice(&VERSION_MARKER);
+            data.extend_from_slice(&version.to_le_bytes());
+        }
+        ENCODING
+            .encode(&mut data, object)
+            .with_context(|| "Failed to serialize object")?;
+        Ok(data)
+    }
+
+    /// Decodes an object of any known version of `T`.
+    pub(crate) fn decode<T: DecodeOwned<Binary>>(&self, bytes: &[u8]) -> Result<T> {
+        let upgraded = self.upgrade::<T>(bytes)?;
+        ENCODING
+            .decode(upgraded.as_deref().unwrap_or(bytes))
+            .with_context(|| "Failed to deserialize object")
+    }
+
+    /// Returns the encoding of the current version of `T`, without the version header, or `None`
+    /// if the bytes already contain the current version.
+    fn upgrade<T>(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+        let current = self.current_version::<T>();
+        let (version, payload) = split_version(bytes)?;
+        if version == current {
+            return Ok((payload.len() != bytes.len()).then(|| payload.to_vec()));
+        }
+        if version > current {
+            bail!(
+                "Object has version {version} of {}, but the latest known version is {current}",
+                std::any::type_name::<T>()
+            );
+        }
+
+        let upgrade = self
+            .upgrades
+            .get(std::any::type_name::<T>())
+            .and_then(|upgrades| upgrades.get(&version))
+            .with_context(|| {
+                format!(
+                    "No upgrade registered for version {version} of {}",
+                    std::any::type_name::<T>()
+                )
+            })?;
+        upgrade(payload).map(Some)
+    }
+
+    /// Returns the bytes of the object in the current version of `T`, including the version
+    /// header, or `None` if the object does not have to be rewritten.
+    fn rewrite<T>(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+        let current = self.current_version::<T>();
+        if split_version(bytes)?.0 == current {
+            return Ok(None);
+        }
+
+        let payload = self.upgrade::<T>(bytes)?.unwrap_or_else(|| bytes.to_vec());
+        let mut data = Vec::with_capacity(payload.len() + 8);
+        if current > 1 {
+            data.extend_from_slice(&VERSION_MARKER);
+            data.extend_from_slice(&current.to_le_bytes());
+        }
+        data.extend_from_slice(&payload);
+        Ok(Some(data))
+    }
+}
+
+/// Splits stored object bytes into the version and the encoded object.
+fn split_version(bytes: &[u8]) -> Result<(u32, &[u8])> {
+    let Some(rest) = bytes.strip_prefix(VERSION_MARKER.as_slice()) else {
+        return Ok((1, bytes));
+    };
+    let (version, payload) = rest
+        .split_first_chunk::<4>()
+        .with_context(|| "Object version header is truncated")?;
+    Ok((u32::from_le_bytes(*version), payload))
+}
+
+/// The outcome of [`QuarkStore::rewrite_objects`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RewriteSummary {
+    pub objects_scanned: usize,
+    pub objects_rewritten: usize,
+}
+
+impl QuarkStore {
+    /// Registers the upgrades which are applied to objects of older versions when they are
+    /// resolved, and determines the version new objects are written with.
+    pub fn with_migrations(mut self, migrations: ObjectMigrations) -> Self {
+        match &mut self {
+            QuarkStore::Scylla(session) => session.set_migrations(migrations),
+            QuarkStore::Memory {
+                migrations: store_migrations,
+                ..
+            } => *store_migrations = migrations,
+        }
+        self
+    }
+
+    /// Rewrites all objects of older versions of `T`, which the deserialization of the commits
+    /// of the type `R` decodes as `T`, in the current version, so they no longer have to be
+    /// upgraded when they are resolved. Objects keep their ids, so no refs or commits are
+    /// changed.
+    ///
+    /// The rewritten objects are evicted from the cache of this store, see
+    /// [`QuarkStore::insert_object_bytes`]. Caches of other processes
+    /// may still return the old version, which is upgraded when it is resolved.
+    pub async fn rewrite_objects<R: Deserialize, T>(&self) -> Result<RewriteSummary> {
+        let type_name = R::type_tag().name;
+        let roots = self
+            .commits()
+            .await?
+            .into_iter()
+            .filter(|commit| {
+                commit
+                    .type_tag
+                    .as_ref()
+                    .is_some_and(|tag| tag.name == type_name)
+            })
+            .map(|commit| commit.root_ref)
+            .collect::<HashSet<_>>();
+
+        let visited = Mutex::new(Vec::new());
+        for root in roots {
+            let root = self
+                .resolve_ref(Some(root))
+                .await?
+                .with_context(|| format!("Ref {root} not found"))?;
+            R::deserialize(root, DeserializeCx::recording(self, &visited)).await?;
+        }
+        let object_refs = visited
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter(|(_, visited_type)| *visited_type == std::any::type_name::<T>())
+            .map(|(object_ref, _)| object_ref)
+            .collect::<HashSet<_>>();
+
+        let mut summary = RewriteSummary::default();
+        let mut rewritten = Vec::new();
+        for object_ref in object_refs {
+            let bytes = self
+                .resolve_object_bytes(object_ref)
+                .await?
+                .with_context(|| format!("Object {object_ref} not found"))?;
+            summary.objects_scanned += 1;
+            if let Some(bytes) = self.migrations().rewrite::<T>(&bytes)? {
+                rewritten.push((object_ref, bytes));
+            }
+        }
+
+        summary.objects_rewritten = rewritten.len();
+        self.insert_object_bytes(&rewritten).await?;
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use musli::Decode;
+
+    use super::*;
+    use crate::{
+        CommitOptions, DocumentMap, Id, ItemName, ObjectStore, RefStore, Tagged, VectorClock,
+        VersionedStore,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+    struct ItemV1 {
+        value: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+    struct Item {
+        value: u64,
+        label: String,
+    }
+
+    impl ItemName for ItemV1 {
+        fn item_name() -> String {
+            "ItemV1".to_string()
+        }
+
+        fn item_schema() -> String {
+            "ItemV1 { value: u64 }".to_string()
+        }
+    }
+
+    impl ItemName for Item {
+        fn item_name() -> String {
+            "Item".to_string()
+        }
+
+        fn item_schema() -> String {
+            "Item { value: u64, label: String }".to_string()
+        }
+    }
+
+    fn migrations() -> ObjectMigrations {
+        ObjectMigrations::new().register::<Item, ItemV1>(1, |old| Item {
+            value: old.value,
+            label: format!("item {}", old.value),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_objects_are_upgraded_on_resolve() {
+        let store = QuarkStore::test();
+        let old = store.insert_object(&ItemV1 { value: 1 }).await.unwrap();
+
+        let store = store.with_migrations(migrations());
+        let new = store
+            .insert_object(&Item {
+                value: 2,
+                label: "two".into(),
+            })
+            .await
+            .unwrap();
+
+        let items = store.resolve_objects::<Item>(&[old, new]).await.unwrap();
+        assert_eq!(items[0].as_ref().unwrap().label, "item 1");
+        assert_eq!(items[1].as_ref().unwrap().label, "two");
+
+        // Readers which do not know the new version reject the object.
+        let bytes = store.resolve_object_bytes(new).await.unwrap().unwrap();
+        assert!(ObjectMigrations::new().decode::<Item>(&bytes).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_objects() {
+        let store = QuarkStore::test();
+        let items = vec![ItemV1 { value: 1 }, ItemV1 { value: 2 }];
+        let root = store.insert(&items).await.unwrap();
+
+        let store = store.with_migrations(migrations());
+        store
+            .commit_with(
+                Id::gen(),
+                VectorClock::default(),
+                root,
+                CommitOptions::new().with_type_tag(<Vec<Item>>::type_tag()),
+            )
+            .await
+            .unwrap();
+
+        // The documents are not decoded as part of the map, only its keys.
+        let mut documents = DocumentMap::new();
+        documents.insert("items", root);
+        let documents_root = store.insert(&documents).await.unwrap();
+        store
+            .commit_with(
+                Id::gen(),
+                VectorClock::default(),
+                documents_root,
+                CommitOptions::new().with_type_tag(DocumentMap::type_tag()),
+            )
+            .await
+            .unwrap();
+        let summary = store.rewrite_objects::<DocumentMap, Item>().await.unwrap();
+        assert_eq!(summary.objects_scanned, 0);
+
+        let summary = store.rewrite_objects::<Vec<Item>, Item>().await.unwrap();
+        assert_eq!(summary.objects_scanned, 2);
+        assert_eq!(summary.objects_rewritten, 2);
+        let summary = store.rewrite_objects::<Vec<Item>, Item>().await.unwrap();
+        assert_eq!(summary.objects_rewritten, 0);
+
+        let upgraded: Vec<Item> = store.resolve(root).await.unwrap().unwrap();
+        assert_eq!(upgraded[1].label, "item 2");
+    }
+}
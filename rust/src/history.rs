@@ -0,0 +1,200 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use musli::{Decode, Encode};
+
+use crate::{
+    Commit, CommitId, QuarkStore, Replica, ReplicaId, SigningKey, TypeTag, VersionedStore,
+};
+
+/// A hybrid logical clock timestamp, which follows the wall clock but stays monotonic across
+/// replicas whose clocks are skewed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub struct HybridTimestamp {
+    /// Milliseconds since the Unix epoch.
+    pub wall_ms: u64,
+    /// Orders timestamps with the same wall clock time.
+    pub logical: u32,
+}
+
+impl HybridTimestamp {
+    /// Returns a timestamp which is later than all of the given timestamps and not earlier than
+    /// the current wall clock time.
+    pub fn next(after: impl IntoIterator<Item = HybridTimestamp>) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        match after.into_iter().max() {
+            Some(latest) if latest.wall_ms >= now => Self {
+                wall_ms: latest.wall_ms,
+                logical: latest.logical + 1,
+            },
+            _ => Self {
+                wall_ms: now,
+                logical: 0,
+            },
+        }
+    }
+
+    pub fn as_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.wall_ms)
+    }
+}
+
+impl std::fmt::Display for HybridTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.wall_ms, self.logical)
+    }
+}
+
+/// Optional information about a commit, which is recorded for auditing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct CommitMetadata {
+    pub author: Option<String>,
+    pub timestamp: Option<HybridTimestamp>,
+    pub message: Option<String>,
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl CommitMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Sets the timestamp explicitly, otherwise [`Replica::commit_with`] uses the clock of the
+    /// replica.
+    pub fn with_timestamp(mut self, timestamp: HybridTimestamp) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_annotation(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.annotations.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Everything a commit records besides its version and root, see
+/// [`VersionedStore::commit_with`].
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions<'a> {
+    /// The heads of other replicas which are merged into the commit in addition to its parent.
+    pub merge_parent_ids: Vec<CommitId>,
+    /// The type of the object behind the root of the commit.
+    pub type_tag: Option<TypeTag>,
+    pub metadata: CommitMetadata,
+    /// Signs the commit with the key if given.
+    pub signing_key: Option<&'a SigningKey>,
+}
+
+impl<'a> CommitOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_merge_parents(mut self, commit_ids: impl IntoIterator<Item = CommitId>) -> Self {
+        self.merge_parent_ids = commit_ids.into_iter().collect();
+        self
+    }
+
+    pub fn with_type_tag(mut self, type_tag: TypeTag) -> Self {
+        self.type_tag = Some(type_tag);
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: CommitMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn signed_with(mut self, signing_key: &'a SigningKey) -> Self {
+        self.signing_key = Some(signing_key);
+        self
+    }
+}
+
+impl QuarkStore {
+    /// Returns the commits of the replica starting with its head and following the parents, so
+    /// the latest commit comes first. Returns at most `limit` commits if a limit is given.
+    pub async fn history(
+        &self,
+        replica_id: ReplicaId,
+        limit: Option<usize>,
+    ) -> Result<Vec<Commit>> {
+        let mut history = Vec::new();
+        let mut next = self.latest_commit_for_replica(replica_id).await?;
+        while let Some(commit) = next {
+            if limit.is_some_and(|limit| history.len() >= limit) {
+                break;
+            }
+            next = match commit.parent_commit_id {
+                Some(parent_id) => Some(self.resolve_commit(parent_id).await?),
+                None => None,
+            };
+            history.push(commit);
+        }
+        Ok(history)
+    }
+}
+
+impl Replica {
+    /// Returns the history of the replica, see [`QuarkStore::history`].
+    pub async fn history(&self, limit: Option<usize>) -> Result<Vec<Commit>> {
+        self.store().history(self.id(), limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, RefStore, VectorClock};
+
+    #[tokio::test]
+    async fn test_commit_metadata_in_history() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&vec![1u64]).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+
+        let mut replica = Replica::clone(replica_id, store).await.unwrap();
+        let metadata = CommitMetadata::new()
+            .with_author("alice")
+            .with_message("Add two")
+            .with_annotation("ticket", "42");
+        let first = replica
+            .commit_with(&vec![1u64, 2], metadata.clone())
+            .await
+            .unwrap();
+        let second = replica.commit_object(&vec![1u64, 2, 3]).await.unwrap();
+
+        let history = replica.history(None).await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0], second);
+        assert_eq!(history[1], first);
+        assert_eq!(history[1].metadata.author, metadata.author);
+        assert_eq!(history[1].metadata.annotations, metadata.annotations);
+        assert!(history[2].metadata.is_empty());
+        assert!(second.metadata.timestamp > first.metadata.timestamp);
+        assert_eq!(replica.history(Some(1)).await.unwrap(), vec![second]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{hash::Hash, marker::PhantomData};
+
+use anyhow::{Context, Result};
+use futures::{stream, Stream, TryStreamExt};
+use musli::{de::DecodeOwned, mode::Binary};
+use tokio::sync::OnceCell;
+
+use crate::{object_hash, ObjectRef, ObjectStore, QuarkStore, Ref};
+
+/// Number of objects which are fetched from the store at once while streaming.
+const STREAM_CHUNK_SIZE: usize = 100;
+
+/// A handle to a list or set stored in a [`QuarkStore`] whose elements are only fetched and
+/// decoded on demand.
+///
+/// Only the chain of refs is loaded (once) to determine the order of the elements, the objects
+/// themselves are resolved when they are accessed.
+pub struct LazyCollection<'a, T> {
+    store: &'a QuarkStore,
+    root: Ref,
+    object_refs: OnceCell<Vec<ObjectRef>>,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl QuarkStore {
+    /// Returns a lazy handle to the collection with the given root ref, without decoding any of
+    /// its elements.
+    pub async fn resolve_lazy<T: Hash + DecodeOwned<Binary>>(
+        &self,
+        root: u64,
+    ) -> Result<Option<LazyCollection<'_, T>>> {
+        let Some(root) = self.resolve_ref(Some(root)).await? else {
+            return Ok(None);
+        };
+        Ok(Some(LazyCollection {
+            store: self,
+            root,
+            object_refs: OnceCell::new(),
+            _item: PhantomData,
+        }))
+    }
+}
+
+impl<'a, T: Hash + DecodeOwned<Binary>> LazyCollection<'a, T> {
+    /// Returns the root ref of the collection.
+    pub fn root(&self) -> &Ref {
+        &self.root
+    }
+
+    /// Returns the number of elements in the collection.
+    pub async fn len(&self) -> Result<usize> {
+        Ok(self.object_refs().await?.len())
+    }
+
+    /// Returns whether the collection has no elements.
+    pub async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Fetches and decodes the element at the given index.
+    pub async fn get(&self, index: usize) -> Result<Option<T>> {
+        let Some(&object_ref) = self.object_refs().await?.get(index) else {
+            return Ok(None);
+        };
+        let object = self
+            .store
+            .resolve_object(object_ref)
+            .await?
+            .with_context(|| "Object not found")?;
+        Ok(Some(object))
+    }
+
+    /// Checks whether the collection contains the given item by comparing content hashes, so no
+    /// element has to be fetched or decoded.
+    pub async fn contains(&self, item: &T) -> Result<bool> {
+        let hash = object_hash(item);
+        Ok(self.object_refs().await?.contains(&hash))
+    }
+
+    /// Streams the elements of the collection in order, fetching them in chunks.
+    pub fn stream(&self) -> impl Stream<Item = Result<T>> + '_ {
+        stream::try_unfold(0, move |offset| async move {
+            let object_refs = self.object_refs().await?;
+            if offset >= object_refs.len() {
+                return anyhow::Ok(None);
+            }
+
+            let end = std::cmp::min(offset + STREAM_CHUNK_SIZE, object_refs.len());
+            let items = self
+                .store
+                .resolve_objects::<T>(&object_refs[offset..end])
+                .await?
+                .into_iter()
+                .map(|object| object.with_context(|| "Object not found"))
+                .collect::<Vec<_>>();
+            Ok(Some((stream::iter(items), end)))
+        })
+        .try_flatten()
+    }
+
+    async fn object_refs(&self) -> Result<&[ObjectRef]> {
+        let object_refs = self
+            .object_refs
+            .get_or_try_init(|| async {
+                let mut object_refs = vec![self.root.object_ref];
+                let mut node = self.root.clone();
+                while let Some(new_node) = self.store.resolve_ref(node.left).await? {
+                    object_refs.push(new_node.object_ref);
+                    node = new_node;
+                }
+                object_refs.reverse();
+                anyhow::Ok(object_refs)
+            })
+            .await?;
+        Ok(object_refs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RefStore;
+
+    #[tokio::test]
+    async fn test_lazy_collection_access() {
+        let store = QuarkStore::test();
+        let list = vec![10u64, 20, 30, 40];
+        let root = store.insert(&list).await.unwrap();
+
+        let lazy = store.resolve_lazy::<u64>(root).await.unwrap().unwrap();
+        assert_eq!(lazy.len().await.unwrap(), 4);
+        assert_eq!(lazy.get(0).await.unwrap(), Some(10));
+        assert_eq!(lazy.get(3).await.unwrap(), Some(40));
+        assert_eq!(lazy.get(4).await.unwrap(), None);
+        assert!(lazy.contains(&30).await.unwrap());
+        assert!(!lazy.contains(&50).await.unwrap());
+
+        let streamed: Vec<u64> = lazy.stream().try_collect().await.unwrap();
+        assert_eq!(streamed, list);
+    }
+
+    #[tokio::test]
+    async fn test_lazy_collection_streams_in_chunks() {
+        let store = QuarkStore::test();
+        let list = (0..(STREAM_CHUNK_SIZE as u64 * 2 + 5)).collect::<Vec<_>>();
+        let root = store.insert(&list).await.unwrap();
+
+        let lazy = store.resolve_lazy::<u64>(root).await.unwrap().unwrap();
+        let streamed: Vec<u64> = lazy.stream().try_collect().await.unwrap();
+        assert_eq!(streamed, list);
+    }
+}
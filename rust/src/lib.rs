@@ -1,16 +1,56 @@
+pub mod agent;
+pub mod branch;
+pub mod bundle;
+pub mod cache;
+pub mod config;
+pub mod documents;
+pub mod evolution;
+pub mod history;
+pub mod lazy;
 pub mod list;
+pub mod membership;
+pub mod namespace;
+pub mod net;
+pub mod notify;
+pub mod offline;
+pub mod preview;
 pub mod quark;
 pub mod replica;
+pub mod report;
+pub mod schema;
 pub mod set;
+pub mod signing;
+pub mod sync;
+pub mod type_tag;
+pub mod typed;
 pub mod vector_clock;
 
+pub use agent::*;
 pub use anyhow::{Context, Result};
+pub use branch::*;
+pub use bundle::*;
+pub use cache::*;
+pub use config::*;
+pub use documents::*;
+pub use evolution::*;
+pub use history::*;
+pub use lazy::*;
+pub use membership::*;
 use musli::{
     mode::{Binary, Text},
     Decode, Encode,
 };
+pub use namespace::*;
+pub use net::*;
+pub use notify::*;
+pub use offline::*;
+pub use preview::*;
 pub use quark::*;
 pub use replica::*;
+pub use report::*;
+pub use signing::*;
+pub use type_tag::*;
+pub use typed::*;
 pub use vector_clock::*;
 
 pub type HashSet<T> = fxhash::FxHashSet<T>;
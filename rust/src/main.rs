@@ -1,6 +1,5 @@
 use mrdt_rs::*;
 use musli::{Decode, Encode};
-use std::env;
 
 #[derive(Default, Debug, Hash, Clone, PartialEq, Eq, Decode, Encode)]
 pub struct Person {
@@ -9,6 +8,16 @@ pub struct Person {
     pub age: i32,
 }
 
+impl ItemName for Person {
+    fn item_name() -> String {
+        "Person".to_string()
+    }
+
+    fn item_schema() -> String {
+        "Person { first_name: String, last_name: String, age: i32 }".to_string()
+    }
+}
+
 impl Person {
     pub fn new(first_name: impl Into<String>, last_name: impl Into<String>, age: i32) -> Self {
         Self {
@@ -23,11 +32,11 @@ impl Person {
 async fn main() -> Result<()> {
     env_logger::init();
 
-    let hostname = env::var("SCYLLA_URL").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let config = QuarkStoreConfig::from_env()?;
 
-    let base_store = QuarkStore::setup(hostname.clone(), "test").await.unwrap();
-    let store1 = QuarkStore::setup(hostname.clone(), "test").await.unwrap();
-    let store2 = QuarkStore::setup(hostname, "test").await.unwrap();
+    let base_store = QuarkStore::connect(config.clone()).await.unwrap();
+    let store1 = QuarkStore::connect(config.clone()).await.unwrap();
+    let store2 = QuarkStore::connect(config).await.unwrap();
 
     // TODO: It is unclear how to handle different replicas without a "common" ancestor, we start
     // by manually establishing a base commit
@@ -0,0 +1,178 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+
+use crate::{CommitId, HybridTimestamp, QuarkStore, ReplicaId, VectorClock, VersionedStore};
+
+/// How a replica has been registered in the store.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Membership {
+    pub name: Option<String>,
+    pub aliases: BTreeSet<String>,
+    /// Retired replicas keep their history, but no longer take part in syncing.
+    pub retired: bool,
+}
+
+/// A replica of the store, as returned by [`QuarkStore::list_replicas`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaInfo {
+    pub id: ReplicaId,
+    pub membership: Membership,
+    pub head: Option<CommitId>,
+    /// The version of the head, which contains everything the replica has seen so far.
+    pub last_seen_version: Option<VectorClock>,
+    /// The time the head was committed, if it has been recorded.
+    pub last_seen_at: Option<HybridTimestamp>,
+    /// The number of commits of active replicas which the replica has not merged yet.
+    pub lag: u32,
+}
+
+impl QuarkStore {
+    /// Registers the replica under the given name. Registering a retired replica again makes it
+    /// active.
+    pub async fn register_replica(&self, replica_id: ReplicaId, name: &str) -> Result<()> {
+        self.check_name_available(replica_id, name).await?;
+        self.set_replica_name(replica_id, name).await?;
+        self.set_replica_retired(replica_id, false).await
+    }
+
+    pub async fn rename_replica(&self, replica_id: ReplicaId, name: &str) -> Result<()> {
+        self.membership(replica_id).await?;
+        self.check_name_available(replica_id, name).await?;
+        self.set_replica_name(replica_id, name).await
+    }
+
+    /// Adds another name under which the replica can be found with [`QuarkStore::find_replica`].
+    pub async fn add_replica_alias(&self, replica_id: ReplicaId, alias: &str) -> Result<()> {
+        self.membership(replica_id).await?;
+        self.check_name_available(replica_id, alias).await?;
+        self.insert_replica_alias(replica_id, alias).await
+    }
+
+    pub async fn retire_replica(&self, replica_id: ReplicaId) -> Result<()> {
+        self.membership(replica_id).await?;
+        self.set_replica_retired(replica_id, true).await
+    }
+
+    /// Returns the replica with the given name or alias.
+    pub async fn find_replica(&self, name: &str) -> Result<Option<ReplicaId>> {
+        Ok(self
+            .replica_memberships()
+            .await?
+            .into_iter()
+            .find(|(_, membership)| {
+                membership.name.as_deref() == Some(name) || membership.aliases.contains(name)
+            })
+            .map(|(replica_id, _)| replica_id))
+    }
+
+    /// Returns how the replica has been registered, or an error if it has not been registered.
+    pub async fn membership(&self, replica_id: ReplicaId) -> Result<Membership> {
+        match self.replica_memberships().await?.remove(&replica_id) {
+            Some(membership) => Ok(membership),
+            None => bail!("Replica {replica_id} is not registered"),
+        }
+    }
+
+    /// Lists all registered replicas and all replicas which have a head.
+    pub async fn list_replicas(&self) -> Result<Vec<ReplicaInfo>> {
+        let mut memberships = self.replica_memberships().await?;
+        let mut replicas = Vec::new();
+        for (replica_id, head) in self.replica_heads().await? {
+            let commit = self.resolve_commit(head).await?;
+            replicas.push(ReplicaInfo {
+                id: replica_id,
+                membership: memberships.remove(&replica_id).unwrap_or_default(),
+                head: Some(head),
+                last_seen_version: Some(commit.version),
+                last_seen_at: commit.metadata.timestamp,
+                lag: 0,
+            });
+        }
+        replicas.extend(
+            memberships
+                .into_iter()
+                .map(|(replica_id, membership)| ReplicaInfo {
+                    id: replica_id,
+                    membership,
+                    head: None,
+                    last_seen_version: None,
+                    last_seen_at: None,
+                    lag: 0,
+                }),
+        );
+
+        let frontier = replicas
+            .iter()
+            .filter(|replica| !replica.membership.retired)
+            .filter_map(|replica| replica.last_seen_version.as_ref())
+            .fold(VectorClock::default(), |frontier, version| {
+                VectorClock::merge(&frontier, version)
+            });
+        for replica in &mut replicas {
+            let version = replica.last_seen_version.clone().unwrap_or_default();
+            let seen = u32::from(version.sum());
+            replica.lag = u32::from(VectorClock::merge(&frontier, &version).sum()) - seen;
+        }
+        replicas.sort_by_key(|replica| replica.id);
+        Ok(replicas)
+    }
+
+    async fn check_name_available(&self, replica_id: ReplicaId, name: &str) -> Result<()> {
+        match self.find_replica(name).await? {
+            Some(owner) if owner != replica_id => {
+                bail!("Name {name} is already used by replica {owner}")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the replicas which have been retired.
+    pub async fn retired_replicas(&self) -> Result<Vec<ReplicaId>> {
+        Ok(self
+            .replica_memberships()
+            .await?
+            .into_iter()
+            .filter(|(_, membership)| membership.retired)
+            .map(|(replica_id, _)| replica_id)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, RefStore};
+
+    #[tokio::test]
+    async fn test_replica_membership() {
+        let store = QuarkStore::test();
+        let (replica_a, replica_b) = (Id::gen(), Id::gen());
+        store.register_replica(replica_a, "laptop").await.unwrap();
+        store.add_replica_alias(replica_a, "alice").await.unwrap();
+        store.register_replica(replica_b, "phone").await.unwrap();
+        assert!(store.register_replica(replica_b, "laptop").await.is_err());
+        assert_eq!(store.find_replica("alice").await.unwrap(), Some(replica_a));
+
+        let root = store.insert(&vec![1u64]).await.unwrap();
+        let mut version = VectorClock::default();
+        version.inc(replica_a);
+        store
+            .commit(replica_b, version.clone(), root)
+            .await
+            .unwrap();
+        version.inc(replica_a);
+        store.commit(replica_a, version, root).await.unwrap();
+
+        let replicas = store.list_replicas().await.unwrap();
+        let lag = |id| replicas.iter().find(|info| info.id == id).unwrap().lag;
+        assert_eq!((lag(replica_a), lag(replica_b)), (0, 1));
+
+        store.retire_replica(replica_a).await.unwrap();
+        assert_eq!(store.retired_replicas().await.unwrap(), vec![replica_a]);
+        store.rename_replica(replica_a, "old laptop").await.unwrap();
+        let membership = store.membership(replica_a).await.unwrap();
+        assert_eq!(membership.name.as_deref(), Some("old laptop"));
+        assert!(membership.retired);
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{Commit, CommitId, HashSet, QuarkStore};
+
+/// The amount of data which belongs to a namespace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NamespaceStats {
+    pub replicas: u64,
+    pub commits: u64,
+    /// The refs reachable from the commits of the namespace. As refs and objects are content
+    /// addressed, they can be shared with other namespaces.
+    pub refs: u64,
+    pub objects: u64,
+    /// The size of all objects in their encoded form.
+    pub object_bytes: u64,
+}
+
+/// The number of refs and objects which have been removed by [`QuarkStore::sweep_refs`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectedGarbage {
+    pub refs: u64,
+    pub objects: u64,
+}
+
+impl QuarkStore {
+    /// Isolates the store from all other namespaces in the same keyspace. The commits, heads,
+    /// branches, tags, remote heads, public keys, type tags and memberships of the store are kept
+    /// in the partition of the namespace, so cloning, syncing and tags only see the commits and
+    /// replicas of the namespace, while refs and objects are still shared, as they are content
+    /// addressed.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        let namespace = namespace.into();
+        match &mut self {
+            QuarkStore::Scylla(session) => session.set_namespace(namespace),
+            QuarkStore::Memory {
+                namespace: current, ..
+            } => *current = Some(namespace),
+        }
+        self
+    }
+
+    /// Returns the namespace of the store, or `None` if it uses the tables without a namespace.
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            QuarkStore::Scylla(session) => session.namespace(),
+            QuarkStore::Memory { namespace, .. } => namespace.as_deref(),
+        }
+    }
+
+    /// Returns the key of the partition which holds the tags, branches, remote heads, keys and
+    /// memberships of the store. Stores without a namespace use the empty namespace.
+    pub(crate) fn namespace_key(&self) -> &str {
+        self.namespace().unwrap_or_default()
+    }
+
+    /// Returns the amount of data which belongs to the namespace. Only the partition of the
+    /// namespace and the refs and objects its commits reach are read.
+    pub async fn namespace_stats(&self, namespace: &str) -> Result<NamespaceStats> {
+        let commits = self.partition_commits(Some(namespace)).await?;
+        let refs = self
+            .collect_refs(
+                commits.iter().map(|commit| commit.root_ref),
+                &mut HashSet::default(),
+            )
+            .await?;
+        let objects: HashSet<_> = refs.iter().map(|reference| reference.object_ref).collect();
+
+        let mut object_bytes = 0;
+        for id in &objects {
+            object_bytes += self
+                .resolve_object_bytes(*id)
+                .await?
+                .map_or(0, |bytes| bytes.len() as u64);
+        }
+        Ok(NamespaceStats {
+            replicas: self.partition_heads(Some(namespace)).await?.len() as u64,
+            commits: commits.len() as u64,
+            refs: refs.len() as u64,
+            objects: objects.len() as u64,
+            object_bytes,
+        })
+    }
+
+    /// Removes the commits of the namespace which can no longer be reached from the heads and
+    /// branches of its replicas, the heads of its remotes, its tags or the `retained` commits, and
+    /// returns how many were removed. Only the partition of the namespace is read.
+    ///
+    /// Offline snapshots and bundles which have not been imported yet are invisible to the store,
+    /// so their history must be retained explicitly, e.g. with
+    /// [`crate::Bundle::referenced_commits`].
+    ///
+    /// The refs and objects of the removed commits are kept, as other namespaces can share them.
+    /// [`QuarkStore::sweep_refs`] removes them once no commit reaches them anymore.
+    pub async fn collect_garbage(&self, namespace: &str, retained: &[CommitId]) -> Result<u64> {
+        let commits: HashMap<CommitId, Commit> = self
+            .partition_commits(Some(namespace))
+            .await?
+            .into_iter()
+            .map(|commit| (commit.id, commit))
+            .collect();
+
+        let mut stack: Vec<CommitId> = self
+            .tag_targets(namespace)
+            .await?
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect();
+        stack.extend(
+            self.partition_heads(Some(namespace))
+                .await?
+                .into_iter()
+                .map(|(_, id)| id),
+        );
+        stack.extend(self.namespace_branch_heads(namespace).await?);
+        stack.extend(self.namespace_remote_heads(namespace).await?);
+        stack.extend(retained.iter().copied());
+        let mut reachable = HashSet::default();
+        while let Some(commit_id) = stack.pop() {
+            if !reachable.insert(commit_id) {
+                continue;
+            }
+            if let Some(commit) = commits.get(&commit_id) {
+                stack.extend(commit.parent_commit_id);
+                stack.extend(commit.merge_parent_ids.iter().copied());
+            }
+        }
+
+        let mut removed = 0;
+        for commit_id in commits.keys() {
+            if !reachable.contains(commit_id) {
+                self.remove_commit(Some(namespace), *commit_id).await?;
+                removed += 1;
+            }
+        }
+        log::debug!("Collected {removed} commits of namespace {namespace}");
+        Ok(removed)
+    }
+
+    /// Removes the refs and objects which can no longer be reached from any commit, e.g. once
+    /// [`QuarkStore::collect_garbage`] removed the commits referencing them.
+    ///
+    /// Refs and objects are shared by all namespaces, so the sweep reads the commits of every
+    /// namespace and the whole ref table. It must not run concurrently with writes, as refs are
+    /// stored before the commit which references them.
+    pub async fn sweep_refs(&self) -> Result<CollectedGarbage> {
+        let mut roots: Vec<u64> = self
+            .partition_commits(None)
+            .await?
+            .into_iter()
+            .map(|commit| commit.root_ref)
+            .collect();
+        for namespace in self.namespaces().await? {
+            roots.extend(
+                self.partition_commits(Some(&namespace))
+                    .await?
+                    .into_iter()
+                    .map(|commit| commit.root_ref),
+            );
+        }
+        let live_refs = self.collect_refs(roots, &mut HashSet::default()).await?;
+        let live_ids: HashSet<_> = live_refs.iter().map(|reference| reference.id).collect();
+        let live_objects: HashSet<_> = live_refs
+            .iter()
+            .map(|reference| reference.object_ref)
+            .collect();
+
+        let garbage = self
+            .ref_objects()
+            .await?
+            .into_iter()
+            .filter(|(id, _)| !live_ids.contains(id))
+            .collect::<Vec<_>>();
+        let garbage_refs = garbage.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let garbage_objects = garbage
+            .iter()
+            .map(|(_, object_ref)| *object_ref)
+            .filter(|object_ref| !live_objects.contains(object_ref))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        self.remove_refs(&garbage_refs).await?;
+        self.remove_objects(&garbage_objects).await?;
+
+        log::debug!(
+            "Swept {} refs and {} objects",
+            garbage_refs.len(),
+            garbage_objects.len()
+        );
+        Ok(CollectedGarbage {
+            refs: garbage_refs.len() as u64,
+            objects: garbage_objects.len() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CommitOptions, Id, RefStore, VectorClock, VersionedStore};
+
+    #[tokio::test]
+    async fn test_namespaces_are_isolated() {
+        let store = QuarkStore::test().with_namespace("a");
+        let (replica_a, replica_b, replica_c) = (Id::gen(), Id::gen(), Id::gen());
+        let shared_root = store.insert(&vec![1u64]).await.unwrap();
+        let first = store
+            .commit(replica_a, VectorClock::default(), shared_root)
+            .await
+            .unwrap();
+        store
+            .create_branch(replica_a, "draft", first.id)
+            .await
+            .unwrap();
+        let draft_root = store.insert(&vec![7u64, 8]).await.unwrap();
+        let mut version = VectorClock::default();
+        version.inc(replica_a);
+        store
+            .commit_to_branch(
+                replica_a,
+                "draft",
+                version,
+                draft_root,
+                CommitOptions::new(),
+            )
+            .await
+            .unwrap();
+        store.create_tag("v1", first.id).await.unwrap();
+
+        store
+            .set_remote_head("origin", replica_b, first.id)
+            .await
+            .unwrap();
+        store.set_replica_name(replica_a, "alice").await.unwrap();
+        store
+            .insert_replica_public_key(replica_a, [1; 32])
+            .await
+            .unwrap();
+
+        let store = store.with_namespace("b");
+        assert!(store.branch_heads(replica_a).await.unwrap().is_empty());
+        assert!(store.remote_heads("origin").await.unwrap().is_empty());
+        assert!(store.replica_memberships().await.unwrap().is_empty());
+        assert!(store.replica_public_key(replica_a).await.unwrap().is_none());
+        assert!(store.clone(replica_b).await.is_err());
+        assert!(store.commits().await.unwrap().is_empty());
+        assert!(store.tag("v1").await.unwrap().is_none());
+        let second = store
+            .commit(replica_b, VectorClock::default(), shared_root)
+            .await
+            .unwrap();
+        store.create_tag("v1", second.id).await.unwrap();
+        assert_eq!(store.clone(replica_c).await.unwrap(), second);
+        assert_eq!(
+            store
+                .resolve_commit_for_version(VectorClock::default())
+                .await
+                .unwrap(),
+            second
+        );
+        assert_eq!(store.replica_heads().await.unwrap().len(), 2);
+
+        let mut namespaces = store.namespaces().await.unwrap();
+        namespaces.sort();
+        assert_eq!(namespaces, vec!["a", "b"]);
+        let stats = store.namespace_stats("a").await.unwrap();
+        assert_eq!((stats.replicas, stats.commits), (1, 2));
+
+        let store = store.with_namespace("a");
+        assert_eq!(store.collect_garbage("a", &[]).await.unwrap(), 0);
+        store.delete_branch(replica_a, "draft").await.unwrap();
+        assert_eq!(store.collect_garbage("a", &[]).await.unwrap(), 1);
+        assert!(store.resolve_ref(Some(draft_root)).await.unwrap().is_some());
+        let collected = store.sweep_refs().await.unwrap();
+        assert!(collected.refs > 0);
+        assert!(store.resolve_ref(Some(draft_root)).await.unwrap().is_none());
+        assert!(store
+            .resolve_ref(Some(shared_root))
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(store.namespace_stats("a").await.unwrap().commits, 1);
+        assert_eq!(store.namespace_stats("b").await.unwrap().commits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_garbage_collection_keeps_every_head() {
+        let store = QuarkStore::test().with_namespace("a");
+        let replica_id = Id::gen();
+        let root = store.insert(&vec![1u64]).await.unwrap();
+        let first = store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut version = VectorClock::default();
+        for name in ["fetched", "snapshot", "draft"] {
+            version.inc(replica_id);
+            store
+                .create_branch(replica_id, name, first.id)
+                .await
+                .unwrap();
+            store
+                .commit_to_branch(
+                    replica_id,
+                    name,
+                    version.clone(),
+                    root,
+                    CommitOptions::new(),
+                )
+                .await
+                .unwrap();
+        }
+        let heads = store.branch_heads(replica_id).await.unwrap();
+        let head = |name: &str| heads.iter().find(|(branch, _)| branch == name).unwrap().1;
+        store
+            .set_remote_head("origin", Id::gen(), head("fetched"))
+            .await
+            .unwrap();
+        store.delete_branch(replica_id, "fetched").await.unwrap();
+        store.delete_branch(replica_id, "snapshot").await.unwrap();
+
+        let other = store.with_namespace("b");
+        assert_eq!(
+            other
+                .collect_garbage("a", &[head("snapshot")])
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(other.collect_garbage("a", &[]).await.unwrap(), 1);
+        let store = other.with_namespace("a");
+        assert!(store.find_commit(head("snapshot")).await.unwrap().is_none());
+        assert!(store.find_commit(head("fetched")).await.unwrap().is_some());
+        assert!(store.find_commit(head("draft")).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,672 @@
+use std::net::SocketAddr;
+
+use anyhow::{bail, Context, Result};
+use futures::{stream::FuturesUnordered, StreamExt};
+use musli::{Decode, Encode};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use crate::{
+    quark::ENCODING, Bundle, BundleHead, BundleObject, Commit, CommitId, HashSet, ImportSummary,
+    ObjectRef, QuarkStore, Ref, ReplicaId, VectorClock,
+};
+
+/// Sent by both sides when a connection is opened, followed by their [`PROTOCOL_VERSION`].
+const PROTOCOL_MAGIC: &[u8; 8] = b"MRDTSYNC";
+/// The version of the sync protocol. Peers only talk to each other if their versions match.
+///
+/// Version 2 added the documents a partial store replicates to [`SyncRequest::Pull`].
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Frames larger than this are rejected, so a corrupted or malicious length prefix cannot make
+/// the reader allocate much memory. Bundles are sent in multiple parts to stay below it.
+const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+/// The number of commit ids sent in one frame.
+const COMMIT_IDS_PER_PART: usize = 16 * 1024;
+/// The number of commits or refs of a bundle sent in one frame.
+const COMMITS_PER_PART: usize = 256;
+const REFS_PER_PART: usize = 16 * 1024;
+/// Objects are added to a part until their size exceeds this.
+const OBJECT_BYTES_PER_PART: usize = 1024 * 1024;
+/// The number of commit ids a client may announce with [`SyncRequest::Have`] before its next
+/// [`SyncRequest::Pull`].
+const MAX_HAVES: usize = 4 * 1024 * 1024;
+/// The number of heads, commits, refs, objects and omitted roots a pushed bundle may consist of.
+const MAX_PUSHED_ITEMS: usize = 16 * 1024 * 1024;
+/// The total size of the objects of a pushed bundle.
+const MAX_PUSHED_OBJECT_BYTES: usize = 1024 * 1024 * 1024;
+
+/// A request sent from a [`SyncClient`] to a sync server.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum SyncRequest {
+    /// Asks for the heads of the replicas on the server.
+    Heads,
+    /// Asks for the summary of the history on the server, see [`QuarkStore::haves`]. It is sent in
+    /// multiple [`SyncResponse::Haves`] parts, the last of which is empty.
+    Haves,
+    /// Adds commits the client has to those which are left out of the next [`SyncRequest::Pull`],
+    /// together with their ancestors. This is not answered.
+    Have(Vec<CommitId>),
+    /// Asks for the history of the given replicas, or of all replicas, which is missing on the
+    /// client. The bundle is sent in multiple [`SyncResponse::Bundle`] parts.
+    Pull {
+        replica_ids: Option<Vec<ReplicaId>>,
+        /// The documents the store replicates, all documents when `None`.
+        documents: Option<Vec<String>>,
+    },
+    /// A part of a bundle, which is imported into the store of the server once its last part
+    /// arrived. Only the last part is answered.
+    Push(BundlePart),
+}
+
+/// The response of a sync server to a [`SyncRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum SyncResponse {
+    Heads(Vec<BundleHead>),
+    Haves(Vec<CommitId>),
+    Bundle(BundlePart),
+    Imported(ImportSummary),
+    Error(String),
+}
+
+/// A part of a [`Bundle`] sent over the wire. The parts of a bundle end with [`BundlePart::End`].
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum BundlePart {
+    Heads(Vec<BundleHead>),
+    Base(VectorClock),
+    Commits(Vec<Commit>),
+    Refs(Vec<Ref>),
+    Objects(Vec<BundleObject>),
+    OmittedRoots(Vec<ObjectRef>),
+    End,
+}
+
+impl BundlePart {
+    /// Splits the bundle into parts, which are small enough to be sent in a single frame.
+    pub fn split(bundle: Bundle) -> Vec<BundlePart> {
+        let mut parts = vec![BundlePart::Heads(bundle.heads)];
+        parts.extend(bundle.base.map(BundlePart::Base));
+        parts.extend(chunks(bundle.commits, COMMITS_PER_PART).map(BundlePart::Commits));
+        parts.extend(chunks(bundle.refs, REFS_PER_PART).map(BundlePart::Refs));
+
+        let mut objects = Vec::new();
+        let mut size = 0;
+        for object in bundle.objects {
+            size += object.bytes.len();
+            objects.push(object);
+            if size >= OBJECT_BYTES_PER_PART {
+                parts.push(BundlePart::Objects(std::mem::take(&mut objects)));
+                size = 0;
+            }
+        }
+        if !objects.is_empty() {
+            parts.push(BundlePart::Objects(objects));
+        }
+
+        parts.extend(chunks(bundle.omitted_roots, REFS_PER_PART).map(BundlePart::OmittedRoots));
+        parts.push(BundlePart::End);
+        parts
+    }
+
+    /// Returns the number of items in the part and the total size of its objects.
+    fn size(&self) -> (usize, usize) {
+        match self {
+            BundlePart::Heads(heads) => (heads.len(), 0),
+            BundlePart::Base(_) | BundlePart::End => (0, 0),
+            BundlePart::Commits(commits) => (commits.len(), 0),
+            BundlePart::Refs(refs) => (refs.len(), 0),
+            BundlePart::Objects(objects) => (
+                objects.len(),
+                objects.iter().map(|object| object.bytes.len()).sum(),
+            ),
+            BundlePart::OmittedRoots(roots) => (roots.len(), 0),
+        }
+    }
+
+    /// Adds the part to the bundle. Returns true if this was the last part.
+    pub fn apply(self, bundle: &mut Bundle) -> bool {
+        match self {
+            BundlePart::Heads(heads) => bundle.heads.extend(heads),
+            BundlePart::Base(base) => bundle.base = Some(base),
+            BundlePart::Commits(commits) => bundle.commits.extend(commits),
+            BundlePart::Refs(refs) => bundle.refs.extend(refs),
+            BundlePart::Objects(objects) => bundle.objects.extend(objects),
+            BundlePart::OmittedRoots(roots) => bundle.omitted_roots.extend(roots),
+            BundlePart::End => return true,
+        }
+        false
+    }
+}
+
+fn chunks<T>(items: Vec<T>, size: usize) -> impl Iterator<Item = Vec<T>> {
+    let mut items = items.into_iter().peekable();
+    std::iter::from_fn(move || {
+        items.peek()?;
+        Some(items.by_ref().take(size).collect())
+    })
+}
+
+/// Writes a single frame, which consists of the length of the encoded message as a little
+/// endian u32 followed by the message itself.
+pub async fn write_frame<T: Encode<musli::mode::Binary>>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &T,
+) -> Result<()> {
+    let mut payload = Vec::new();
+    ENCODING
+        .encode(&mut payload, message)
+        .with_context(|| "Failed to encode frame")?;
+    if payload.len() > MAX_FRAME_SIZE {
+        bail!("Frame of {} bytes exceeds the maximum size", payload.len());
+    }
+
+    writer.write_u32_le(payload.len() as u32).await?;
+    writer.write_all(&payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads a single frame written by [`write_frame`]. Returns `None` if the connection was closed
+/// before a new frame started.
+pub async fn read_frame<T: for<'de> Decode<'de, musli::mode::Binary>>(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<T>> {
+    let len = match reader.read_u32_le().await {
+        Ok(len) => len as usize,
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if len > MAX_FRAME_SIZE {
+        bail!("Frame of {len} bytes exceeds the maximum size");
+    }
+
+    // The buffer grows with the data which actually arrives, not with the announced length.
+    let mut payload = Vec::new();
+    reader.take(len as u64).read_to_end(&mut payload).await?;
+    if payload.len() != len {
+        bail!("Connection closed in the middle of a frame");
+    }
+    let message = ENCODING
+        .from_slice(&payload)
+        .with_context(|| "Failed to decode frame")?;
+    Ok(Some(message))
+}
+
+/// Sends the protocol version to the peer and checks that it speaks the same one.
+async fn handshake(stream: &mut (impl AsyncRead + AsyncWrite + Unpin)) -> Result<()> {
+    let mut hello = Vec::with_capacity(PROTOCOL_MAGIC.len() + 4);
+    hello.extend_from_slice(PROTOCOL_MAGIC);
+    hello.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    stream.write_all(&hello).await?;
+    stream.flush().await?;
+
+    let mut magic = [0; PROTOCOL_MAGIC.len()];
+    stream
+        .read_exact(&mut magic)
+        .await
+        .with_context(|| "Connection closed during the handshake")?;
+    if &magic != PROTOCOL_MAGIC {
+        bail!("Peer does not speak the sync protocol");
+    }
+    let version = stream.read_u32_le().await?;
+    if version != PROTOCOL_VERSION {
+        bail!("Peer speaks sync protocol version {version}, expected {PROTOCOL_VERSION}");
+    }
+    Ok(())
+}
+
+/// Serves the store to sync clients connecting to the listener.
+///
+/// Connections are handled concurrently on the current task, so the store does not have to be
+/// shared between threads. This only returns if accepting a connection fails.
+pub async fn serve(store: &QuarkStore, listener: TcpListener) -> Result<()> {
+    let mut connections = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, addr) = accepted.with_context(|| "Failed to accept connection")?;
+                log::debug!("Accepted sync connection from {addr}");
+                connections.push(handle_connection(store, stream, addr));
+            }
+            Some((addr, result)) = connections.next(), if !connections.is_empty() => {
+                match result {
+                    Ok(()) => log::debug!("Sync connection from {addr} closed"),
+                    Err(err) => log::warn!("Sync connection from {addr} failed: {err:#}"),
+                }
+            }
+        }
+    }
+}
+
+async fn handle_connection(
+    store: &QuarkStore,
+    mut stream: TcpStream,
+    addr: SocketAddr,
+) -> (SocketAddr, Result<()>) {
+    let result = async {
+        handshake(&mut stream).await?;
+        let mut connection = Connection::default();
+        while let Some(request) = read_frame::<SyncRequest>(&mut stream).await? {
+            connection.admit(&request)?;
+            let responses = connection
+                .handle_request(store, request)
+                .await
+                .unwrap_or_else(|err| vec![SyncResponse::Error(format!("{err:#}"))]);
+            for response in &responses {
+                write_frame(&mut stream, response).await?;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    (addr, result)
+}
+
+/// Bounds the state a connection accumulates across requests.
+#[derive(Debug, Clone, Copy)]
+struct ConnectionLimits {
+    haves: usize,
+    pushed_items: usize,
+    pushed_object_bytes: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            haves: MAX_HAVES,
+            pushed_items: MAX_PUSHED_ITEMS,
+            pushed_object_bytes: MAX_PUSHED_OBJECT_BYTES,
+        }
+    }
+}
+
+/// The state of a connection, which spans multiple requests.
+#[derive(Default)]
+struct Connection {
+    limits: ConnectionLimits,
+    haves: HashSet<CommitId>,
+    pushed: Bundle,
+    pushed_items: usize,
+    pushed_object_bytes: usize,
+}
+
+impl Connection {
+    /// Accounts for the state the request adds to the connection. Fails if it exceeds the
+    /// limits, in which case the connection is dropped, as the client does not follow the
+    /// protocol.
+    fn admit(&mut self, request: &SyncRequest) -> Result<()> {
+        match request {
+            SyncRequest::Have(commit_ids) => {
+                if self.haves.len() + commit_ids.len() > self.limits.haves {
+                    bail!(
+                        "Client announced more than {} commits before pulling",
+                        self.limits.haves
+                    );
+                }
+            }
+            SyncRequest::Push(part) => {
+                let (items, object_bytes) = part.size();
+                self.pushed_items += items;
+                self.pushed_object_bytes += object_bytes;
+                if self.pushed_items > self.limits.pushed_items
+                    || self.pushed_object_bytes > self.limits.pushed_object_bytes
+                {
+                    bail!(
+                        "Pushed bundle exceeds {} items or {} bytes of objects",
+                        self.limits.pushed_items,
+                        self.limits.pushed_object_bytes
+                    );
+                }
+            }
+            SyncRequest::Heads | SyncRequest::Haves | SyncRequest::Pull { .. } => {}
+        }
+        Ok(())
+    }
+
+    async fn handle_request(
+        &mut self,
+        store: &QuarkStore,
+        request: SyncRequest,
+    ) -> Result<Vec<SyncResponse>> {
+        Ok(match request {
+            SyncRequest::Heads => vec![SyncResponse::Heads(heads(store).await?)],
+            SyncRequest::Haves => {
+                let haves = store.haves().await?.into_iter().collect();
+                chunks(haves, COMMIT_IDS_PER_PART)
+                    .chain([Vec::new()])
+                    .map(SyncResponse::Haves)
+                    .collect()
+            }
+            SyncRequest::Have(commit_ids) => {
+                self.haves.extend(commit_ids);
+                Vec::new()
+            }
+            SyncRequest::Pull {
+                replica_ids,
+                documents,
+            } => {
+                let haves = std::mem::take(&mut self.haves);
+                let documents = documents.map(|keys| keys.into_iter().collect());
+                let bundle = store
+                    .pack_missing(replica_ids.as_deref(), documents.as_ref(), &haves)
+                    .await?;
+                BundlePart::split(bundle)
+                    .into_iter()
+                    .map(SyncResponse::Bundle)
+                    .collect()
+            }
+            SyncRequest::Push(part) => {
+                if !part.apply(&mut self.pushed) {
+                    return Ok(Vec::new());
+                }
+                let bundle = std::mem::take(&mut self.pushed);
+                self.pushed_items = 0;
+                self.pushed_object_bytes = 0;
+                vec![SyncResponse::Imported(store.import_bundle(&bundle).await?)]
+            }
+        })
+    }
+}
+
+async fn heads(store: &QuarkStore) -> Result<Vec<BundleHead>> {
+    Ok(store
+        .replica_heads()
+        .await?
+        .into_iter()
+        .map(|(replica_id, commit_id)| BundleHead {
+            replica_id,
+            commit_id,
+        })
+        .collect())
+}
+
+/// A connection to a sync server, which synchronizes a local store with the store of the server.
+pub struct SyncClient {
+    stream: TcpStream,
+}
+
+impl SyncClient {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr)
+            .await
+            .with_context(|| "Failed to connect to sync server")?;
+        stream.set_nodelay(true)?;
+        handshake(&mut stream).await?;
+        Ok(Self { stream })
+    }
+
+    /// Returns the heads of the replicas on the server.
+    pub async fn heads(&mut self) -> Result<Vec<BundleHead>> {
+        match self.request(&SyncRequest::Heads).await? {
+            SyncResponse::Heads(heads) => Ok(heads),
+            response => unexpected(response),
+        }
+    }
+
+    /// Fetches everything the local store is missing from the server, like
+    /// [`QuarkStore::pull`].
+    pub async fn pull(&mut self, store: &QuarkStore, remote_name: &str) -> Result<ImportSummary> {
+        let haves = store.haves().await?.into_iter().collect();
+        for commit_ids in chunks(haves, COMMIT_IDS_PER_PART) {
+            write_frame(&mut self.stream, &SyncRequest::Have(commit_ids)).await?;
+        }
+        let request = SyncRequest::Pull {
+            replica_ids: None,
+            documents: store
+                .partial_documents()
+                .map(|keys| keys.iter().cloned().collect()),
+        };
+        write_frame(&mut self.stream, &request).await?;
+
+        let mut bundle = Bundle::default();
+        loop {
+            match self.response().await? {
+                SyncResponse::Bundle(part) => {
+                    if part.apply(&mut bundle) {
+                        break;
+                    }
+                }
+                response => return unexpected(response),
+            }
+        }
+
+        let summary = store.import_bundle(&bundle).await?;
+        for head in &bundle.heads {
+            store
+                .set_remote_head(remote_name, head.replica_id, head.commit_id)
+                .await?;
+        }
+        Ok(summary)
+    }
+
+    /// Sends everything the server is missing to it, like [`QuarkStore::push`].
+    pub async fn push(&mut self, store: &QuarkStore, remote_name: &str) -> Result<ImportSummary> {
+        write_frame(&mut self.stream, &SyncRequest::Haves).await?;
+        let mut haves = HashSet::default();
+        loop {
+            match self.response().await? {
+                SyncResponse::Haves(commit_ids) if commit_ids.is_empty() => break,
+                SyncResponse::Haves(commit_ids) => haves.extend(commit_ids),
+                response => return unexpected(response),
+            }
+        }
+
+        let bundle = store.pack_missing(None, None, &haves).await?;
+        for part in BundlePart::split(bundle) {
+            write_frame(&mut self.stream, &SyncRequest::Push(part)).await?;
+        }
+        let summary = match self.response().await? {
+            SyncResponse::Imported(summary) => summary,
+            response => return unexpected(response),
+        };
+
+        for head in self.heads().await? {
+            store
+                .set_remote_head(remote_name, head.replica_id, head.commit_id)
+                .await?;
+        }
+        Ok(summary)
+    }
+
+    async fn request(&mut self, request: &SyncRequest) -> Result<SyncResponse> {
+        write_frame(&mut self.stream, request).await?;
+        self.response().await
+    }
+
+    async fn response(&mut self) -> Result<SyncResponse> {
+        match read_frame(&mut self.stream).await? {
+            Some(SyncResponse::Error(err)) => bail!("Sync server failed: {err}"),
+            Some(response) => Ok(response),
+            None => bail!("Sync server closed the connection"),
+        }
+    }
+}
+
+fn unexpected<T>(response: SyncResponse) -> Result<T> {
+    bail!("Unexpected response from sync server: {response:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, RefStore, VectorClock, VersionedStore};
+
+    #[tokio::test]
+    async fn test_frames_roundtrip() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &SyncRequest::Heads).await.unwrap();
+        write_frame(&mut buffer, &SyncRequest::Haves).await.unwrap();
+
+        let mut reader = buffer.as_slice();
+        let first: Option<SyncRequest> = read_frame(&mut reader).await.unwrap();
+        let second: Option<SyncRequest> = read_frame(&mut reader).await.unwrap();
+        let third: Option<SyncRequest> = read_frame(&mut reader).await.unwrap();
+        assert_eq!(first, Some(SyncRequest::Heads));
+        assert_eq!(second, Some(SyncRequest::Haves));
+        assert_eq!(third, None);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frames_are_rejected() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_frame::<SyncRequest>(&mut buffer.as_slice())
+            .await
+            .is_err());
+
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&1024u32.to_le_bytes());
+        buffer.extend_from_slice(&[0; 16]);
+        assert!(read_frame::<SyncRequest>(&mut buffer.as_slice())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_other_versions() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let (client_result, server_result) = tokio::join!(handshake(&mut client), async {
+            server.write_all(PROTOCOL_MAGIC).await?;
+            server.write_u32_le(PROTOCOL_VERSION + 1).await?;
+            let mut hello = [0; PROTOCOL_MAGIC.len() + 4];
+            server.read_exact(&mut hello).await?;
+            anyhow::Ok(hello)
+        });
+        assert!(client_result.is_err());
+        assert_eq!(
+            &server_result.unwrap()[..PROTOCOL_MAGIC.len()],
+            PROTOCOL_MAGIC
+        );
+    }
+
+    #[test]
+    fn test_bundle_parts_roundtrip() {
+        let bundle = Bundle {
+            heads: vec![BundleHead {
+                replica_id: Id::gen(),
+                commit_id: Id::gen(),
+            }],
+            base: Some(VectorClock::default()),
+            refs: (0..REFS_PER_PART as u64 + 1)
+                .map(|id| Ref::compute(id, None, None))
+                .collect(),
+            objects: (0..3)
+                .map(|id| BundleObject {
+                    id,
+                    bytes: vec![0; OBJECT_BYTES_PER_PART / 2 + 1],
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let parts = BundlePart::split(bundle.clone());
+        assert_eq!(parts.len(), 7);
+        let mut received = Bundle::default();
+        let ends = parts
+            .into_iter()
+            .map(|part| part.apply(&mut received))
+            .collect::<Vec<_>>();
+        assert_eq!(ends.iter().filter(|end| **end).count(), 1);
+        assert!(ends.last().unwrap());
+        assert_eq!(received, bundle);
+    }
+
+    #[tokio::test]
+    async fn test_connection_limits() {
+        let store = QuarkStore::test();
+        let mut connection = Connection {
+            limits: ConnectionLimits {
+                haves: 2,
+                pushed_items: 2,
+                pushed_object_bytes: 4,
+            },
+            ..Default::default()
+        };
+
+        let have = SyncRequest::Have(vec![Id::gen(), Id::gen()]);
+        connection.admit(&have).unwrap();
+        connection.handle_request(&store, have).await.unwrap();
+        assert!(connection
+            .admit(&SyncRequest::Have(vec![Id::gen()]))
+            .is_err());
+
+        let objects = |bytes: usize| {
+            SyncRequest::Push(BundlePart::Objects(vec![BundleObject {
+                id: 1,
+                bytes: vec![0; bytes],
+            }]))
+        };
+        connection.admit(&objects(4)).unwrap();
+        assert!(connection.admit(&objects(1)).is_err());
+
+        let mut connection = Connection {
+            limits: connection.limits,
+            ..Default::default()
+        };
+        for request in [
+            SyncRequest::Push(BundlePart::OmittedRoots(vec![1, 2])),
+            SyncRequest::Push(BundlePart::End),
+        ] {
+            connection.admit(&request).unwrap();
+            connection.handle_request(&store, request).await.unwrap();
+        }
+        // The limits apply to every pushed bundle on its own.
+        connection
+            .admit(&SyncRequest::Push(BundlePart::OmittedRoots(vec![1, 2])))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sync_over_loopback() {
+        let server_store = QuarkStore::test();
+        let client_store = QuarkStore::test();
+        let server_replica = Id::gen();
+        let client_replica = Id::gen();
+
+        let mut version = VectorClock::default();
+        version.inc(server_replica);
+        let root = server_store.insert(&vec![1u64, 2]).await.unwrap();
+        server_store
+            .commit(server_replica, version, root)
+            .await
+            .unwrap();
+
+        let mut version = VectorClock::default();
+        version.inc(client_replica);
+        let root = client_store.insert(&vec![3u64]).await.unwrap();
+        client_store
+            .commit(client_replica, version, root)
+            .await
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = async {
+            let mut client = SyncClient::connect(addr).await?;
+            assert_eq!(client.heads().await?.len(), 1);
+
+            let pulled = client.pull(&client_store, "server").await?;
+            assert_eq!(pulled.updated_heads, vec![server_replica]);
+
+            let pushed = client.push(&client_store, "server").await?;
+            assert_eq!(pushed.updated_heads, vec![client_replica]);
+            assert_eq!(client.heads().await?.len(), 2);
+            anyhow::Ok(())
+        };
+
+        tokio::select! {
+            result = serve(&server_store, listener) => panic!("Server stopped: {result:?}"),
+            result = client => result.unwrap(),
+        }
+
+        assert_eq!(client_store.remote_heads("server").await.unwrap().len(), 2);
+        assert!(server_store
+            .latest_commit_for_replica(client_replica)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,160 @@
+use std::{collections::VecDeque, future::ready, time::Duration};
+
+use anyhow::Result;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{Commit, CommitId, HashMap, QuarkStore, ReplicaId, VectorClock, VersionedStore};
+
+/// How many commits an in-memory store buffers for slow subscribers.
+pub(crate) const NOTIFICATION_CAPACITY: usize = 1024;
+
+/// How often stores backed by Scylla are polled for new commits by default.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A commit which has been added to the history of a replica.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitEvent {
+    pub replica_id: ReplicaId,
+    pub commit: Commit,
+}
+
+impl QuarkStore {
+    /// Subscribes to new commits of the given replica, or of all replicas when `None`.
+    ///
+    /// Only commits made after the subscription are reported. In-memory stores notify their
+    /// subscribers directly, stores backed by Scylla are polled with [`DEFAULT_POLL_INTERVAL`].
+    pub async fn subscribe(
+        &self,
+        replica_id: Option<ReplicaId>,
+    ) -> Result<BoxStream<'_, Result<CommitEvent>>> {
+        self.subscribe_with_interval(replica_id, DEFAULT_POLL_INTERVAL)
+            .await
+    }
+
+    /// Like [`QuarkStore::subscribe`], but polls stores backed by Scylla with the given interval.
+    pub async fn subscribe_with_interval(
+        &self,
+        replica_id: Option<ReplicaId>,
+        poll_interval: Duration,
+    ) -> Result<BoxStream<'_, Result<CommitEvent>>> {
+        let events = match self {
+            QuarkStore::Scylla(_) => self.poll_commits(poll_interval).await?,
+            QuarkStore::Memory { notifier, .. } => {
+                stream::unfold(notifier.subscribe(), |mut receiver| async move {
+                    loop {
+                        match receiver.recv().await {
+                            Ok(event) => return Some((Ok(event), receiver)),
+                            Err(RecvError::Lagged(skipped)) => {
+                                log::warn!("Subscriber missed {skipped} commits");
+                            }
+                            Err(RecvError::Closed) => return None,
+                        }
+                    }
+                })
+                .boxed()
+            }
+        };
+
+        Ok(events
+            .try_filter(move |event| {
+                ready(replica_id.is_none_or(|replica_id| replica_id == event.replica_id))
+            })
+            .boxed())
+    }
+
+    /// Polls the heads of all replicas and reports the commits since the last seen head, which
+    /// acts as the watermark of a replica. For replicas which appear for the first time, only
+    /// their head is reported.
+    async fn poll_commits(
+        &self,
+        poll_interval: Duration,
+    ) -> Result<BoxStream<'_, Result<CommitEvent>>> {
+        let mut watermarks = HashMap::default();
+        for (replica_id, commit_id) in self.replica_heads().await? {
+            let commit = self.resolve_commit(commit_id).await?;
+            watermarks.insert(replica_id, (commit.id, commit.version));
+        }
+
+        let state = PollState {
+            watermarks,
+            pending: VecDeque::new(),
+        };
+        Ok(stream::try_unfold(state, move |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Ok(Some((event, state)));
+                }
+
+                tokio::time::sleep(poll_interval).await;
+                for (replica_id, head_id) in self.replica_heads().await? {
+                    let watermark = state.watermarks.get(&replica_id);
+                    if watermark.is_some_and(|(commit_id, _)| *commit_id == head_id) {
+                        continue;
+                    }
+
+                    let head = self.resolve_commit(head_id).await?;
+                    let mut commits = vec![head.clone()];
+                    if let Some((watermark_id, watermark_version)) = watermark {
+                        while let Some(parent_id) = commits.last().unwrap().parent_commit_id {
+                            let parent = self.resolve_commit(parent_id).await?;
+                            if parent.id == *watermark_id || parent.version <= *watermark_version {
+                                break;
+                            }
+                            commits.push(parent);
+                        }
+                    }
+
+                    state.pending.extend(
+                        commits
+                            .into_iter()
+                            .rev()
+                            .map(|commit| CommitEvent { replica_id, commit }),
+                    );
+                    state.watermarks.insert(replica_id, (head.id, head.version));
+                }
+            }
+        })
+        .boxed())
+    }
+}
+
+struct PollState {
+    watermarks: HashMap<ReplicaId, (CommitId, VectorClock)>,
+    pending: VecDeque<CommitEvent>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, RefStore};
+
+    #[tokio::test]
+    async fn test_subscribe_to_commits() {
+        let store = QuarkStore::test();
+        let (replica_a, replica_b) = (Id::gen(), Id::gen());
+        let root = store.insert(&vec![1u64]).await.unwrap();
+
+        let mut all = store.subscribe(None).await.unwrap();
+        let mut only_b = store.subscribe(Some(replica_b)).await.unwrap();
+
+        let first = store
+            .commit(replica_a, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let second = store
+            .commit(replica_b, VectorClock::default(), root)
+            .await
+            .unwrap();
+
+        let event = all.next().await.unwrap().unwrap();
+        assert_eq!((event.replica_id, event.commit), (replica_a, first));
+        let event = all.next().await.unwrap().unwrap();
+        assert_eq!(event.commit, second.clone());
+        let event = only_b.next().await.unwrap().unwrap();
+        assert_eq!((event.replica_id, event.commit), (replica_b, second));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+
+use crate::{Bundle, BundleSelection, CommitId, ImportSummary, QuarkStore, ReplicaId, SyncClient};
+
+/// An exchange with the upstream which takes longer than this is abandoned, so an unreachable
+/// upstream does not block commits.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The store an offline-first [`crate::Replica`] synchronizes its local store with.
+pub enum Upstream {
+    /// A store the replica can access directly, e.g. a shared Scylla keyspace.
+    Store(Box<QuarkStore>),
+    /// The address of a sync server, see [`crate::serve`].
+    Server(String),
+}
+
+/// The outcome of synchronizing a local store with its upstream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpstreamSync {
+    pub pulled: ImportSummary,
+    pub pushed: ImportSummary,
+}
+
+/// Tracks the commits of an offline-first replica which have not reached the upstream yet.
+///
+/// The queue only lives in memory and is informational: a sync pushes every commit the upstream
+/// is missing, so commits restored from a snapshot are pushed even though the queue starts empty.
+pub(crate) struct OfflineState {
+    replica_id: ReplicaId,
+    upstream: Upstream,
+    remote_name: String,
+    pending: Vec<CommitId>,
+    /// Whether the last exchange with the upstream succeeded. Commits are only pushed right away
+    /// while online, otherwise they wait for the next [`OfflineState::sync`].
+    online: bool,
+}
+
+impl OfflineState {
+    pub(crate) fn new(
+        replica_id: ReplicaId,
+        upstream: Upstream,
+        remote_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            replica_id,
+            upstream,
+            remote_name: remote_name.into(),
+            pending: Vec::new(),
+            online: false,
+        }
+    }
+
+    pub(crate) fn pending(&self) -> &[CommitId] {
+        &self.pending
+    }
+
+    /// Queues the commit and pushes it if the upstream was reachable the last time.
+    pub(crate) async fn enqueue(&mut self, local: &QuarkStore, commit_id: CommitId) -> Result<()> {
+        self.pending.push(commit_id);
+        if !self.online {
+            return Ok(());
+        }
+        let result = self.exchange(local, false).await;
+        self.online = result.is_ok();
+        result.map(|_| ())
+    }
+
+    /// Pulls everything new from the upstream into the local store and pushes all local commits,
+    /// including the queued ones, to it.
+    pub(crate) async fn sync(&mut self, local: &QuarkStore) -> Result<UpstreamSync> {
+        let result = self.exchange(local, true).await;
+        self.online = result.is_ok();
+        result
+    }
+
+    async fn exchange(&mut self, local: &QuarkStore, pull: bool) -> Result<UpstreamSync> {
+        let remote_name = &self.remote_name;
+        let exchange = async {
+            let mut sync = UpstreamSync::default();
+            match &self.upstream {
+                Upstream::Store(store) => {
+                    if pull {
+                        sync.pulled = local.pull(remote_name, store).await?;
+                    }
+                    sync.pushed = local.push(remote_name, store).await?;
+                }
+                Upstream::Server(addr) => {
+                    let mut client = SyncClient::connect(addr.as_str()).await?;
+                    if pull {
+                        sync.pulled = client.pull(local, remote_name).await?;
+                    }
+                    sync.pushed = client.push(local, remote_name).await?;
+                }
+            }
+            anyhow::Ok(sync)
+        };
+        let sync = tokio::time::timeout(UPSTREAM_TIMEOUT, exchange)
+            .await
+            .with_context(|| "Timed out waiting for the upstream")??;
+        self.remove_accepted(local).await?;
+        Ok(sync)
+    }
+
+    /// Removes the queued commits which are part of the history the upstream accepted for the
+    /// replica. Commits which were quarantined or diverged stay queued.
+    async fn remove_accepted(&mut self, local: &QuarkStore) -> Result<()> {
+        let mut next = local
+            .remote_heads(&self.remote_name)
+            .await?
+            .into_iter()
+            .find(|(replica_id, _)| *replica_id == self.replica_id)
+            .map(|(_, commit_id)| commit_id);
+        while let Some(commit_id) = next {
+            let Some(index) = self.pending.iter().position(|id| *id == commit_id) else {
+                break;
+            };
+            self.pending.remove(index);
+            next = local
+                .find_commit(commit_id)
+                .await?
+                .and_then(|commit| commit.parent_commit_id);
+        }
+        Ok(())
+    }
+}
+
+impl QuarkStore {
+    /// Loads an in-memory store from a snapshot written by [`QuarkStore::save_snapshot`], or
+    /// creates an empty one if the snapshot does not exist yet.
+    ///
+    /// The store is not durable by itself: everything committed after the last
+    /// [`QuarkStore::save_snapshot`] is lost when the process exits, unless it has reached the
+    /// upstream before.
+    pub async fn load_snapshot(path: impl AsRef<Path>) -> Result<Self> {
+        let store = Self::memory();
+        if path.as_ref().exists() {
+            store.import_bundle(&Bundle::load(path)?).await?;
+        }
+        Ok(store)
+    }
+
+    /// Writes the complete history of the store to a file, so a local store survives restarts.
+    pub async fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.export_bundle(&BundleSelection::all())
+            .await?
+            .save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{serve, Id, RefStore, Replica, SignaturePolicy, VectorClock, VersionedStore};
+
+    #[tokio::test]
+    async fn test_offline_commits_are_pushed_later() {
+        let upstream = QuarkStore::test();
+        let base = upstream.insert(&vec![1u64]).await.unwrap();
+        upstream
+            .commit(Id::gen(), VectorClock::default(), base)
+            .await
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let replica_id = Id::gen();
+        let mut replica = tokio::select! {
+            result = serve(&upstream, listener) => panic!("Server stopped: {result:?}"),
+            replica = Replica::offline_first(
+                replica_id,
+                QuarkStore::test(),
+                Upstream::Server(addr.clone()),
+                "origin",
+            ) => replica.unwrap(),
+        };
+
+        // The server is not running anymore, so the commit is queued.
+        replica.commit_object(&vec![1u64, 2]).await.unwrap();
+        assert_eq!(replica.pending_commits().len(), 1);
+
+        let listener = TcpListener::bind(&addr).await.unwrap();
+        let sync = tokio::select! {
+            result = serve(&upstream, listener) => panic!("Server stopped: {result:?}"),
+            sync = replica.sync_upstream() => sync.unwrap(),
+        };
+        assert_eq!(sync.pushed.commits_imported, 1);
+        assert!(replica.pending_commits().is_empty());
+
+        let head = upstream
+            .latest_commit_for_replica(replica_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&head, replica.latest_commit());
+
+        let path = std::env::temp_dir().join(format!("mrdt-snapshot-{}", Id::gen()));
+        replica.store().save_snapshot(&path).await.unwrap();
+        let restored = QuarkStore::load_snapshot(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            restored
+                .latest_commit_for_replica(replica_id)
+                .await
+                .unwrap(),
+            Some(head)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quarantined_commits_stay_queued() {
+        let upstream = QuarkStore::test().with_signature_policy(SignaturePolicy::RequireSigned);
+        let base = upstream.insert(&vec![1u64]).await.unwrap();
+        upstream
+            .commit(Id::gen(), VectorClock::default(), base)
+            .await
+            .unwrap();
+
+        let mut replica = Replica::offline_first(
+            Id::gen(),
+            QuarkStore::test(),
+            Upstream::Store(Box::new(upstream)),
+            "origin",
+        )
+        .await
+        .unwrap();
+        replica.commit_object(&vec![1u64, 2]).await.unwrap();
+
+        let sync = replica.sync_upstream().await.unwrap();
+        assert_eq!(sync.pushed.quarantined_heads, vec![replica.id()]);
+        assert_eq!(replica.pending_commits().len(), 1);
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+
+use crate::{Commit, Deserialize, HashSet, Mergeable, MrdtItem, Replica, ReplicaId};
+
+/// The elements which have been added to and removed from an object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectDiff<I> {
+    pub added: Vec<I>,
+    pub removed: Vec<I>,
+}
+
+impl<I> ObjectDiff<I> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Objects which can be compared element by element.
+pub trait Diffable {
+    type Item;
+
+    fn diff(old: &Self, new: &Self) -> ObjectDiff<Self::Item>;
+}
+
+impl<T: MrdtItem> Diffable for HashSet<T> {
+    type Item = T;
+
+    fn diff(old: &Self, new: &Self) -> ObjectDiff<T> {
+        ObjectDiff {
+            added: new.difference(old).cloned().collect(),
+            removed: old.difference(new).cloned().collect(),
+        }
+    }
+}
+
+impl<T: MrdtItem> Diffable for Vec<T> {
+    type Item = T;
+
+    fn diff(old: &Self, new: &Self) -> ObjectDiff<T> {
+        let (old_items, new_items) = (
+            old.iter().collect::<HashSet<_>>(),
+            new.iter().collect::<HashSet<_>>(),
+        );
+        ObjectDiff {
+            added: new
+                .iter()
+                .filter(|item| !old_items.contains(item))
+                .cloned()
+                .collect(),
+            removed: old
+                .iter()
+                .filter(|item| !new_items.contains(item))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// The inputs and the result of a three-way merge, see [`Replica::preview_merge`].
+#[derive(Debug, Clone)]
+pub struct MergePreview<T> {
+    /// The commit which is used as the common ancestor of both sides, or `None` if the common
+    /// ancestor is the merge of several concurrent commits.
+    pub lca_commit: Option<Commit>,
+    /// The commit which is merged into the current state.
+    pub other_commit: Commit,
+    pub lca: T,
+    pub current: T,
+    pub other: T,
+    pub merged: T,
+}
+
+impl<T: Diffable> MergePreview<T> {
+    /// Returns the changes the merge makes to the current state of the replica.
+    pub fn diff(&self) -> ObjectDiff<T::Item> {
+        T::diff(&self.current, &self.merged)
+    }
+}
+
+impl Replica {
+    /// Computes the result of merging with the other replica like [`Replica::merge_with`], but
+    /// neither stores the merged object nor commits it.
+    pub async fn preview_merge<T: Deserialize + Mergeable>(
+        &self,
+        other_replica: ReplicaId,
+    ) -> Result<MergePreview<T>> {
+        let commit_to_merge_with = self.head_to_merge_with(other_replica).await?;
+        self.merge_objects(commit_to_merge_with).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, QuarkStore, RefStore, VectorClock, VersionedStore};
+
+    #[tokio::test]
+    async fn test_preview_merge_does_not_commit() {
+        let store = QuarkStore::test();
+        let (replica_a, replica_b) = (Id::gen(), Id::gen());
+        let root = store.insert(&HashSet::from_iter([1u64, 2])).await.unwrap();
+        store
+            .commit(replica_a, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut replica = Replica::clone(replica_a, store).await.unwrap();
+
+        let mut version = VectorClock::default();
+        version.inc(replica_b);
+        let other_root = replica
+            .store()
+            .insert(&HashSet::from_iter([1u64, 3]))
+            .await
+            .unwrap();
+        replica
+            .store()
+            .commit(replica_b, version, other_root)
+            .await
+            .unwrap();
+        let head = replica
+            .commit_object(&HashSet::from_iter([1u64, 2, 4]))
+            .await
+            .unwrap();
+
+        let preview = replica
+            .preview_merge::<HashSet<u64>>(replica_b)
+            .await
+            .unwrap();
+        assert_eq!(preview.merged, HashSet::from_iter([1, 3, 4]));
+        let diff = preview.diff();
+        assert_eq!((diff.added, diff.removed), (vec![3], vec![2]));
+        assert_eq!(replica.latest_commit(), &head);
+
+        let (_, merged) = replica.merge_with::<HashSet<u64>>(replica_b).await.unwrap();
+        assert_eq!(merged, preview.merged);
+    }
+}
@@ -1,26 +1,36 @@
 use anyhow::{anyhow, Context, Result};
+use futures::TryStreamExt;
 use log::log_enabled;
 use musli::{
     de::DecodeOwned,
     mode::Binary,
     storage::{Encoding, OPTIONS},
-    Encode,
+    Decode, Encode,
 };
 use scylla::{
-    batch::Batch, prepared_statement::PreparedStatement, query::Query,
-    serialize::row::SerializeRow, QueryResult, Session, SessionBuilder,
+    batch::Batch, frame::response::result::Row, prepared_statement::PreparedStatement,
+    query::Query, serialize::row::SerializeRow, statement::Consistency, QueryResult, Session,
+    SessionBuilder,
 };
 use std::hash::{Hash, Hasher};
-use std::time::Instant;
-
-#[cfg(test)]
-use std::{cell::RefCell, collections::HashMap};
-
-use crate::{HashSet, Id, MrdtItem, ReplicaId, VectorClock};
+use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+};
+use tokio::sync::broadcast;
+
+use crate::schema::{self, migrate, SCHEMA_TABLE_NAME};
+use crate::{
+    notify::NOTIFICATION_CAPACITY, CacheConfig, CacheStats, CommitEvent, CommitMetadata,
+    CommitOptions, CommitSignature, HashSet, HybridTimestamp, Id, ItemName, Membership, MrdtItem,
+    ObjectCache, ObjectMigrations, QuarkStoreConfig, ReplicaId, SignaturePolicy, SigningKey,
+    Tagged, TypeTag, VectorClock,
+};
 
-const ENCODING: Encoding<OPTIONS> = Encoding::new().with_options();
+pub(crate) const ENCODING: Encoding<OPTIONS> = Encoding::new().with_options();
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
 pub struct Ref {
     pub id: u64,
     pub left: Option<u64>,
@@ -49,26 +59,197 @@ impl Ref {
     }
 }
 
+/// Computes the content hash under which an object is stored in the object table.
+pub(crate) fn object_hash<T: Hash + ?Sized>(object: &T) -> u64 {
+    let mut hasher = std::hash::DefaultHasher::new();
+    object.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The replication strategy used when the keyspace of a [`ScyllaSession`] is created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replication {
+    Simple {
+        replication_factor: u32,
+    },
+    /// Uses the same replication factor in every datacenter.
+    NetworkTopology {
+        replication_factor: u32,
+    },
+    /// Uses an individual replication factor per datacenter.
+    Datacenters(Vec<(String, u32)>),
+}
+
+impl Replication {
+    fn to_cql(&self) -> String {
+        match self {
+            Replication::Simple { replication_factor } => format!(
+                "{{'class' : 'SimpleStrategy', 'replication_factor' : {replication_factor}}}"
+            ),
+            Replication::NetworkTopology { replication_factor } => format!(
+                "{{'class' : 'NetworkTopologyStrategy', 'replication_factor' : {replication_factor}}}"
+            ),
+            Replication::Datacenters(datacenters) => {
+                let mut cql = "{'class' : 'NetworkTopologyStrategy'".to_string();
+                for (datacenter, replication_factor) in datacenters {
+                    cql.push_str(&format!(", '{datacenter}' : {replication_factor}"));
+                }
+                cql.push('}');
+                cql
+            }
+        }
+    }
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication::NetworkTopology {
+            replication_factor: 1,
+        }
+    }
+}
+
+/// Options of a [`ScyllaSession`], which are applied to the keyspace and all prepared statements.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub consistency: Consistency,
+    pub replication: Replication,
+    pub page_size: i32,
+    /// Timeout of a single request, uses the default of the driver if `None`.
+    pub request_timeout: Option<Duration>,
+    /// Timeout for establishing connections, uses the default of the driver if `None`.
+    pub connection_timeout: Option<Duration>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            consistency: Consistency::LocalQuorum,
+            replication: Replication::default(),
+            page_size: 5000,
+            request_timeout: None,
+            connection_timeout: None,
+        }
+    }
+}
+
+/// All statements used by the [`QuarkStore`], prepared once when the store is set up.
+pub struct Statements {
+    select_first_commit_id: PreparedStatement,
+    select_commits: PreparedStatement,
+    select_commit: PreparedStatement,
+    select_commit_for_version: PreparedStatement,
+    insert_commit: PreparedStatement,
+    select_root_types: PreparedStatement,
+    insert_root_type: PreparedStatement,
+    select_replica_key: PreparedStatement,
+    insert_replica_key: PreparedStatement,
+    select_replica_commit_id: PreparedStatement,
+    select_replicas: PreparedStatement,
+    insert_replica: PreparedStatement,
+    select_replica_type_tag: PreparedStatement,
+    update_replica_type_tag: PreparedStatement,
+    select_replica_memberships: PreparedStatement,
+    update_replica_name: PreparedStatement,
+    insert_replica_alias: PreparedStatement,
+    update_replica_retired: PreparedStatement,
+    select_ref: PreparedStatement,
+    select_refs: PreparedStatement,
+    insert_ref: PreparedStatement,
+    select_object: PreparedStatement,
+    select_objects: PreparedStatement,
+    insert_object: PreparedStatement,
+    select_remote_heads: PreparedStatement,
+    insert_remote_head: PreparedStatement,
+    select_namespace_remote_heads: PreparedStatement,
+    select_branches: PreparedStatement,
+    insert_branch: PreparedStatement,
+    delete_branch: PreparedStatement,
+    select_namespace_branches: PreparedStatement,
+    select_tags: PreparedStatement,
+    select_tag: PreparedStatement,
+    insert_tag: PreparedStatement,
+    select_namespaces: PreparedStatement,
+    select_namespace_commits: PreparedStatement,
+    select_first_namespace_commit_id: PreparedStatement,
+    select_namespace_commit: PreparedStatement,
+    select_namespace_commit_for_version: PreparedStatement,
+    insert_namespace_commit: PreparedStatement,
+    delete_namespace_commit: PreparedStatement,
+    select_namespace_replica_commit_id: PreparedStatement,
+    select_namespace_replicas: PreparedStatement,
+    insert_namespace_replica: PreparedStatement,
+    delete_commit: PreparedStatement,
+    delete_ref: PreparedStatement,
+    delete_object: PreparedStatement,
+}
+
 pub struct ScyllaSession {
     session: Session,
     keyspace: String,
+    table_prefix: String,
+    config: SessionConfig,
+    statements: Option<Box<Statements>>,
+    cache: Option<ObjectCache>,
+    migrations: ObjectMigrations,
+    signature_policy: SignaturePolicy,
+    partial_documents: Option<BTreeSet<String>>,
+    namespace: Option<String>,
 }
 
 impl ScyllaSession {
     pub async fn new(hostname: impl Into<String>, keyspace: impl Into<String>) -> Result<Self> {
-        let hostname = hostname.into();
-        let keyspace = keyspace.into();
+        Self::connect(&QuarkStoreConfig::new(hostname, keyspace)).await
+    }
 
-        let session: Session = SessionBuilder::new().known_node(hostname).build().await?;
-        session.query(format!("CREATE KEYSPACE IF NOT EXISTS {keyspace} WITH REPLICATION = {{'class' : 'NetworkTopologyStrategy', 'replication_factor' : 1}}"), ()).await?;
+    /// Connects to the cluster and creates the keyspace if it does not exist yet.
+    pub async fn connect(store_config: &QuarkStoreConfig) -> Result<Self> {
+        let keyspace = store_config.keyspace.clone();
+        let config = store_config.session.clone();
 
-        Ok(Self { session, keyspace })
+        let mut builder = SessionBuilder::new().known_nodes(&store_config.nodes);
+        if let Some(credentials) = &store_config.credentials {
+            builder = builder.user(&credentials.username, &credentials.password);
+        }
+        if let Some(pool_size) = store_config.pool_size {
+            builder = builder.pool_size(pool_size);
+        }
+        if let Some(timeout) = config.connection_timeout {
+            builder = builder.connection_timeout(timeout);
+        }
+        let session: Session = builder.build().await?;
+        let replication = config.replication.to_cql();
+        session
+            .query(
+                format!(
+                    "CREATE KEYSPACE IF NOT EXISTS {keyspace} WITH REPLICATION = {replication}"
+                ),
+                (),
+            )
+            .await?;
+
+        Ok(Self {
+            session,
+            keyspace,
+            table_prefix: store_config.table_prefix.clone(),
+            config,
+            statements: None,
+            cache: store_config.cache.map(ObjectCache::new),
+            migrations: ObjectMigrations::default(),
+            signature_policy: SignaturePolicy::default(),
+            partial_documents: None,
+            namespace: store_config.namespace.clone(),
+        })
     }
 
     pub fn raw(&self) -> &Session {
         &self.session
     }
 
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
     pub async fn query(
         &self,
         query: impl Into<Query>,
@@ -80,30 +261,396 @@ impl ScyllaSession {
             .with_context(|| "Failed to execute query")
     }
 
+    pub async fn execute(
+        &self,
+        statement: &PreparedStatement,
+        values: impl SerializeRow,
+    ) -> Result<QueryResult> {
+        self.session
+            .execute(statement, values)
+            .await
+            .with_context(|| "Failed to execute statement")
+    }
+
+    /// Executes the statement and fetches all pages of its result, as the prepared statements
+    /// only return the first page when they are executed directly.
+    pub async fn execute_all(
+        &self,
+        statement: &PreparedStatement,
+        values: impl SerializeRow,
+    ) -> Result<Vec<Row>> {
+        self.session
+            .execute_iter(statement.clone(), values)
+            .await
+            .with_context(|| "Failed to execute statement")?
+            .try_collect()
+            .await
+            .with_context(|| "Failed to fetch rows")
+    }
+
+    /// Executes the given statement for each of the values in a single batch.
+    pub async fn execute_batch(
+        &self,
+        statement: &PreparedStatement,
+        values: Vec<impl SerializeRow>,
+    ) -> Result<()> {
+        let mut batch = Batch::default();
+        batch.set_consistency(self.config.consistency);
+        for _ in 0..values.len() {
+            batch.append_statement(statement.clone());
+        }
+        self.session
+            .batch(&batch, values)
+            .await
+            .with_context(|| "Failed to execute batch")?;
+        Ok(())
+    }
+
     pub fn table_name(&self, name: &str) -> String {
-        format!("{}.{}", self.keyspace, name)
+        format!("{}.{}{}", self.keyspace, self.table_prefix, name)
+    }
+
+    /// Returns the keyspace and the name of the table as they are stored in `system_schema`,
+    /// which keeps unquoted identifiers in lower case.
+    pub(crate) fn schema_table_name(&self, name: &str) -> (String, String) {
+        (
+            self.keyspace.to_lowercase(),
+            format!("{}{}", self.table_prefix, name).to_lowercase(),
+        )
+    }
+
+    pub fn cache(&self) -> Option<&ObjectCache> {
+        self.cache.as_ref()
+    }
+
+    pub(crate) fn set_migrations(&mut self, migrations: ObjectMigrations) {
+        self.migrations = migrations;
+    }
+
+    pub(crate) fn signature_policy(&self) -> SignaturePolicy {
+        self.signature_policy
+    }
+
+    pub(crate) fn set_signature_policy(&mut self, policy: SignaturePolicy) {
+        self.signature_policy = policy;
+    }
+
+    pub(crate) fn partial_documents(&self) -> Option<&BTreeSet<String>> {
+        self.partial_documents.as_ref()
+    }
+
+    pub(crate) fn set_partial_documents(&mut self, keys: BTreeSet<String>) {
+        self.partial_documents = Some(keys);
+    }
+
+    pub(crate) fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    pub(crate) fn set_namespace(&mut self, namespace: String) {
+        self.namespace = Some(namespace);
+    }
+
+    /// Returns the prepared statements, which are available once the tables have been set up.
+    pub fn statements(&self) -> Result<&Statements> {
+        self.statements
+            .as_deref()
+            .with_context(|| "Statements have not been prepared")
+    }
+
+    async fn prepare_statements(&mut self) -> Result<()> {
+        let commit_table = self.table_name(COMMIT_TABLE_NAME);
+        let replica_table = self.table_name(REPLICA_TABLE_NAME);
+        let ref_table = self.table_name(REF_TABLE_NAME);
+        let object_table = self.table_name(OBJECT_TABLE_NAME);
+        let remote_head_table = self.table_name(REMOTE_HEAD_TABLE_NAME);
+        let root_type_table = self.table_name(ROOT_TYPE_TABLE_NAME);
+        let replica_key_table = self.table_name(REPLICA_KEY_TABLE_NAME);
+        let replica_type_table = self.table_name(REPLICA_TYPE_TABLE_NAME);
+        let replica_membership_table = self.table_name(REPLICA_MEMBERSHIP_TABLE_NAME);
+        let branch_table = self.table_name(BRANCH_TABLE_NAME);
+        let tag_table = self.table_name(TAG_TABLE_NAME);
+        let namespace_commit_table = self.table_name(NAMESPACE_COMMIT_TABLE_NAME);
+        let namespace_replica_table = self.table_name(NAMESPACE_REPLICA_TABLE_NAME);
+
+        self.statements = Some(Box::new(Statements {
+            select_first_commit_id: self
+                .prepare(format!("SELECT id FROM {commit_table} LIMIT 1"))
+                .await?,
+            select_commits: self
+                .prepare(format!("SELECT {COMMIT_COLUMNS} FROM {commit_table}"))
+                .await?,
+            select_commit: self
+                .prepare(format!(
+                    "SELECT {COMMIT_COLUMNS} FROM {commit_table} WHERE id = ?"
+                ))
+                .await?,
+            select_commit_for_version: self
+                .prepare(format!(
+                    "SELECT {COMMIT_COLUMNS} FROM {commit_table} WHERE version = ? ALLOW FILTERING"
+                ))
+                .await?,
+            insert_commit: self
+                .prepare(format!(
+                    "INSERT INTO {commit_table} ({COMMIT_COLUMNS}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+                ))
+                .await?,
+            select_root_types: self
+                .prepare(format!(
+                    "SELECT type_name, schema_fingerprint FROM {root_type_table} WHERE root_ref = ?"
+                ))
+                .await?,
+            insert_root_type: self
+                .prepare(format!(
+                    "INSERT INTO {root_type_table} (root_ref, type_name, schema_fingerprint) VALUES (?, ?, ?)"
+                ))
+                .await?,
+            select_replica_key: self
+                .prepare(format!(
+                    "SELECT public_key FROM {replica_key_table} WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            insert_replica_key: self
+                .prepare(format!(
+                    "INSERT INTO {replica_key_table} (namespace, replica_id, public_key) VALUES (?, ?, ?) IF NOT EXISTS"
+                ))
+                .await?,
+            select_replica_commit_id: self
+                .prepare(format!("SELECT commit_id FROM {replica_table} WHERE id = ?"))
+                .await?,
+            select_replicas: self
+                .prepare(format!("SELECT id, commit_id FROM {replica_table}"))
+                .await?,
+            insert_replica: self
+                .prepare(format!(
+                    "INSERT INTO {replica_table} (id, commit_id) VALUES (?, ?)"
+                ))
+                .await?,
+            select_replica_type_tag: self
+                .prepare(format!(
+                    "SELECT type_name, schema_fingerprint FROM {replica_type_table} WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            update_replica_type_tag: self
+                .prepare(format!(
+                    "UPDATE {replica_type_table} SET type_name = ?, schema_fingerprint = ? WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            select_replica_memberships: self
+                .prepare(format!(
+                    "SELECT replica_id, name, aliases, retired FROM {replica_membership_table} WHERE namespace = ?"
+                ))
+                .await?,
+            update_replica_name: self
+                .prepare(format!(
+                    "UPDATE {replica_membership_table} SET name = ? WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            insert_replica_alias: self
+                .prepare(format!(
+                    "UPDATE {replica_membership_table} SET aliases = aliases + ? WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            update_replica_retired: self
+                .prepare(format!(
+                    "UPDATE {replica_membership_table} SET retired = ? WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            select_ref: self
+                .prepare(format!(
+                    "SELECT left, right, object_ref FROM {ref_table} WHERE id = ?"
+                ))
+                .await?,
+            select_refs: self
+                .prepare(format!("SELECT id, object_ref FROM {ref_table}"))
+                .await?,
+            insert_ref: self
+                .prepare(format!(
+                    "INSERT INTO {ref_table} (id, left, right, object_ref) VALUES (?, ?, ?, ?)"
+                ))
+                .await?,
+            select_object: self
+                .prepare(format!("SELECT object FROM {object_table} WHERE id = ?"))
+                .await?,
+            select_objects: self
+                .prepare(format!(
+                    "SELECT id, object FROM {object_table} WHERE id IN ?"
+                ))
+                .await?,
+            insert_object: self
+                .prepare(format!(
+                    "INSERT INTO {object_table} (id, object) VALUES (?, ?)"
+                ))
+                .await?,
+            select_remote_heads: self
+                .prepare(format!(
+                    "SELECT replica_id, commit_id FROM {remote_head_table} WHERE namespace = ? AND remote = ?"
+                ))
+                .await?,
+            insert_remote_head: self
+                .prepare(format!(
+                    "INSERT INTO {remote_head_table} (namespace, remote, replica_id, commit_id) VALUES (?, ?, ?, ?)"
+                ))
+                .await?,
+            select_namespace_remote_heads: self
+                .prepare(format!(
+                    "SELECT commit_id FROM {remote_head_table} WHERE namespace = ?"
+                ))
+                .await?,
+            select_branches: self
+                .prepare(format!(
+                    "SELECT name, commit_id FROM {branch_table} WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            insert_branch: self
+                .prepare(format!(
+                    "INSERT INTO {branch_table} (namespace, replica_id, name, commit_id) VALUES (?, ?, ?, ?)"
+                ))
+                .await?,
+            delete_branch: self
+                .prepare(format!(
+                    "DELETE FROM {branch_table} WHERE namespace = ? AND replica_id = ? AND name = ?"
+                ))
+                .await?,
+            select_namespace_branches: self
+                .prepare(format!("SELECT commit_id FROM {branch_table} WHERE namespace = ?"))
+                .await?,
+            select_tags: self
+                .prepare(format!(
+                    "SELECT name, commit_id FROM {tag_table} WHERE namespace = ?"
+                ))
+                .await?,
+            select_tag: self
+                .prepare(format!(
+                    "SELECT commit_id FROM {tag_table} WHERE namespace = ? AND name = ?"
+                ))
+                .await?,
+            insert_tag: self
+                .prepare(format!(
+                    "INSERT INTO {tag_table} (namespace, name, commit_id) VALUES (?, ?, ?) IF NOT EXISTS"
+                ))
+                .await?,
+            select_namespaces: self
+                .prepare(format!(
+                    "SELECT DISTINCT namespace FROM {namespace_commit_table}"
+                ))
+                .await?,
+            select_namespace_commits: self
+                .prepare(format!(
+                    "SELECT {COMMIT_COLUMNS} FROM {namespace_commit_table} WHERE namespace = ?"
+                ))
+                .await?,
+            select_first_namespace_commit_id: self
+                .prepare(format!(
+                    "SELECT id FROM {namespace_commit_table} WHERE namespace = ? LIMIT 1"
+                ))
+                .await?,
+            select_namespace_commit: self
+                .prepare(format!(
+                    "SELECT {COMMIT_COLUMNS} FROM {namespace_commit_table} WHERE namespace = ? AND id = ?"
+                ))
+                .await?,
+            select_namespace_commit_for_version: self
+                .prepare(format!(
+                    "SELECT {COMMIT_COLUMNS} FROM {namespace_commit_table} WHERE namespace = ? AND version = ? ALLOW FILTERING"
+                ))
+                .await?,
+            insert_namespace_commit: self
+                .prepare(format!(
+                    "INSERT INTO {namespace_commit_table} (namespace, {COMMIT_COLUMNS}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+                ))
+                .await?,
+            delete_namespace_commit: self
+                .prepare(format!(
+                    "DELETE FROM {namespace_commit_table} WHERE namespace = ? AND id = ?"
+                ))
+                .await?,
+            select_namespace_replica_commit_id: self
+                .prepare(format!(
+                    "SELECT commit_id FROM {namespace_replica_table} WHERE namespace = ? AND replica_id = ?"
+                ))
+                .await?,
+            select_namespace_replicas: self
+                .prepare(format!(
+                    "SELECT replica_id, commit_id FROM {namespace_replica_table} WHERE namespace = ?"
+                ))
+                .await?,
+            insert_namespace_replica: self
+                .prepare(format!(
+                    "INSERT INTO {namespace_replica_table} (namespace, replica_id, commit_id) VALUES (?, ?, ?)"
+                ))
+                .await?,
+            delete_commit: self
+                .prepare(format!("DELETE FROM {commit_table} WHERE id = ?"))
+                .await?,
+            delete_ref: self
+                .prepare(format!("DELETE FROM {ref_table} WHERE id = ?"))
+                .await?,
+            delete_object: self
+                .prepare(format!("DELETE FROM {object_table} WHERE id = ?"))
+                .await?,
+        }));
+        Ok(())
+    }
+
+    async fn prepare(&self, query: String) -> Result<PreparedStatement> {
+        let mut statement = self
+            .session
+            .prepare(query)
+            .await
+            .with_context(|| "Failed to prepare statement")?;
+        statement.set_consistency(self.config.consistency);
+        statement.set_page_size(self.config.page_size);
+        statement.set_request_timeout(self.config.request_timeout);
+        Ok(statement)
     }
 }
 
+// The in-memory tables are only created once per store, so their size does not matter.
+#[allow(clippy::large_enum_variant)]
 pub enum QuarkStore {
     Scylla(ScyllaSession),
-    #[cfg(test)]
-    Fake {
-        commits: RefCell<HashMap<Id, Commit>>,
-        objects: RefCell<HashMap<u64, Vec<u8>>>,
-        refs: RefCell<HashMap<u64, Ref>>,
-        replicas: RefCell<HashMap<ReplicaId, CommitId>>,
+    /// Keeps all tables in memory, which is useful for tests and short-lived local replicas.
+    Memory {
+        commits: Mutex<HashMap<Id, Commit>>,
+        objects: Mutex<HashMap<u64, Vec<u8>>>,
+        refs: Mutex<HashMap<u64, Ref>>,
+        replicas: Mutex<HashMap<ReplicaId, CommitId>>,
+        /// The type tags, memberships, keys, remote heads and branches by namespace, see
+        /// [`QuarkStore::namespace_key`].
+        replica_types: Mutex<HashMap<(String, ReplicaId), TypeTag>>,
+        memberships: Mutex<HashMap<(String, ReplicaId), Membership>>,
+        root_types: Mutex<HashMap<u64, Vec<TypeTag>>>,
+        replica_keys: Mutex<HashMap<(String, ReplicaId), [u8; 32]>>,
+        remote_heads: Mutex<HashMap<(String, String, ReplicaId), CommitId>>,
+        branches: Mutex<HashMap<(String, ReplicaId, String), CommitId>>,
+        /// The tags by namespace and name.
+        tags: Mutex<HashMap<(String, String), CommitId>>,
+        /// The commits and heads of stores with a namespace, partitioned by namespace.
+        namespace_commits: Mutex<HashMap<String, HashMap<CommitId, Commit>>>,
+        namespace_replicas: Mutex<HashMap<String, HashMap<ReplicaId, CommitId>>>,
+        notifier: broadcast::Sender<CommitEvent>,
+        migrations: ObjectMigrations,
+        signature_policy: SignaturePolicy,
+        partial_documents: Option<BTreeSet<String>>,
+        namespace: Option<String>,
     },
 }
 
 pub type CommitId = Id;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
 pub struct Commit {
     pub id: CommitId,
     pub version: VectorClock,
     pub root_ref: u64,
     pub parent_commit_id: Option<Id>,
+    /// The heads of other replicas which were merged into this commit in addition to its parent,
+    /// see [`Replica::merge_all`].
+    pub merge_parent_ids: Vec<CommitId>,
+    pub type_tag: Option<TypeTag>,
+    pub metadata: CommitMetadata,
+    pub signature: Option<CommitSignature>,
 }
 
 pub type ObjectRef = u64;
@@ -116,6 +663,20 @@ pub trait VersionedStore {
         replica_id: ReplicaId,
         version: VectorClock,
         root_ref: u64,
+    ) -> Result<Commit> {
+        self.commit_with(replica_id, version, root_ref, CommitOptions::default())
+            .await
+    }
+
+    /// Like [`VersionedStore::commit`], but records the heads which have been merged, the type
+    /// of the object behind `root_ref` and the metadata of the commit, and signs the commit if a
+    /// key is given.
+    async fn commit_with(
+        &self,
+        replica_id: ReplicaId,
+        version: VectorClock,
+        root_ref: u64,
+        options: CommitOptions<'_>,
     ) -> Result<Commit>;
 
     async fn latest_commit_for_replica(&self, replica_id: ReplicaId) -> Result<Option<Commit>>;
@@ -147,12 +708,12 @@ pub trait RefStore {
 }
 
 #[allow(async_fn_in_trait)]
-pub trait Serialize {
+pub trait Serialize: Tagged {
     async fn serialize(&self, cx: SerializeCx) -> Result<Vec<Ref>>;
 }
 
 #[allow(async_fn_in_trait)]
-pub trait Deserialize: Sized {
+pub trait Deserialize: Sized + Tagged {
     async fn deserialize(root: Ref, cx: DeserializeCx) -> Result<Self>;
 }
 
@@ -163,11 +724,20 @@ impl VersionedStore for QuarkStore {
         log::debug!("Cloning replica {replica_id}");
         match self {
             QuarkStore::Scylla(session) => {
-                let commit_table = session.table_name(COMMIT_TABLE_NAME);
-
-                let commit_id = session
-                    .query(format!("SELECT id FROM {commit_table}"), &[])
-                    .await?
+                let statements = session.statements()?;
+                let result = match session.namespace() {
+                    Some(namespace) => {
+                        session
+                            .execute(&statements.select_first_namespace_commit_id, (namespace,))
+                            .await?
+                    }
+                    None => {
+                        session
+                            .execute(&statements.select_first_commit_id, ())
+                            .await?
+                    }
+                };
+                let commit_id = result
                     .rows_or_empty()
                     .first()
                     .and_then(|row| {
@@ -182,80 +752,94 @@ impl VersionedStore for QuarkStore {
                 update_current_commit_id_for_replica(session, replica_id, commit_id).await?;
                 Ok(commit)
             }
-            #[cfg(test)]
-            QuarkStore::Fake {
-                replicas, commits, ..
+            QuarkStore::Memory {
+                replicas,
+                commits,
+                namespace_commits,
+                namespace_replicas,
+                namespace,
+                ..
             } => {
-                let commits = commits.borrow();
-                let commit = commits
-                    .values()
-                    .next()
-                    .with_context(|| "No commits available")?;
-
-                let mut replicas = replicas.borrow_mut();
-                replicas.insert(replica_id, commit.id);
-
-                Ok(commit.clone())
+                let mut commits = commits.lock().unwrap();
+                let mut namespace_commits = namespace_commits.lock().unwrap();
+                let commit =
+                    memory_partition(&mut commits, &mut namespace_commits, namespace.as_deref())
+                        .values()
+                        .next()
+                        .cloned()
+                        .with_context(|| "No commits available")?;
+
+                let mut replicas = replicas.lock().unwrap();
+                let mut namespace_replicas = namespace_replicas.lock().unwrap();
+                memory_partition(&mut replicas, &mut namespace_replicas, namespace.as_deref())
+                    .insert(replica_id, commit.id);
+                Ok(commit)
             }
         }
     }
 
-    async fn commit(
+    async fn commit_with(
         &self,
         replica_id: ReplicaId,
         version: VectorClock,
         root_ref: u64,
+        options: CommitOptions<'_>,
     ) -> Result<Commit> {
         log::debug!("Replica {replica_id} adding new commit. Ref: {root_ref}, Version: {version}");
         match self {
             QuarkStore::Scylla(session) => {
-                let commit_table = session.table_name(COMMIT_TABLE_NAME);
-                let commit_id = Id::gen();
-                let prev_commit_id = current_commit_id_for_replica(session, replica_id).await?;
-                update_current_commit_id_for_replica(session, replica_id, commit_id).await?;
-
-                let mut version_bytes = Vec::new();
-                ENCODING
-                    .encode(&mut version_bytes, &version)
-                    .with_context(|| "Failed to serialize version")?;
-
-                session.query(
-                    format!("INSERT INTO {commit_table} (id, version, root_ref, prev_commit_id) VALUES (?, ?, ?, ?)"),
-                    (
-                        commit_id.as_str(),
-                        &version_bytes,
-                        root_ref as i64,
-                        prev_commit_id.map(|id| id.to_string()),
-                    ),
-                )
-                .await?;
-
-                Ok(Commit {
-                    id: commit_id,
+                let commit = Commit {
+                    id: Id::gen(),
                     version,
                     root_ref,
-                    parent_commit_id: prev_commit_id,
-                })
+                    parent_commit_id: current_commit_id_for_replica(session, replica_id).await?,
+                    merge_parent_ids: options.merge_parent_ids,
+                    type_tag: options.type_tag,
+                    metadata: options.metadata,
+                    signature: None,
+                };
+                let commit = sign_commit(commit, replica_id, options.signing_key)?;
+                self.insert_commit(&commit).await?;
+                update_current_commit_id_for_replica(session, replica_id, commit.id).await?;
+                Ok(commit)
             }
-            #[cfg(test)]
-            QuarkStore::Fake {
-                commits, replicas, ..
+            QuarkStore::Memory {
+                replicas,
+                namespace_replicas,
+                namespace,
+                notifier,
+                ..
             } => {
-                let commit_id = Id::gen();
-                let mut replicas = replicas.borrow_mut();
-                let prev_commit_id = *replicas
-                    .get(&replica_id)
-                    .with_context(|| "No previous commit available")?;
-                replicas.insert(replica_id, commit_id);
-
-                let mut commits = commits.borrow_mut();
+                let parent_commit_id = {
+                    let mut replicas = replicas.lock().unwrap();
+                    let mut namespace_replicas = namespace_replicas.lock().unwrap();
+                    memory_partition(&mut replicas, &mut namespace_replicas, namespace.as_deref())
+                        .get(&replica_id)
+                        .copied()
+                };
                 let commit = Commit {
-                    id: commit_id,
+                    id: Id::gen(),
                     version,
                     root_ref,
-                    parent_commit_id: Some(prev_commit_id),
+                    parent_commit_id,
+                    merge_parent_ids: options.merge_parent_ids,
+                    type_tag: options.type_tag,
+                    metadata: options.metadata,
+                    signature: None,
                 };
-                commits.insert(commit_id, commit.clone());
+                let commit = sign_commit(commit, replica_id, options.signing_key)?;
+                self.insert_commit(&commit).await?;
+                {
+                    let mut replicas = replicas.lock().unwrap();
+                    let mut namespace_replicas = namespace_replicas.lock().unwrap();
+                    memory_partition(&mut replicas, &mut namespace_replicas, namespace.as_deref())
+                        .insert(replica_id, commit.id);
+                }
+
+                let _ = notifier.send(CommitEvent {
+                    replica_id,
+                    commit: commit.clone(),
+                });
                 Ok(commit)
             }
         }
@@ -269,111 +853,69 @@ impl VersionedStore for QuarkStore {
                     None => Ok(None),
                 }
             }
-            #[cfg(test)]
-            QuarkStore::Fake {
-                replicas, commits, ..
+            QuarkStore::Memory {
+                replicas,
+                namespace_replicas,
+                namespace,
+                ..
             } => {
-                let replicas = replicas.borrow();
-                let Some(commit_id) = replicas.get(&replica_id) else {
-                    return Ok(None);
+                let commit_id = {
+                    let mut replicas = replicas.lock().unwrap();
+                    let mut namespace_replicas = namespace_replicas.lock().unwrap();
+                    memory_partition(&mut replicas, &mut namespace_replicas, namespace.as_deref())
+                        .get(&replica_id)
+                        .copied()
                 };
-                Ok(commits.borrow().get(commit_id).cloned())
+                match commit_id {
+                    Some(commit_id) => self.find_commit(commit_id).await,
+                    None => Ok(None),
+                }
             }
         }
     }
 
     async fn resolve_commit(&self, commit_id: CommitId) -> Result<Commit> {
-        match self {
-            QuarkStore::Scylla(session) => {
-                let commit_table = session.table_name(COMMIT_TABLE_NAME);
-                let result = session
-                    .query(
-                        format!(
-                            "SELECT version, root_ref, prev_commit_id FROM {commit_table} WHERE id = ?"
-                        ),
-                        (commit_id.as_str(),),
-                    )
-                    .await?
-                    .single_row()
-                    .with_context(|| "Failed to select single row")?;
-
-                let version_blob = result.columns[0]
-                    .as_ref()
-                    .and_then(|value| value.clone().into_blob())
-                    .with_context(|| "Failed to deserialize value")?;
-
-                let root_ref = result.columns[1]
-                    .as_ref()
-                    .and_then(|value| value.clone().as_bigint())
-                    .with_context(|| "Failed to deserialize root ref")?
-                    as u64;
-
-                let prev_commit_id = result.columns[2]
-                    .as_ref()
-                    .and_then(|value| value.clone().into_string());
-
-                Ok(Commit {
-                    id: commit_id,
-                    version: ENCODING.decode(version_blob.as_slice())?,
-                    root_ref,
-                    parent_commit_id: prev_commit_id.and_then(|id| Id::try_from(id).ok()),
-                })
-            }
-            #[cfg(test)]
-            QuarkStore::Fake { commits, .. } => {
-                let commits = commits.borrow();
-                commits
-                    .get(&commit_id)
-                    .with_context(|| "Commit not found")
-                    .cloned()
-            }
-        }
+        self.find_commit(commit_id)
+            .await?
+            .with_context(|| "Commit not found")
     }
 
     async fn resolve_commit_for_version(&self, version: VectorClock) -> Result<Commit> {
         match self {
             QuarkStore::Scylla(session) => {
-                let commit_table = session.table_name(COMMIT_TABLE_NAME);
-
                 let mut version_bytes = Vec::new();
                 ENCODING.encode(&mut version_bytes, &version)?;
-                let result = session
-                    .query(
-                        format!(
-                            "SELECT id, root_ref, prev_commit_id FROM {commit_table} WHERE version = ? ALLOW FILTERING"
-                        ),
-                        (&version_bytes,),
-                    )
-                    .await?
-                    .first_row()
-                    .with_context(|| "Failed to select the first row")?;
-
-                let commit_id = result.columns[0]
-                    .as_ref()
-                    .and_then(|value| value.clone().into_string())
-                    .with_context(|| "Failed to deserialize value")?;
-
-                let root_ref = result.columns[1]
-                    .as_ref()
-                    .and_then(|value| value.as_bigint())
-                    .with_context(|| "Failed to deserialize root ref")?
-                    as u64;
-
-                let prev_commit_id = result.columns[2]
-                    .as_ref()
-                    .and_then(|value| value.clone().into_string());
-
-                Ok(Commit {
-                    id: Id::try_from(commit_id)?,
-                    version,
-                    root_ref,
-                    parent_commit_id: prev_commit_id.and_then(|id| Id::try_from(id).ok()),
-                })
+                let statements = session.statements()?;
+                let rows = match session.namespace() {
+                    Some(namespace) => {
+                        session
+                            .execute_all(
+                                &statements.select_namespace_commit_for_version,
+                                (namespace, &version_bytes),
+                            )
+                            .await?
+                    }
+                    None => {
+                        session
+                            .execute_all(&statements.select_commit_for_version, (&version_bytes,))
+                            .await?
+                    }
+                };
+                let row = rows
+                    .into_iter()
+                    .next()
+                    .with_context(|| "Failed to resolve commit for version")?;
+                commit_from_row(row)
             }
-            #[cfg(test)]
-            QuarkStore::Fake { commits, .. } => {
-                let commits = commits.borrow();
-                commits
+            QuarkStore::Memory {
+                commits,
+                namespace_commits,
+                namespace,
+                ..
+            } => {
+                let mut commits = commits.lock().unwrap();
+                let mut namespace_commits = namespace_commits.lock().unwrap();
+                memory_partition(&mut commits, &mut namespace_commits, namespace.as_deref())
                     .values()
                     .find(|c| c.version == version)
                     .with_context(|| "Failed to resolve commit for version")
@@ -383,25 +925,44 @@ impl VersionedStore for QuarkStore {
     }
 }
 
+/// Returns the partition of an in-memory table which belongs to the namespace, or the table itself
+/// for stores without a namespace.
+fn memory_partition<'a, K, V>(
+    table: &'a mut HashMap<K, V>,
+    partitions: &'a mut HashMap<String, HashMap<K, V>>,
+    namespace: Option<&str>,
+) -> &'a mut HashMap<K, V> {
+    match namespace {
+        Some(namespace) => partitions.entry(namespace.to_string()).or_default(),
+        None => table,
+    }
+}
+
 async fn current_commit_id_for_replica(
     session: &ScyllaSession,
     replica_id: ReplicaId,
 ) -> Result<Option<CommitId>> {
-    let replica_table = session.table_name(REPLICA_TABLE_NAME);
-
-    let raw_id = session
-        .query(
-            format!("SELECT commit_id FROM {replica_table} WHERE id = ?"),
-            (replica_id.as_str(),),
-        )
-        .await?
-        .rows_or_empty()
-        .first()
-        .and_then(|row| {
-            row.columns[0]
-                .as_ref()
-                .and_then(|value| value.clone().into_string())
-        });
+    let statements = session.statements()?;
+    let result = match session.namespace() {
+        Some(namespace) => {
+            session
+                .execute(
+                    &statements.select_namespace_replica_commit_id,
+                    (namespace, replica_id.as_str()),
+                )
+                .await?
+        }
+        None => {
+            session
+                .execute(&statements.select_replica_commit_id, (replica_id.as_str(),))
+                .await?
+        }
+    };
+    let raw_id = result.rows_or_empty().first().and_then(|row| {
+        row.columns[0]
+            .as_ref()
+            .and_then(|value| value.clone().into_string())
+    });
 
     match raw_id {
         Some(id) => Ok(Some(Id::try_from(id)?)),
@@ -414,88 +975,225 @@ async fn update_current_commit_id_for_replica(
     replica_id: ReplicaId,
     commit_id: CommitId,
 ) -> Result<()> {
-    let replica_table = session.table_name(REPLICA_TABLE_NAME);
-
-    session
-        .query(
-            format!("INSERT INTO {replica_table} (id, commit_id) VALUES (?, ?)"),
-            (replica_id.as_str(), commit_id.as_str()),
-        )
-        .await?;
+    let statements = session.statements()?;
+    match session.namespace() {
+        Some(namespace) => {
+            session
+                .execute(
+                    &statements.insert_namespace_replica,
+                    (namespace, replica_id.as_str(), commit_id.as_str()),
+                )
+                .await?
+        }
+        None => {
+            session
+                .execute(
+                    &statements.insert_replica,
+                    (replica_id.as_str(), commit_id.as_str()),
+                )
+                .await?
+        }
+    };
     Ok(())
 }
 
-const COMMIT_TABLE_NAME: &str = "commit";
-const OBJECT_TABLE_NAME: &str = "object";
-const REF_TABLE_NAME: &str = "ref";
-const REPLICA_TABLE_NAME: &str = "replica";
+/// Adds the signature of the replica to a new commit, if the replica has a signing key.
+pub(crate) fn sign_commit(
+    mut commit: Commit,
+    replica_id: ReplicaId,
+    signing_key: Option<&SigningKey>,
+) -> Result<Commit> {
+    if let Some(key) = signing_key {
+        commit.signature = Some(CommitSignature::sign(replica_id, &commit, key)?);
+    }
+    Ok(commit)
+}
+
+/// Parses a row of the commit table, which has been selected with [`COMMIT_COLUMNS`].
+fn commit_from_row(row: Row) -> Result<Commit> {
+    let id = row.columns[0]
+        .as_ref()
+        .and_then(|value| value.clone().into_string())
+        .with_context(|| "Failed to deserialize commit id")?;
+    let version_blob = row.columns[1]
+        .as_ref()
+        .and_then(|value| value.clone().into_blob())
+        .with_context(|| "Failed to deserialize version")?;
+    let root_ref = row.columns[2]
+        .as_ref()
+        .and_then(|value| value.as_bigint())
+        .with_context(|| "Failed to deserialize root ref")?;
+    let prev_commit_id = row.columns[3]
+        .as_ref()
+        .and_then(|value| value.clone().into_string());
+    let type_name = row.columns[4]
+        .as_ref()
+        .and_then(|value| value.clone().into_string());
+    let fingerprint = row.columns[5].as_ref().and_then(|value| value.as_bigint());
+    let author = row.columns[6]
+        .as_ref()
+        .and_then(|value| value.clone().into_string());
+    let wall_time = row.columns[7].as_ref().and_then(|value| value.as_bigint());
+    let logical_time = row.columns[8].as_ref().and_then(|value| value.as_int());
+    let message = row.columns[9]
+        .as_ref()
+        .and_then(|value| value.clone().into_string());
+    let annotations = row.columns[10]
+        .as_ref()
+        .and_then(|value| value.as_map())
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|(key, value)| Some((key.as_text()?.clone(), value.as_text()?.clone())))
+                .collect()
+        })
+        .unwrap_or_default();
+    let signer = row.columns[11]
+        .as_ref()
+        .and_then(|value| value.clone().into_string());
+    let signature = row.columns[12]
+        .as_ref()
+        .and_then(|value| value.clone().into_blob());
+    let merge_parent_ids = row.columns[13]
+        .as_ref()
+        .and_then(|value| value.as_list())
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| id.as_text().cloned())
+                .map(Id::try_from)
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(Commit {
+        id: Id::try_from(id)?,
+        version: ENCODING.decode(version_blob.as_slice())?,
+        root_ref: root_ref as u64,
+        parent_commit_id: prev_commit_id.and_then(|id| Id::try_from(id).ok()),
+        merge_parent_ids,
+        type_tag: type_name
+            .zip(fingerprint)
+            .map(|(name, fingerprint)| TypeTag::with_fingerprint(name, fingerprint as u64)),
+        metadata: CommitMetadata {
+            author,
+            timestamp: wall_time.map(|wall_ms| HybridTimestamp {
+                wall_ms: wall_ms as u64,
+                logical: logical_time.unwrap_or_default() as u32,
+            }),
+            message,
+            annotations,
+        },
+        signature: match (signer, signature) {
+            (Some(signer), Some(bytes)) => Some(CommitSignature {
+                signer: Id::try_from(signer)?,
+                bytes,
+            }),
+            _ => None,
+        },
+    })
+}
+
+pub(crate) const COMMIT_TABLE_NAME: &str = "commit";
+pub(crate) const OBJECT_TABLE_NAME: &str = "object";
+pub(crate) const REF_TABLE_NAME: &str = "ref";
+pub(crate) const REPLICA_TABLE_NAME: &str = "replica";
+pub(crate) const REMOTE_HEAD_TABLE_NAME: &str = "remote_head";
+pub(crate) const ROOT_TYPE_TABLE_NAME: &str = "root_type";
+pub(crate) const REPLICA_KEY_TABLE_NAME: &str = "replica_key";
+pub(crate) const REPLICA_TYPE_TABLE_NAME: &str = "replica_type";
+pub(crate) const REPLICA_MEMBERSHIP_TABLE_NAME: &str = "replica_membership";
+pub(crate) const BRANCH_TABLE_NAME: &str = "branch";
+pub(crate) const TAG_TABLE_NAME: &str = "tag";
+pub(crate) const NAMESPACE_COMMIT_TABLE_NAME: &str = "namespace_commit";
+pub(crate) const NAMESPACE_REPLICA_TABLE_NAME: &str = "namespace_replica";
+
+/// The columns of the commit table, in the order expected by [`commit_from_row`].
+const COMMIT_COLUMNS: &str =
+    "id, version, root_ref, prev_commit_id, type_name, schema_fingerprint, \
+    author, wall_time, logical_time, message, annotations, signer, signature, merge_parent_ids";
 
 impl QuarkStore {
     pub async fn setup(
         hostname: impl Into<String>,
         keyspace: impl Into<String>,
     ) -> Result<QuarkStore> {
-        let session = ScyllaSession::new(hostname, keyspace).await?;
-
-        let ref_table_name = session.table_name(REF_TABLE_NAME);
-        session
-            .query(
-                format!(
-                    "CREATE TABLE IF NOT EXISTS {ref_table_name}
-                        (id BIGINT, left BIGINT, right BIGINT, object_ref BIGINT,
-                        PRIMARY KEY (id))"
-                ),
-                &[],
-            )
-            .await
-            .with_context(|| "Failed to create ref table")?;
-
-        let object_table_name = session.table_name(OBJECT_TABLE_NAME);
-        session
-            .query(
-                format!(
-                    "CREATE TABLE IF NOT EXISTS {object_table_name}
-                        (id BIGINT, object BLOB,
-                        PRIMARY KEY (id))"
-                ),
-                &[],
-            )
-            .await
-            .with_context(|| "Failed to create object table")?;
+        Self::connect(QuarkStoreConfig::new(hostname, keyspace)).await
+    }
 
-        let commit_table_name = session.table_name(COMMIT_TABLE_NAME);
-        session
-            .query(
-                format!(
-                    "CREATE TABLE IF NOT EXISTS {commit_table_name}
-                        (id TEXT, version BLOB, root_ref BIGINT, prev_commit_id TEXT, PRIMARY KEY (id))"),
-                &[],
-            )
-            .await
-            .with_context(|| "Failed to create commit table")?;
+    /// Connects to the cluster described by the configuration and sets up the tables of the store.
+    pub async fn connect(config: QuarkStoreConfig) -> Result<QuarkStore> {
+        let mut session = ScyllaSession::connect(&config).await?;
 
-        let replica_table_name = session.table_name(REPLICA_TABLE_NAME);
-        session
-            .query(
-                format!(
-                    "CREATE TABLE IF NOT EXISTS {replica_table_name}
-                        (id TEXT, commit_id TEXT, PRIMARY KEY (id))"
-                ),
-                &[],
-            )
-            .await
-            .with_context(|| "Failed to create replica table")?;
+        let schema_version = migrate(&session).await?;
+        log::debug!("Store tables are at schema version {schema_version}");
 
+        session.prepare_statements().await?;
         Ok(Self::Scylla(session))
     }
 
+    /// Creates an empty store, which keeps everything in memory.
+    pub fn memory() -> Self {
+        Self::Memory {
+            commits: Mutex::new(HashMap::new()),
+            objects: Mutex::new(HashMap::new()),
+            refs: Mutex::new(HashMap::new()),
+            replicas: Mutex::new(HashMap::new()),
+            replica_types: Mutex::new(HashMap::new()),
+            memberships: Mutex::new(HashMap::new()),
+            root_types: Mutex::new(HashMap::new()),
+            replica_keys: Mutex::new(HashMap::new()),
+            remote_heads: Mutex::new(HashMap::new()),
+            branches: Mutex::new(HashMap::new()),
+            tags: Mutex::new(HashMap::new()),
+            namespace_commits: Mutex::new(HashMap::new()),
+            namespace_replicas: Mutex::new(HashMap::new()),
+            notifier: broadcast::channel(NOTIFICATION_CAPACITY).0,
+            migrations: ObjectMigrations::default(),
+            signature_policy: SignaturePolicy::default(),
+            partial_documents: None,
+            namespace: None,
+        }
+    }
+
     #[cfg(test)]
     pub fn test() -> Self {
-        Self::Fake {
-            commits: RefCell::new(HashMap::new()),
-            objects: RefCell::new(HashMap::new()),
-            refs: RefCell::new(HashMap::new()),
-            replicas: RefCell::new(HashMap::new()),
+        Self::memory()
+    }
+
+    /// Enables a read-through cache for refs and objects. Only stores backed by Scylla are
+    /// cached, as the other stores already keep everything in memory. Cached objects save the
+    /// round trip to Scylla, but are still decoded on every read, see [`ObjectCache`].
+    pub fn with_cache(mut self, config: CacheConfig) -> Self {
+        match &mut self {
+            QuarkStore::Scylla(session) => session.cache = Some(ObjectCache::new(config)),
+            QuarkStore::Memory { .. } => {}
+        }
+        self
+    }
+
+    /// Returns the schema version of the store tables, which is `None` for stores that are not
+    /// backed by Scylla.
+    pub async fn schema_version(&self) -> Result<Option<u32>> {
+        match self {
+            QuarkStore::Scylla(session) => Ok(Some(schema::schema_version(session).await?)),
+            QuarkStore::Memory { .. } => Ok(None),
+        }
+    }
+
+    /// Returns the upgrades which are applied to objects of older versions.
+    pub fn migrations(&self) -> &ObjectMigrations {
+        match self {
+            QuarkStore::Scylla(session) => &session.migrations,
+            QuarkStore::Memory { migrations, .. } => migrations,
+        }
+    }
+
+    /// Returns the statistics of the cache, if caching is enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        match self {
+            QuarkStore::Scylla(session) => session.cache().map(|cache| cache.stats()),
+            QuarkStore::Memory { .. } => None,
         }
     }
 
@@ -503,9 +1201,8 @@ impl QuarkStore {
     pub fn insert_ref(&self, reference: Ref) {
         match self {
             QuarkStore::Scylla(_) => todo!(),
-            #[cfg(test)]
-            QuarkStore::Fake { refs, .. } => {
-                let mut refs = refs.borrow_mut();
+            QuarkStore::Memory { refs, .. } => {
+                let mut refs = refs.lock().unwrap();
                 refs.insert(reference.id, reference);
             }
         }
@@ -518,15 +1215,17 @@ impl QuarkStore {
 
         match self {
             QuarkStore::Scylla(session) => {
-                let ref_table = session.table_name(REF_TABLE_NAME);
-                let result = session
-                    .query(
-                        format!("SELECT left, right, object_ref FROM {ref_table} WHERE id = ?"),
-                        (id as i64,),
-                    )
-                    .await?
-                    .single_row()
-                    .with_context(|| "Failed to select single row")?;
+                if let Some(reference) = session.cache().and_then(|cache| cache.get_ref(id)) {
+                    return Ok(Some(reference));
+                }
+
+                let Some(result) = session
+                    .execute(&session.statements()?.select_ref, (id as i64,))
+                    .await?
+                    .maybe_first_row()?
+                else {
+                    return Ok(None);
+                };
 
                 let left = result.columns[0]
                     .as_ref()
@@ -544,16 +1243,19 @@ impl QuarkStore {
                     .with_context(|| "Failed to deserialize object_ref")?
                     as u64;
 
-                Ok(Some(Ref {
+                let reference = Ref {
                     id,
                     left,
                     right,
                     object_ref,
-                }))
+                };
+                if let Some(cache) = session.cache() {
+                    cache.insert_ref(reference.clone());
+                }
+                Ok(Some(reference))
             }
-            #[cfg(test)]
-            QuarkStore::Fake { refs, .. } => {
-                let refs = refs.borrow();
+            QuarkStore::Memory { refs, .. } => {
+                let refs = refs.lock().unwrap();
                 Ok(refs.get(&id).cloned())
             }
         }
@@ -564,12 +1266,13 @@ impl ObjectStore for QuarkStore {
     async fn resolve_object<T: Hash + DecodeOwned<Binary>>(&self, id: u64) -> Result<Option<T>> {
         match self {
             QuarkStore::Scylla(session) => {
-                let object_table = session.table_name(OBJECT_TABLE_NAME);
+                if let Some(bytes) = session.cache().and_then(|cache| cache.get_object(id)) {
+                    let data: T = self.migrations().decode(bytes.as_ref())?;
+                    return Ok(Some(data));
+                }
+
                 let object_blob = session
-                    .query(
-                        format!("SELECT object FROM {object_table} WHERE id = ?"),
-                        (id as i64,),
-                    )
+                    .execute(&session.statements()?.select_object, (id as i64,))
                     .await?
                     .single_row()
                     .with_context(|| "Failed to select single row")?
@@ -578,20 +1281,18 @@ impl ObjectStore for QuarkStore {
                     .and_then(|value| value.clone().into_blob())
                     .with_context(|| "Failed to deserialize value")?;
 
-                let data: T = ENCODING
-                    .decode(object_blob.as_slice())
-                    .with_context(|| "Failed to deserialize object")?;
+                let data: T = self.migrations().decode(object_blob.as_slice())?;
+                if let Some(cache) = session.cache() {
+                    cache.insert_object(id, &object_blob);
+                }
                 Ok(Some(data))
             }
-            #[cfg(test)]
-            QuarkStore::Fake { objects, .. } => {
-                let objects = objects.borrow();
+            QuarkStore::Memory { objects, .. } => {
+                let objects = objects.lock().unwrap();
                 let Some(bytes) = objects.get(&id) else {
                     return Ok(None);
                 };
-                let decoded = ENCODING
-                    .decode(bytes.as_slice())
-                    .with_context(|| "Failed to deserialize object")?;
+                let decoded = self.migrations().decode(bytes.as_slice())?;
 
                 Ok(Some(decoded))
             }
@@ -606,19 +1307,25 @@ impl ObjectStore for QuarkStore {
             QuarkStore::Scylla(session) => {
                 const MAX_CHUNK_SIZE: usize = 100;
 
-                let object_table = session.table_name(OBJECT_TABLE_NAME);
-                let query = format!("SELECT id, object FROM {object_table} WHERE id IN ?");
-                let ids_i64: Vec<i64> = ids.iter().map(|&id| id as i64).collect();
-
                 let mut result = std::iter::repeat_with(|| None)
                     .take(ids.len())
                     .collect::<Vec<_>>();
 
-                for chunk in ids_i64.chunks(MAX_CHUNK_SIZE) {
+                let mut missing_ids = Vec::with_capacity(ids.len());
+                for (index, &id) in ids.iter().enumerate() {
+                    match session.cache().and_then(|cache| cache.get_object(id)) {
+                        Some(bytes) => {
+                            let data: T = self.migrations().decode(bytes.as_ref())?;
+                            result[index] = Some(data);
+                        }
+                        None => missing_ids.push(id as i64),
+                    }
+                }
+
+                for chunk in missing_ids.chunks(MAX_CHUNK_SIZE) {
                     let rows = session
-                        .query(query.clone(), (chunk,))
-                        .await?
-                        .rows_or_empty();
+                        .execute_all(&session.statements()?.select_objects, (chunk,))
+                        .await?;
 
                     for row in rows {
                         let id = row.columns[0]
@@ -630,9 +1337,10 @@ impl ObjectStore for QuarkStore {
                             .and_then(|value| value.clone().into_blob())
                             .with_context(|| "Failed to deserialize value")?;
 
-                        let data: T = ENCODING
-                            .decode(object_blob.as_slice())
-                            .with_context(|| "Failed to deserialize object")?;
+                        let data: T = self.migrations().decode(object_blob.as_slice())?;
+                        if let Some(cache) = session.cache() {
+                            cache.insert_object(id as u64, &object_blob);
+                        }
 
                         if let Some(index) = ids.iter().position(|&x| x == id as u64) {
                             result[index] = Some(data);
@@ -642,15 +1350,12 @@ impl ObjectStore for QuarkStore {
 
                 Ok(result)
             }
-            #[cfg(test)]
-            QuarkStore::Fake { objects, .. } => {
-                let objects = objects.borrow();
+            QuarkStore::Memory { objects, .. } => {
+                let objects = objects.lock().unwrap();
                 let mut result = Vec::with_capacity(ids.len());
                 for &id in ids {
                     if let Some(bytes) = objects.get(&id) {
-                        let decoded = ENCODING
-                            .decode(bytes.as_slice())
-                            .with_context(|| "Failed to deserialize object")?;
+                        let decoded = self.migrations().decode(bytes.as_slice())?;
                         result.push(Some(decoded));
                     } else {
                         result.push(None);
@@ -672,283 +1377,1353 @@ impl ObjectStore for QuarkStore {
                     instant = Some(Instant::now());
                 }
 
-                let prepared: PreparedStatement = session
-                    .raw()
-                    .prepare(format!(
-                        "INSERT INTO {} (id, object) VALUES (?, ?)",
-                        session.table_name(OBJECT_TABLE_NAME)
-                    ))
-                    .await?;
-
                 let total_objects = objects.len();
                 let mut hashes = Vec::with_capacity(total_objects);
 
                 for chunk in (0..total_objects).step_by(BATCH_SIZE) {
                     let end = std::cmp::min(chunk + BATCH_SIZE, total_objects);
-                    let mut batch = Batch::default();
                     let mut values = Vec::with_capacity(end - chunk);
 
                     for object in &objects[chunk..end] {
-                        batch.append_statement(prepared.clone());
-
-                        let mut hasher = std::hash::DefaultHasher::new();
-                        object.hash(&mut hasher);
-                        let hash = hasher.finish();
+                        let hash = object_hash(object);
                         hashes.push(hash);
 
-                        let mut data = Vec::new();
-                        ENCODING
-                            .encode(&mut data, object)
-                            .with_context(|| "Failed to serialize object")?;
+                        let data = self.migrations().encode(object)?;
 
                         values.push((hash as i64, data));
                     }
 
-                    session.raw().batch(&batch, &values).await?;
+                    session
+                        .execute_batch(&session.statements()?.insert_object, values)
+                        .await?;
+                }
+
+                if let Some(instant) = instant {
+                    log::debug!(
+                        "Inserting {} objects took {:?}",
+                        total_objects,
+                        instant.elapsed()
+                    );
+                }
+
+                Ok(hashes)
+            }
+            QuarkStore::Memory { .. } => {
+                let mut hashes = Vec::new();
+                for object in objects {
+                    hashes.push(self.insert_object(object).await?);
+                }
+                Ok(hashes)
+            }
+        }
+    }
+
+    async fn insert_object<T: Hash + Encode<Binary>>(&self, object: &T) -> Result<u64> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let mut instant = None;
+                if log_enabled!(log::Level::Debug) {
+                    instant = Some(Instant::now());
+                }
+
+                let data = self.migrations().encode(object)?;
+
+                let hash = object_hash(object);
+
+                session
+                    .execute(&session.statements()?.insert_object, (hash as i64, data))
+                    .await?;
+
+                if let Some(instant) = instant {
+                    log::debug!("Inserting object took {:?}", instant.elapsed());
+                }
+
+                Ok(hash)
+            }
+            QuarkStore::Memory { objects, .. } => {
+                let hash = object_hash(object);
+
+                let bytes = self.migrations().encode(object)?;
+
+                let mut objects = objects.lock().unwrap();
+                objects.insert(hash, bytes);
+                Ok(hash)
+            }
+        }
+    }
+}
+
+impl RefStore for QuarkStore {
+    async fn resolve<T: Deserialize>(&self, root: u64) -> Result<Option<T>> {
+        let mut instant = None;
+        if log_enabled!(log::Level::Debug) {
+            instant = Some(Instant::now());
+        }
+        log::debug!("Resolving object with root id {root}");
+        self.verify_root_type::<T>(root).await?;
+        let Some(root) = self.resolve_ref(Some(root)).await? else {
+            return Ok(None);
+        };
+        let cx = DeserializeCx {
+            store: self,
+            visited: None,
+        };
+        let result = T::deserialize(root, cx).await?;
+        if log_enabled!(log::Level::Debug) {
+            if let Some(elapsed) = instant.map(|i| i.elapsed()) {
+                log::debug!("Resolving object took {} ms", elapsed.as_millis());
+            }
+        }
+        Ok(Some(result))
+    }
+
+    async fn insert<T: Serialize>(&self, object: &T) -> Result<u64> {
+        let mut insert_versioned_time = None;
+        if log_enabled!(log::Level::Debug) {
+            insert_versioned_time = Some(Instant::now());
+        }
+        let cx = SerializeCx { store: self };
+        let references = object.serialize(cx).await?;
+        log::debug!("Inserting object with {} references", references.len());
+        let mut reference_time = None;
+        if log_enabled!(log::Level::Debug) {
+            reference_time = Some(Instant::now());
+        }
+
+        let root_ref_id = references.last().with_context(|| "Structure is empty")?.id;
+
+        self.insert_refs(&references).await?;
+        let root_ref = root_ref_id;
+
+        if log_enabled!(log::Level::Debug) {
+            if let Some(elapsed) = reference_time.map(|i| i.elapsed()) {
+                log::debug!("Inserting references took {} ms", elapsed.as_millis());
+            }
+            if let Some(elapsed) = insert_versioned_time.map(|i| i.elapsed()) {
+                log::debug!("Inserting versioned object took {} ms", elapsed.as_millis());
+            }
+        }
+
+        Ok(root_ref)
+    }
+}
+
+pub struct SerializeCx<'a> {
+    store: &'a QuarkStore,
+}
+
+impl SerializeCx<'_> {
+    pub async fn insert_object<T: Hash + Encode<Binary>>(&self, object: &T) -> Result<u64> {
+        self.store.insert_object(object).await
+    }
+
+    pub async fn insert_objects<'a, T: 'a + Hash + Encode<Binary>>(
+        &self,
+        objects: &[&'a T],
+    ) -> Result<Vec<u64>> {
+        self.store.insert_objects(objects).await
+    }
+
+    pub async fn serialize_iter<'a, T: 'a + Hash + Encode<Binary>>(
+        &self,
+        iter: impl Iterator<Item = &'a T>,
+    ) -> Result<Vec<Ref>> {
+        let mut prev_hash: Option<u64> = None;
+        let objects = iter.collect::<Vec<_>>();
+        let mut refs = Vec::with_capacity(objects.len());
+        let object_refs = self.insert_objects(&objects).await?;
+        for object_ref in object_refs {
+            let reference = Ref::compute(object_ref, prev_hash, None);
+            prev_hash = Some(reference.id);
+            refs.push(reference);
+        }
+        Ok(refs)
+    }
+}
+
+pub struct DeserializeCx<'a> {
+    store: &'a QuarkStore,
+    /// Records the resolved objects together with the name of the type they are decoded as,
+    /// see [`QuarkStore::rewrite_objects`].
+    visited: Option<&'a Mutex<Vec<(ObjectRef, &'static str)>>>,
+}
+
+impl<'a> DeserializeCx<'a> {
+    pub(crate) fn recording(
+        store: &'a QuarkStore,
+        visited: &'a Mutex<Vec<(ObjectRef, &'static str)>>,
+    ) -> Self {
+        Self {
+            store,
+            visited: Some(visited),
+        }
+    }
+
+    pub async fn resolve_object<T: Hash + DecodeOwned<Binary>>(
+        &self,
+        id: u64,
+    ) -> Result<Option<T>> {
+        self.record::<T>(&[id]);
+        self.store.resolve_object(id).await
+    }
+
+    pub async fn resolve_objects<T: Hash + DecodeOwned<Binary>>(
+        &self,
+        ids: &[u64],
+    ) -> Result<Vec<Option<T>>> {
+        self.record::<T>(ids);
+        self.store.resolve_objects(ids).await
+    }
+
+    fn record<T>(&self, ids: &[u64]) {
+        if let Some(visited) = self.visited {
+            let type_name = std::any::type_name::<T>();
+            visited
+                .lock()
+                .unwrap()
+                .extend(ids.iter().map(|id| (*id, type_name)));
+        }
+    }
+
+    pub async fn resolve_ref(&self, id: Option<u64>) -> Result<Option<Ref>> {
+        self.store.resolve_ref(id).await
+    }
+
+    pub async fn deserialize_iter<T: Hash + DecodeOwned<Binary>>(
+        &self,
+        root: Ref,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        items.push(root.object_ref);
+        let mut node = root;
+        while let Some(new_node) = self.resolve_ref(node.left).await? {
+            items.push(new_node.object_ref);
+            node = new_node
+        }
+        items.reverse();
+
+        let objects = self.resolve_objects::<T>(&items).await?;
+        let mut objects_only = Vec::with_capacity(objects.len());
+        for object in objects {
+            let Some(object) = object else {
+                log::debug!("Object not found for ids: {:?}", &items);
+                self.store.dump().await?;
+                return Err(anyhow!("Object not found"));
+            };
+            objects_only.push(object);
+        }
+        Ok(objects_only)
+    }
+}
+
+// Raw access to the tables, which is used to transfer history between stores
+
+impl QuarkStore {
+    /// Returns all commits of the namespace of the store.
+    pub async fn commits(&self) -> Result<Vec<Commit>> {
+        self.partition_commits(self.namespace()).await
+    }
+
+    /// Returns all commits of the given namespace, or of the stores without a namespace.
+    pub(crate) async fn partition_commits(&self, namespace: Option<&str>) -> Result<Vec<Commit>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let statements = session.statements()?;
+                let rows = match namespace {
+                    Some(namespace) => {
+                        session
+                            .execute_all(&statements.select_namespace_commits, (namespace,))
+                            .await?
+                    }
+                    None => session.execute_all(&statements.select_commits, ()).await?,
+                };
+                rows.into_iter().map(commit_from_row).collect()
+            }
+            QuarkStore::Memory {
+                commits,
+                namespace_commits,
+                ..
+            } => {
+                let mut commits = commits.lock().unwrap();
+                let mut namespace_commits = namespace_commits.lock().unwrap();
+                Ok(
+                    memory_partition(&mut commits, &mut namespace_commits, namespace)
+                        .values()
+                        .cloned()
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    /// Returns the commit if it belongs to the namespace of the store, without scanning the
+    /// commit table.
+    pub async fn find_commit(&self, commit_id: CommitId) -> Result<Option<Commit>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let statements = session.statements()?;
+                let result = match session.namespace() {
+                    Some(namespace) => {
+                        session
+                            .execute(
+                                &statements.select_namespace_commit,
+                                (namespace, commit_id.as_str()),
+                            )
+                            .await?
+                    }
+                    None => {
+                        session
+                            .execute(&statements.select_commit, (commit_id.as_str(),))
+                            .await?
+                    }
+                };
+                result.maybe_first_row()?.map(commit_from_row).transpose()
+            }
+            QuarkStore::Memory {
+                commits,
+                namespace_commits,
+                namespace,
+                ..
+            } => {
+                let mut commits = commits.lock().unwrap();
+                let mut namespace_commits = namespace_commits.lock().unwrap();
+                Ok(
+                    memory_partition(&mut commits, &mut namespace_commits, namespace.as_deref())
+                        .get(&commit_id)
+                        .cloned(),
+                )
+            }
+        }
+    }
+
+    /// Returns the id of the latest commit of every replica in the namespace of the store.
+    pub async fn replica_heads(&self) -> Result<Vec<(ReplicaId, CommitId)>> {
+        self.partition_heads(self.namespace()).await
+    }
+
+    /// Returns the id of the latest commit of every replica in the given namespace, or of the
+    /// stores without a namespace.
+    pub(crate) async fn partition_heads(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<(ReplicaId, CommitId)>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let statements = session.statements()?;
+                let rows = match namespace {
+                    Some(namespace) => {
+                        session
+                            .execute_all(&statements.select_namespace_replicas, (namespace,))
+                            .await?
+                    }
+                    None => session.execute_all(&statements.select_replicas, ()).await?,
+                };
+                let mut heads = Vec::new();
+                for row in rows {
+                    let replica_id = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize replica id")?;
+                    let Some(commit_id) = row.columns[1]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                    else {
+                        continue;
+                    };
+                    heads.push((Id::try_from(replica_id)?, Id::try_from(commit_id)?));
+                }
+                Ok(heads)
+            }
+            QuarkStore::Memory {
+                replicas,
+                namespace_replicas,
+                ..
+            } => {
+                let mut replicas = replicas.lock().unwrap();
+                let mut namespace_replicas = namespace_replicas.lock().unwrap();
+                Ok(
+                    memory_partition(&mut replicas, &mut namespace_replicas, namespace)
+                        .iter()
+                        .map(|(replica_id, commit_id)| (*replica_id, *commit_id))
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    /// Points the head of the replica to the given commit.
+    pub async fn set_replica_head(&self, replica_id: ReplicaId, commit_id: CommitId) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                update_current_commit_id_for_replica(session, replica_id, commit_id).await
+            }
+            QuarkStore::Memory {
+                replicas,
+                namespace_replicas,
+                namespace,
+                notifier,
+                ..
+            } => {
+                let commit = self
+                    .find_commit(commit_id)
+                    .await?
+                    .with_context(|| "Commit not found")?;
+                {
+                    let mut replicas = replicas.lock().unwrap();
+                    let mut namespace_replicas = namespace_replicas.lock().unwrap();
+                    memory_partition(&mut replicas, &mut namespace_replicas, namespace.as_deref())
+                        .insert(replica_id, commit_id);
+                }
+
+                let _ = notifier.send(CommitEvent { replica_id, commit });
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the type tag of the objects the replica commits, if one has been recorded.
+    pub async fn replica_type_tag(&self, replica_id: ReplicaId) -> Result<Option<TypeTag>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let Some(row) = session
+                    .execute(
+                        &session.statements()?.select_replica_type_tag,
+                        (self.namespace_key(), replica_id.as_str()),
+                    )
+                    .await?
+                    .maybe_first_row()?
+                else {
+                    return Ok(None);
+                };
+                let Some(name) = row.columns[0]
+                    .as_ref()
+                    .and_then(|value| value.clone().into_string())
+                else {
+                    return Ok(None);
+                };
+                let fingerprint = row.columns[1]
+                    .as_ref()
+                    .and_then(|value| value.as_bigint())
+                    .with_context(|| "Failed to deserialize schema fingerprint")?;
+                Ok(Some(TypeTag::with_fingerprint(name, fingerprint as u64)))
+            }
+            QuarkStore::Memory { replica_types, .. } => Ok(replica_types
+                .lock()
+                .unwrap()
+                .get(&(self.namespace_key().to_string(), replica_id))
+                .cloned()),
+        }
+    }
+
+    /// Records the type tag of the objects the replica commits.
+    pub async fn set_replica_type_tag(
+        &self,
+        replica_id: ReplicaId,
+        type_tag: &TypeTag,
+    ) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.update_replica_type_tag,
+                        (
+                            type_tag.name.as_str(),
+                            type_tag.fingerprint as i64,
+                            self.namespace_key(),
+                            replica_id.as_str(),
+                        ),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { replica_types, .. } => {
+                replica_types.lock().unwrap().insert(
+                    (self.namespace_key().to_string(), replica_id),
+                    type_tag.clone(),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the membership of every replica which has been registered.
+    pub async fn replica_memberships(&self) -> Result<HashMap<ReplicaId, Membership>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let mut memberships = HashMap::new();
+                for row in session
+                    .execute_all(
+                        &session.statements()?.select_replica_memberships,
+                        (self.namespace_key(),),
+                    )
+                    .await?
+                {
+                    let replica_id = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize replica id")?;
+                    let name = row.columns[1]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string());
+                    let aliases = row.columns[2]
+                        .as_ref()
+                        .and_then(|value| value.as_set())
+                        .map(|aliases| {
+                            aliases
+                                .iter()
+                                .filter_map(|alias| alias.as_text().cloned())
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let retired = row.columns[3].as_ref().and_then(|value| value.as_boolean());
+                    if name.is_none() && retired.is_none() {
+                        continue;
+                    }
+                    memberships.insert(
+                        Id::try_from(replica_id)?,
+                        Membership {
+                            name,
+                            aliases,
+                            retired: retired.unwrap_or_default(),
+                        },
+                    );
+                }
+                Ok(memberships)
+            }
+            QuarkStore::Memory { memberships, .. } => Ok(memberships
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|((namespace, _), _)| namespace == self.namespace_key())
+                .map(|((_, replica_id), membership)| (*replica_id, membership.clone()))
+                .collect()),
+        }
+    }
+
+    pub async fn set_replica_name(&self, replica_id: ReplicaId, name: &str) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.update_replica_name,
+                        (name, self.namespace_key(), replica_id.as_str()),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { memberships, .. } => {
+                memberships
+                    .lock()
+                    .unwrap()
+                    .entry((self.namespace_key().to_string(), replica_id))
+                    .or_default()
+                    .name = Some(name.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn insert_replica_alias(&self, replica_id: ReplicaId, alias: &str) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.insert_replica_alias,
+                        (vec![alias], self.namespace_key(), replica_id.as_str()),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { memberships, .. } => {
+                memberships
+                    .lock()
+                    .unwrap()
+                    .entry((self.namespace_key().to_string(), replica_id))
+                    .or_default()
+                    .aliases
+                    .insert(alias.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn set_replica_retired(&self, replica_id: ReplicaId, retired: bool) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.update_replica_retired,
+                        (retired, self.namespace_key(), replica_id.as_str()),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { memberships, .. } => {
+                memberships
+                    .lock()
+                    .unwrap()
+                    .entry((self.namespace_key().to_string(), replica_id))
+                    .or_default()
+                    .retired = retired;
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the public key the replica has registered.
+    pub async fn replica_public_key(&self, replica_id: ReplicaId) -> Result<Option<[u8; 32]>> {
+        match self {
+            QuarkStore::Scylla(session) => session
+                .execute(
+                    &session.statements()?.select_replica_key,
+                    (self.namespace_key(), replica_id.as_str()),
+                )
+                .await?
+                .maybe_first_row()?
+                .and_then(|row| {
+                    row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_blob())
+                })
+                .map(|bytes| {
+                    <[u8; 32]>::try_from(bytes)
+                        .map_err(|_| anyhow!("Public key of replica {replica_id} is invalid"))
+                })
+                .transpose(),
+            QuarkStore::Memory { replica_keys, .. } => Ok(replica_keys
+                .lock()
+                .unwrap()
+                .get(&(self.namespace_key().to_string(), replica_id))
+                .copied()),
+        }
+    }
+
+    /// Stores the public key of the replica, unless it has already registered one.
+    pub async fn insert_replica_public_key(
+        &self,
+        replica_id: ReplicaId,
+        public_key: [u8; 32],
+    ) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.insert_replica_key,
+                        (
+                            self.namespace_key(),
+                            replica_id.as_str(),
+                            public_key.as_slice(),
+                        ),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { replica_keys, .. } => {
+                replica_keys
+                    .lock()
+                    .unwrap()
+                    .entry((self.namespace_key().to_string(), replica_id))
+                    .or_insert(public_key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the heads of the replicas of the given remote, as they were at the last sync.
+    pub async fn remote_heads(&self, remote: &str) -> Result<Vec<(ReplicaId, CommitId)>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let mut heads = Vec::new();
+                for row in session
+                    .execute_all(
+                        &session.statements()?.select_remote_heads,
+                        (self.namespace_key(), remote),
+                    )
+                    .await?
+                {
+                    let replica_id = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize replica id")?;
+                    let commit_id = row.columns[1]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize commit id")?;
+                    heads.push((Id::try_from(replica_id)?, Id::try_from(commit_id)?));
+                }
+                Ok(heads)
+            }
+            QuarkStore::Memory { remote_heads, .. } => Ok(remote_heads
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|((namespace, name, _), _)| {
+                    namespace == self.namespace_key() && name == remote
+                })
+                .map(|((_, _, replica_id), commit_id)| (*replica_id, *commit_id))
+                .collect()),
+        }
+    }
+
+    /// Records the head of a replica of the given remote.
+    pub async fn set_remote_head(
+        &self,
+        remote: &str,
+        replica_id: ReplicaId,
+        commit_id: CommitId,
+    ) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.insert_remote_head,
+                        (
+                            self.namespace_key(),
+                            remote,
+                            replica_id.as_str(),
+                            commit_id.as_str(),
+                        ),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { remote_heads, .. } => {
+                remote_heads.lock().unwrap().insert(
+                    (
+                        self.namespace_key().to_string(),
+                        remote.to_string(),
+                        replica_id,
+                    ),
+                    commit_id,
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the commits the heads of all remotes in the namespace point to.
+    pub async fn namespace_remote_heads(&self, namespace: &str) -> Result<Vec<CommitId>> {
+        match self {
+            QuarkStore::Scylla(session) => session
+                .execute_all(
+                    &session.statements()?.select_namespace_remote_heads,
+                    (namespace,),
+                )
+                .await?
+                .into_iter()
+                .map(|row| {
+                    let commit_id = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize commit id")?;
+                    Id::try_from(commit_id)
+                })
+                .collect(),
+            QuarkStore::Memory { remote_heads, .. } => Ok(remote_heads
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|((remote_namespace, _, _), _)| remote_namespace == namespace)
+                .map(|(_, commit_id)| *commit_id)
+                .collect()),
+        }
+    }
+
+    /// Returns the heads of the named branches of the replica, without its default branch.
+    pub async fn branch_heads(&self, replica_id: ReplicaId) -> Result<Vec<(String, CommitId)>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let mut heads = Vec::new();
+                for row in session
+                    .execute_all(
+                        &session.statements()?.select_branches,
+                        (self.namespace_key(), replica_id.as_str()),
+                    )
+                    .await?
+                {
+                    let name = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize branch name")?;
+                    let commit_id = row.columns[1]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize commit id")?;
+                    heads.push((name, Id::try_from(commit_id)?));
+                }
+                Ok(heads)
+            }
+            QuarkStore::Memory { branches, .. } => Ok(branches
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|((namespace, id, _), _)| {
+                    namespace == self.namespace_key() && *id == replica_id
+                })
+                .map(|((_, _, name), commit_id)| (name.clone(), *commit_id))
+                .collect()),
+        }
+    }
+
+    /// Returns the commits the named branches of all replicas in the namespace point to.
+    pub async fn namespace_branch_heads(&self, namespace: &str) -> Result<Vec<CommitId>> {
+        match self {
+            QuarkStore::Scylla(session) => session
+                .execute_all(
+                    &session.statements()?.select_namespace_branches,
+                    (namespace,),
+                )
+                .await?
+                .into_iter()
+                .map(|row| {
+                    let commit_id = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize commit id")?;
+                    Id::try_from(commit_id)
+                })
+                .collect(),
+            QuarkStore::Memory { branches, .. } => Ok(branches
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|((branch_namespace, _, _), _)| branch_namespace == namespace)
+                .map(|(_, commit_id)| *commit_id)
+                .collect()),
+        }
+    }
+
+    /// Points the named branch of the replica to the given commit.
+    pub async fn set_branch_head(
+        &self,
+        replica_id: ReplicaId,
+        name: &str,
+        commit_id: CommitId,
+    ) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.insert_branch,
+                        (
+                            self.namespace_key(),
+                            replica_id.as_str(),
+                            name,
+                            commit_id.as_str(),
+                        ),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { branches, .. } => {
+                branches.lock().unwrap().insert(
+                    (
+                        self.namespace_key().to_string(),
+                        replica_id,
+                        name.to_string(),
+                    ),
+                    commit_id,
+                );
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn remove_branch_head(&self, replica_id: ReplicaId, name: &str) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.delete_branch,
+                        (self.namespace_key(), replica_id.as_str(), name),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { branches, .. } => {
+                branches.lock().unwrap().remove(&(
+                    self.namespace_key().to_string(),
+                    replica_id,
+                    name.to_string(),
+                ));
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the commits the tags of the namespace point to.
+    pub async fn tag_targets(&self, namespace: &str) -> Result<Vec<(String, CommitId)>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let mut tags = Vec::new();
+                for row in session
+                    .execute_all(&session.statements()?.select_tags, (namespace,))
+                    .await?
+                {
+                    let name = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize tag name")?;
+                    let commit_id = row.columns[1]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize commit id")?;
+                    tags.push((name, Id::try_from(commit_id)?));
+                }
+                Ok(tags)
+            }
+            QuarkStore::Memory { tags, .. } => Ok(tags
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|((tag_namespace, _), _)| tag_namespace == namespace)
+                .map(|((_, name), commit_id)| (name.clone(), *commit_id))
+                .collect()),
+        }
+    }
+
+    pub async fn tag_target(&self, namespace: &str, name: &str) -> Result<Option<CommitId>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let Some(row) = session
+                    .execute(&session.statements()?.select_tag, (namespace, name))
+                    .await?
+                    .maybe_first_row()?
+                else {
+                    return Ok(None);
+                };
+                let commit_id = row.columns[0]
+                    .as_ref()
+                    .and_then(|value| value.clone().into_string())
+                    .with_context(|| "Failed to deserialize commit id")?;
+                Ok(Some(Id::try_from(commit_id)?))
+            }
+            QuarkStore::Memory { tags, .. } => Ok(tags
+                .lock()
+                .unwrap()
+                .get(&(namespace.to_string(), name.to_string()))
+                .copied()),
+        }
+    }
+
+    /// Stores the tag, unless a tag with the same name already exists in the namespace.
+    pub async fn insert_tag(&self, namespace: &str, name: &str, commit_id: CommitId) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                session
+                    .execute(
+                        &session.statements()?.insert_tag,
+                        (namespace, name, commit_id.as_str()),
+                    )
+                    .await?;
+                Ok(())
+            }
+            QuarkStore::Memory { tags, .. } => {
+                tags.lock()
+                    .unwrap()
+                    .entry((namespace.to_string(), name.to_string()))
+                    .or_insert(commit_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the names of all namespaces which contain commits.
+    pub async fn namespaces(&self) -> Result<Vec<String>> {
+        match self {
+            QuarkStore::Scylla(session) => session
+                .execute_all(&session.statements()?.select_namespaces, ())
+                .await?
+                .into_iter()
+                .map(|row| {
+                    row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize namespace")
+                })
+                .collect(),
+            QuarkStore::Memory {
+                namespace_commits, ..
+            } => Ok(namespace_commits
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, commits)| !commits.is_empty())
+                .map(|(namespace, _)| namespace.clone())
+                .collect()),
+        }
+    }
+
+    /// Deletes the commit from the given namespace, or from the stores without a namespace.
+    pub async fn remove_commit(&self, namespace: Option<&str>, commit_id: CommitId) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let statements = session.statements()?;
+                match namespace {
+                    Some(namespace) => {
+                        session
+                            .execute(
+                                &statements.delete_namespace_commit,
+                                (namespace, commit_id.as_str()),
+                            )
+                            .await?
+                    }
+                    None => {
+                        session
+                            .execute(&statements.delete_commit, (commit_id.as_str(),))
+                            .await?
+                    }
+                };
+                Ok(())
+            }
+            QuarkStore::Memory {
+                commits,
+                namespace_commits,
+                ..
+            } => {
+                let mut commits = commits.lock().unwrap();
+                let mut namespace_commits = namespace_commits.lock().unwrap();
+                memory_partition(&mut commits, &mut namespace_commits, namespace)
+                    .remove(&commit_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the id and the object of every ref in the keyspace, which are shared by all
+    /// namespaces.
+    pub async fn ref_objects(&self) -> Result<Vec<(u64, ObjectRef)>> {
+        match self {
+            QuarkStore::Scylla(session) => session
+                .execute_all(&session.statements()?.select_refs, ())
+                .await?
+                .into_iter()
+                .map(|row| {
+                    let id = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.as_bigint())
+                        .with_context(|| "Failed to deserialize ref id")?;
+                    let object_ref = row.columns[1]
+                        .as_ref()
+                        .and_then(|value| value.as_bigint())
+                        .with_context(|| "Failed to deserialize object ref")?;
+                    Ok((id as u64, object_ref as ObjectRef))
+                })
+                .collect(),
+            QuarkStore::Memory { refs, .. } => Ok(refs
+                .lock()
+                .unwrap()
+                .values()
+                .map(|reference| (reference.id, reference.object_ref))
+                .collect()),
+        }
+    }
+
+    pub async fn remove_refs(&self, ids: &[u64]) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                for chunk in ids.chunks(BATCH_SIZE) {
+                    let values = chunk.iter().map(|id| (*id as i64,)).collect::<Vec<_>>();
+                    session
+                        .execute_batch(&session.statements()?.delete_ref, values)
+                        .await?;
                 }
-
-                if let Some(instant) = instant {
-                    log::debug!(
-                        "Inserting {} objects took {:?}",
-                        total_objects,
-                        instant.elapsed()
-                    );
+                if let Some(cache) = session.cache() {
+                    ids.iter().for_each(|id| cache.remove_ref(*id));
                 }
-
-                Ok(hashes)
+                Ok(())
             }
-            #[cfg(test)]
-            QuarkStore::Fake { .. } => {
-                let mut hashes = Vec::new();
-                for object in objects {
-                    hashes.push(self.insert_object(object).await?);
+            QuarkStore::Memory { refs, .. } => {
+                let mut refs = refs.lock().unwrap();
+                for id in ids {
+                    refs.remove(id);
                 }
-                Ok(hashes)
+                Ok(())
             }
         }
     }
 
-    async fn insert_object<T: Hash + Encode<Binary>>(&self, object: &T) -> Result<u64> {
+    pub async fn remove_objects(&self, ids: &[ObjectRef]) -> Result<()> {
         match self {
             QuarkStore::Scylla(session) => {
-                let mut instant = None;
-                if log_enabled!(log::Level::Debug) {
-                    instant = Some(Instant::now());
+                for chunk in ids.chunks(BATCH_SIZE) {
+                    let values = chunk.iter().map(|id| (*id as i64,)).collect::<Vec<_>>();
+                    session
+                        .execute_batch(&session.statements()?.delete_object, values)
+                        .await?;
                 }
-
-                let mut data = Vec::new();
-                ENCODING
-                    .encode(&mut data, object)
-                    .with_context(|| "Failed to serialize object")?;
-
-                let mut hasher = std::hash::DefaultHasher::new();
-                object.hash(&mut hasher);
-                let hash = hasher.finish();
-
-                session
-                    .query(
-                        format!(
-                            "INSERT INTO {} (id, object) VALUES (?, ?)",
-                            session.table_name(OBJECT_TABLE_NAME)
-                        ),
-                        (hash as i64, data),
-                    )
-                    .await?;
-
-                if let Some(instant) = instant {
-                    log::debug!("Inserting object took {:?}", instant.elapsed());
+                if let Some(cache) = session.cache() {
+                    ids.iter().for_each(|id| cache.remove_object(*id));
                 }
-
-                Ok(hash)
+                Ok(())
             }
-            #[cfg(test)]
-            QuarkStore::Fake { objects, .. } => {
-                let mut hasher = std::hash::DefaultHasher::new();
-                object.hash(&mut hasher);
-                let hash = hasher.finish();
-
-                let mut bytes = Vec::new();
-                ENCODING
-                    .encode(&mut bytes, object)
-                    .with_context(|| "Failed to serialize object")?;
-
-                let mut objects = objects.borrow_mut();
-                objects.insert(hash, bytes);
-                Ok(hash)
+            QuarkStore::Memory { objects, .. } => {
+                let mut objects = objects.lock().unwrap();
+                for id in ids {
+                    objects.remove(id);
+                }
+                Ok(())
             }
         }
     }
-}
 
-impl RefStore for QuarkStore {
-    async fn resolve<T: Deserialize>(&self, root: u64) -> Result<Option<T>> {
-        let mut instant = None;
-        if log_enabled!(log::Level::Debug) {
-            instant = Some(Instant::now());
-        }
-        log::debug!("Resolving object with root id {root}");
-        let Some(root) = self.resolve_ref(Some(root)).await? else {
-            return Ok(None);
+    /// Checks that the root can be read as `T`, see [`TypeTag::verify`]. Successful checks are
+    /// remembered by the cache, so repeated reads of the same root do not query its type tags.
+    async fn verify_root_type<T: Tagged>(&self, root: u64) -> Result<()> {
+        let cache = match self {
+            QuarkStore::Scylla(session) => session.cache(),
+            QuarkStore::Memory { .. } => None,
         };
-        let cx = DeserializeCx { store: self };
-        let result = T::deserialize(root, cx).await?;
-        if log_enabled!(log::Level::Debug) {
-            if let Some(elapsed) = instant.map(|i| i.elapsed()) {
-                log::debug!("Resolving object took {} ms", elapsed.as_millis());
-            }
+        let expected = T::type_tag();
+        if cache.is_some_and(|cache| cache.contains_root_type(root, expected.fingerprint)) {
+            return Ok(());
         }
-        Ok(Some(result))
-    }
 
-    async fn insert<T: Serialize>(&self, object: &T) -> Result<u64> {
-        let mut insert_versioned_time = None;
-        if log_enabled!(log::Level::Debug) {
-            insert_versioned_time = Some(Instant::now());
-        }
-        let cx = SerializeCx { store: self };
-        let references = object.serialize(cx).await?;
-        log::debug!("Inserting object with {} references", references.len());
-        let mut reference_time = None;
-        if log_enabled!(log::Level::Debug) {
-            reference_time = Some(Instant::now());
+        let committed = self.root_type_tags(root).await?;
+        TypeTag::verify::<T>(root, &committed)?;
+        if let Some(cache) = cache.filter(|_| committed.contains(&expected)) {
+            cache.insert_root_type(root, expected.fingerprint);
         }
+        Ok(())
+    }
 
-        let root_ref_id = references.last().with_context(|| "Structure is empty")?.id;
-
-        let root_ref = match self {
+    /// Returns the type tags of all commits which point to the given root ref.
+    pub async fn root_type_tags(&self, root_ref: u64) -> Result<Vec<TypeTag>> {
+        match self {
             QuarkStore::Scylla(session) => {
-                let prepared: PreparedStatement = session
-                    .raw()
-                    .prepare(format!(
-                        "INSERT INTO {} (id, left, right, object_ref) VALUES (?, ?, ?, ?)",
-                        session.table_name(REF_TABLE_NAME)
-                    ))
-                    .await?;
+                let mut tags = Vec::new();
+                for row in session
+                    .execute_all(&session.statements()?.select_root_types, (root_ref as i64,))
+                    .await?
+                {
+                    let name = row.columns[0]
+                        .as_ref()
+                        .and_then(|value| value.clone().into_string())
+                        .with_context(|| "Failed to deserialize type name")?;
+                    let fingerprint = row.columns[1]
+                        .as_ref()
+                        .and_then(|value| value.as_bigint())
+                        .with_context(|| "Failed to deserialize schema fingerprint")?;
+                    tags.push(TypeTag::with_fingerprint(name, fingerprint as u64));
+                }
+                Ok(tags)
+            }
+            QuarkStore::Memory { root_types, .. } => Ok(root_types
+                .lock()
+                .unwrap()
+                .get(&root_ref)
+                .cloned()
+                .unwrap_or_default()),
+        }
+    }
 
-                let total_refs = references.len();
-                for chunk in (0..total_refs).step_by(BATCH_SIZE) {
-                    let end = std::cmp::min(chunk + BATCH_SIZE, total_refs);
-                    let mut batch = Batch::default();
-                    let mut values = Vec::with_capacity(end - chunk);
+    /// Inserts an existing commit into the namespace of the store without changing the head of
+    /// any replica.
+    pub async fn insert_commit(&self, commit: &Commit) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                let mut version_bytes = Vec::new();
+                ENCODING
+                    .encode(&mut version_bytes, &commit.version)
+                    .with_context(|| "Failed to serialize version")?;
 
-                    for reference in &references[chunk..end] {
-                        batch.append_statement(prepared.clone());
-                        values.push((
-                            reference.id as i64,
-                            reference.left.map(|id| id as i64),
-                            reference.right.map(|id| id as i64),
-                            reference.object_ref as i64,
-                        ));
+                let values = (
+                    commit.id.as_str(),
+                    &version_bytes,
+                    commit.root_ref as i64,
+                    commit.parent_commit_id.map(|id| id.to_string()),
+                    commit.type_tag.as_ref().map(|tag| tag.name.as_str()),
+                    commit.type_tag.as_ref().map(|tag| tag.fingerprint as i64),
+                    commit.metadata.author.as_deref(),
+                    commit.metadata.timestamp.map(|time| time.wall_ms as i64),
+                    commit.metadata.timestamp.map(|time| time.logical as i32),
+                    commit.metadata.message.as_deref(),
+                    &commit.metadata.annotations,
+                    commit
+                        .signature
+                        .as_ref()
+                        .map(|signature| signature.signer.as_str()),
+                    commit
+                        .signature
+                        .as_ref()
+                        .map(|signature| signature.bytes.as_slice()),
+                    commit
+                        .merge_parent_ids
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>(),
+                );
+                let statements = session.statements()?;
+                match session.namespace() {
+                    Some(namespace) => {
+                        let (
+                            id,
+                            version,
+                            root_ref,
+                            parent_commit_id,
+                            type_name,
+                            fingerprint,
+                            author,
+                            wall_time,
+                            logical_time,
+                            message,
+                            annotations,
+                            signer,
+                            signature,
+                            merge_parent_ids,
+                        ) = values;
+                        session
+                            .execute(
+                                &statements.insert_namespace_commit,
+                                (
+                                    namespace,
+                                    id,
+                                    version,
+                                    root_ref,
+                                    parent_commit_id,
+                                    type_name,
+                                    fingerprint,
+                                    author,
+                                    wall_time,
+                                    logical_time,
+                                    message,
+                                    annotations,
+                                    signer,
+                                    signature,
+                                    merge_parent_ids,
+                                ),
+                            )
+                            .await?
                     }
+                    None => session.execute(&statements.insert_commit, values).await?,
+                };
 
-                    session.raw().batch(&batch, &values).await?;
+                if let Some(type_tag) = &commit.type_tag {
+                    session
+                        .execute(
+                            &session.statements()?.insert_root_type,
+                            (
+                                commit.root_ref as i64,
+                                type_tag.name.as_str(),
+                                type_tag.fingerprint as i64,
+                            ),
+                        )
+                        .await?;
                 }
-
-                root_ref_id
+                Ok(())
             }
-            #[cfg(test)]
-            QuarkStore::Fake { refs, .. } => {
-                let mut refs = refs.borrow_mut();
-                for reference in references {
-                    refs.insert(reference.id, reference);
+            QuarkStore::Memory {
+                commits,
+                namespace_commits,
+                root_types,
+                namespace,
+                ..
+            } => {
+                {
+                    let mut commits = commits.lock().unwrap();
+                    let mut namespace_commits = namespace_commits.lock().unwrap();
+                    memory_partition(&mut commits, &mut namespace_commits, namespace.as_deref())
+                        .insert(commit.id, commit.clone());
                 }
-                root_ref_id
-            }
-        };
-
-        if log_enabled!(log::Level::Debug) {
-            if let Some(elapsed) = reference_time.map(|i| i.elapsed()) {
-                log::debug!("Inserting references took {} ms", elapsed.as_millis());
-            }
-            if let Some(elapsed) = insert_versioned_time.map(|i| i.elapsed()) {
-                log::debug!("Inserting versioned object took {} ms", elapsed.as_millis());
+                if let Some(type_tag) = &commit.type_tag {
+                    let mut root_types = root_types.lock().unwrap();
+                    let tags = root_types.entry(commit.root_ref).or_default();
+                    if !tags.contains(type_tag) {
+                        tags.push(type_tag.clone());
+                    }
+                }
+                Ok(())
             }
         }
-
-        Ok(root_ref)
-    }
-}
-
-pub struct SerializeCx<'a> {
-    store: &'a QuarkStore,
-}
-
-impl SerializeCx<'_> {
-    pub async fn insert_object<T: Hash + Encode<Binary>>(&self, object: &T) -> Result<u64> {
-        self.store.insert_object(object).await
     }
 
-    pub async fn insert_objects<'a, T: 'a + Hash + Encode<Binary>>(
-        &self,
-        objects: &[&'a T],
-    ) -> Result<Vec<u64>> {
-        self.store.insert_objects(objects).await
-    }
+    pub async fn insert_refs(&self, references: &[Ref]) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                for chunk in references.chunks(BATCH_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|reference| {
+                            (
+                                reference.id as i64,
+                                reference.left.map(|id| id as i64),
+                                reference.right.map(|id| id as i64),
+                                reference.object_ref as i64,
+                            )
+                        })
+                        .collect::<Vec<_>>();
 
-    pub async fn serialize_iter<'a, T: 'a + Hash + Encode<Binary>>(
-        &self,
-        iter: impl Iterator<Item = &'a T>,
-    ) -> Result<Vec<Ref>> {
-        let mut prev_hash: Option<u64> = None;
-        let objects = iter.collect::<Vec<_>>();
-        let mut refs = Vec::with_capacity(objects.len());
-        let object_refs = self.insert_objects(&objects).await?;
-        for object_ref in object_refs {
-            let reference = Ref::compute(object_ref, prev_hash, None);
-            prev_hash = Some(reference.id);
-            refs.push(reference);
+                    session
+                        .execute_batch(&session.statements()?.insert_ref, values)
+                        .await?;
+                }
+                Ok(())
+            }
+            QuarkStore::Memory { refs, .. } => {
+                let mut refs = refs.lock().unwrap();
+                for reference in references {
+                    refs.insert(reference.id, reference.clone());
+                }
+                Ok(())
+            }
         }
-        Ok(refs)
-    }
-}
-
-pub struct DeserializeCx<'a> {
-    store: &'a QuarkStore,
-}
-
-impl DeserializeCx<'_> {
-    pub async fn resolve_object<T: Hash + DecodeOwned<Binary>>(
-        &self,
-        id: u64,
-    ) -> Result<Option<T>> {
-        self.store.resolve_object(id).await
     }
 
-    pub async fn resolve_objects<T: Hash + DecodeOwned<Binary>>(
-        &self,
-        ids: &[u64],
-    ) -> Result<Vec<Option<T>>> {
-        self.store.resolve_objects(ids).await
-    }
-
-    pub async fn resolve_ref(&self, id: Option<u64>) -> Result<Option<Ref>> {
-        self.store.resolve_ref(id).await
-    }
+    /// Returns the encoded bytes of the object, without decoding it.
+    pub async fn resolve_object_bytes(&self, id: ObjectRef) -> Result<Option<Vec<u8>>> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                if let Some(bytes) = session.cache().and_then(|cache| cache.get_object(id)) {
+                    return Ok(Some(bytes.to_vec()));
+                }
 
-    pub async fn deserialize_iter<T: Hash + DecodeOwned<Binary>>(
-        &self,
-        root: Ref,
-    ) -> Result<Vec<T>> {
-        let mut items = Vec::new();
-        items.push(root.object_ref);
-        let mut node = root;
-        while let Some(new_node) = self.resolve_ref(node.left).await? {
-            items.push(new_node.object_ref);
-            node = new_node
+                let Some(row) = session
+                    .execute(&session.statements()?.select_object, (id as i64,))
+                    .await?
+                    .maybe_first_row()?
+                else {
+                    return Ok(None);
+                };
+                let bytes = row.columns[0]
+                    .as_ref()
+                    .and_then(|value| value.clone().into_blob())
+                    .with_context(|| "Failed to deserialize value")?;
+                Ok(Some(bytes))
+            }
+            QuarkStore::Memory { objects, .. } => Ok(objects.lock().unwrap().get(&id).cloned()),
         }
-        items.reverse();
+    }
 
-        let objects = self.resolve_objects::<T>(&items).await?;
-        let mut objects_only = Vec::with_capacity(objects.len());
-        for object in objects {
-            let Some(object) = object else {
-                log::debug!("Object not found for ids: {:?}", &items);
-                self.store.dump().await?;
-                return Err(anyhow!("Object not found"));
-            };
-            objects_only.push(object);
+    /// Inserts already encoded objects under the given ids and evicts them from the cache.
+    pub async fn insert_object_bytes(&self, objects: &[(ObjectRef, Vec<u8>)]) -> Result<()> {
+        match self {
+            QuarkStore::Scylla(session) => {
+                for chunk in objects.chunks(BATCH_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|(id, bytes)| (*id as i64, bytes))
+                        .collect::<Vec<_>>();
+                    session
+                        .execute_batch(&session.statements()?.insert_object, values)
+                        .await?;
+                }
+                // The bytes of an object can be replaced by a newer version of it.
+                if let Some(cache) = session.cache() {
+                    objects.iter().for_each(|(id, _)| cache.remove_object(*id));
+                }
+                Ok(())
+            }
+            QuarkStore::Memory {
+                objects: stored_objects,
+                ..
+            } => {
+                let mut stored_objects = stored_objects.lock().unwrap();
+                for (id, bytes) in objects {
+                    stored_objects.insert(*id, bytes.clone());
+                }
+                Ok(())
+            }
         }
-        Ok(objects_only)
     }
 }
 
@@ -1001,9 +2776,8 @@ impl QuarkStore {
                     log::debug!("{} {:?}", id, latest_commit_id);
                 }
             }
-            #[cfg(test)]
-            QuarkStore::Fake { replicas, .. } => {
-                let replicas = replicas.borrow();
+            QuarkStore::Memory { replicas, .. } => {
+                let replicas = replicas.lock().unwrap();
                 for (id, commit) in replicas.iter() {
                     log::debug!("{} {}", id, commit);
                 }
@@ -1055,9 +2829,8 @@ impl QuarkStore {
                     );
                 }
             }
-            #[cfg(test)]
-            QuarkStore::Fake { commits, .. } => {
-                let commits = commits.borrow();
+            QuarkStore::Memory { commits, .. } => {
+                let commits = commits.lock().unwrap();
                 for (id, commit) in commits.iter() {
                     log::debug!("{} {:?} {:?}", id, commit.version, commit.parent_commit_id);
                 }
@@ -1101,9 +2874,8 @@ impl QuarkStore {
                     );
                 }
             }
-            #[cfg(test)]
-            QuarkStore::Fake { refs, .. } => {
-                let refs = refs.borrow();
+            QuarkStore::Memory { refs, .. } => {
+                let refs = refs.lock().unwrap();
                 for (id, ref_) in refs.iter() {
                     log::debug!(
                         "ID: {}, Left: {:?}, Right: {:?}, Object Ref: {}",
@@ -1138,9 +2910,8 @@ impl QuarkStore {
                     log::debug!("{}", id as u64);
                 }
             }
-            #[cfg(test)]
-            QuarkStore::Fake { objects, .. } => {
-                let objects = objects.borrow();
+            QuarkStore::Memory { objects, .. } => {
+                let objects = objects.lock().unwrap();
                 for (id, _) in objects.iter() {
                     log::debug!("{}", id);
                 }
@@ -1178,17 +2949,17 @@ impl QuarkStore {
                     replicas,
                 }
             }
-            #[cfg(test)]
-            QuarkStore::Fake {
+            QuarkStore::Memory {
                 commits,
                 objects,
                 refs,
                 replicas,
+                ..
             } => TableCounts {
-                commits: commits.borrow().len() as u64,
-                objects: objects.borrow().len() as u64,
-                refs: refs.borrow().len() as u64,
-                replicas: replicas.borrow().len() as u64,
+                commits: commits.lock().unwrap().len() as u64,
+                objects: objects.lock().unwrap().len() as u64,
+                refs: refs.lock().unwrap().len() as u64,
+                replicas: replicas.lock().unwrap().len() as u64,
             },
         };
 
@@ -1219,6 +2990,16 @@ impl QuarkStore {
                     OBJECT_TABLE_NAME,
                     REF_TABLE_NAME,
                     REPLICA_TABLE_NAME,
+                    REMOTE_HEAD_TABLE_NAME,
+                    ROOT_TYPE_TABLE_NAME,
+                    REPLICA_KEY_TABLE_NAME,
+                    REPLICA_TYPE_TABLE_NAME,
+                    REPLICA_MEMBERSHIP_TABLE_NAME,
+                    BRANCH_TABLE_NAME,
+                    TAG_TABLE_NAME,
+                    NAMESPACE_COMMIT_TABLE_NAME,
+                    NAMESPACE_REPLICA_TABLE_NAME,
+                    SCHEMA_TABLE_NAME,
                 ];
 
                 for table_name in table_names {
@@ -1226,43 +3007,61 @@ impl QuarkStore {
                     session.query(format!("DROP TABLE {}", table), ()).await?;
                 }
             }
-            #[cfg(test)]
-            QuarkStore::Fake {
+            QuarkStore::Memory {
                 commits,
                 objects,
                 refs,
                 replicas,
+                replica_types,
+                memberships,
+                root_types,
+                replica_keys,
+                remote_heads,
+                branches,
+                tags,
+                namespace_commits,
+                namespace_replicas,
+                ..
             } => {
-                commits.borrow_mut().clear();
-                objects.borrow_mut().clear();
-                refs.borrow_mut().clear();
-                replicas.borrow_mut().clear();
+                commits.lock().unwrap().clear();
+                objects.lock().unwrap().clear();
+                refs.lock().unwrap().clear();
+                replicas.lock().unwrap().clear();
+                replica_types.lock().unwrap().clear();
+                memberships.lock().unwrap().clear();
+                root_types.lock().unwrap().clear();
+                replica_keys.lock().unwrap().clear();
+                remote_heads.lock().unwrap().clear();
+                branches.lock().unwrap().clear();
+                tags.lock().unwrap().clear();
+                namespace_commits.lock().unwrap().clear();
+                namespace_replicas.lock().unwrap().clear();
             }
         }
         Ok(())
     }
 }
 
-impl<T: MrdtItem> Serialize for HashSet<T> {
+impl<T: MrdtItem + ItemName> Serialize for HashSet<T> {
     async fn serialize(&self, cx: SerializeCx<'_>) -> Result<Vec<Ref>> {
         cx.serialize_iter(self.iter()).await
     }
 }
 
-impl<T: MrdtItem> Deserialize for HashSet<T> {
+impl<T: MrdtItem + ItemName> Deserialize for HashSet<T> {
     async fn deserialize(root: Ref, cx: DeserializeCx<'_>) -> Result<Self> {
         let items = cx.deserialize_iter(root).await?;
         Ok(items.into_iter().collect())
     }
 }
 
-impl<T: MrdtItem> Serialize for Vec<T> {
+impl<T: MrdtItem + ItemName> Serialize for Vec<T> {
     async fn serialize(&self, cx: SerializeCx<'_>) -> Result<Vec<Ref>> {
         cx.serialize_iter(self.iter()).await
     }
 }
 
-impl<T: MrdtItem> Deserialize for Vec<T> {
+impl<T: MrdtItem + ItemName> Deserialize for Vec<T> {
     async fn deserialize(root: Ref, cx: DeserializeCx<'_>) -> Result<Self> {
         let items = cx.deserialize_iter(root).await?;
         Ok(items.into_iter().collect())
@@ -1280,6 +3079,12 @@ mod tests {
         items: Vec<String>,
     }
 
+    impl Tagged for List {
+        fn type_tag() -> TypeTag {
+            TypeTag::new("List")
+        }
+    }
+
     impl Serialize for List {
         async fn serialize(&self, cx: SerializeCx<'_>) -> Result<Vec<Ref>> {
             let mut refs = Vec::with_capacity(self.items.len());
@@ -1328,6 +3133,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_replication_to_cql() {
+        assert_eq!(
+            Replication::Simple {
+                replication_factor: 3
+            }
+            .to_cql(),
+            "{'class' : 'SimpleStrategy', 'replication_factor' : 3}"
+        );
+        assert_eq!(
+            Replication::default().to_cql(),
+            "{'class' : 'NetworkTopologyStrategy', 'replication_factor' : 1}"
+        );
+        assert_eq!(
+            Replication::Datacenters(vec![("dc1".into(), 3), ("dc2".into(), 2)]).to_cql(),
+            "{'class' : 'NetworkTopologyStrategy', 'dc1' : 3, 'dc2' : 2}"
+        );
+    }
+
     #[tokio::test]
     async fn test_serialize_deserialize() {
         let list = List {
@@ -1343,10 +3167,95 @@ mod tests {
         }
 
         let root = refs.last().cloned().unwrap();
-        let deserialized = List::deserialize(root, DeserializeCx { store: &store })
+        let deserialized = List::deserialize(
+            root,
+            DeserializeCx {
+                store: &store,
+                visited: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(deserialized, list);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_checks_type_tag() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&vec![1u64]).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
             .await
             .unwrap();
 
-        assert_eq!(deserialized, list);
+        let mut replica = crate::Replica::clone(replica_id, store).await.unwrap();
+        let commit = replica.commit_object(&vec![1u64, 2]).await.unwrap();
+        assert_eq!(commit.type_tag, Some(<Vec<u64>>::type_tag()));
+
+        let list: Vec<u64> = replica.latest_object().await.unwrap().unwrap();
+        assert_eq!(list, vec![1, 2]);
+        let error = replica.latest_object::<HashSet<u64>>().await.unwrap_err();
+        assert!(error.to_string().starts_with("Type mismatch"));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Scylla node, configured with the SCYLLA_* variables"]
+    async fn test_scans_read_all_pages() {
+        let mut config = QuarkStoreConfig::from_env().unwrap();
+        config.table_prefix = format!("paging_{}_", Id::gen()).to_lowercase();
+        config.session.page_size = 10;
+        let store = QuarkStore::connect(config).await.unwrap();
+
+        let root = store.insert(&vec![1u64]).await.unwrap();
+        for _ in 0..25 {
+            store
+                .commit(Id::gen(), VectorClock::default(), root)
+                .await
+                .unwrap();
+        }
+        let commits = store.commits().await.unwrap().len();
+        let heads = store.replica_heads().await.unwrap().len();
+        store.reset_db().await.unwrap();
+        assert_eq!((commits, heads), (25, 25));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Scylla node, configured with the SCYLLA_* variables"]
+    async fn test_cache_serves_repeated_reads() {
+        let mut config = QuarkStoreConfig::from_env().unwrap();
+        config.table_prefix = format!("cache_{}_", Id::gen()).to_lowercase();
+        let store = QuarkStore::connect(config)
+            .await
+            .unwrap()
+            .with_cache(CacheConfig::default());
+
+        let root = store.insert(&vec![1u64, 2, 3]).await.unwrap();
+        let first: Option<Vec<u64>> = store.resolve(root).await.unwrap();
+        let after_first = store.cache_stats().unwrap();
+        let second: Option<Vec<u64>> = store.resolve(root).await.unwrap();
+        let after_second = store.cache_stats().unwrap();
+
+        // Objects which are replaced or removed are evicted, so no stale bytes are returned.
+        let object_ref = store.insert_object(&4u64).await.unwrap();
+        store.resolve_object::<u64>(object_ref).await.unwrap();
+        let replaced = store.migrations().encode(&5u64).unwrap();
+        store
+            .insert_object_bytes(&[(object_ref, replaced.clone())])
+            .await
+            .unwrap();
+        let read_replaced = store.resolve_object_bytes(object_ref).await.unwrap();
+        store.remove_objects(&[object_ref]).await.unwrap();
+        let read_removed = store.resolve_object_bytes(object_ref).await.unwrap();
+        store.reset_db().await.unwrap();
+
+        assert_eq!(first, Some(vec![1, 2, 3]));
+        assert_eq!(second, first);
+        assert!(after_first.misses > 0);
+        assert_eq!(after_second.misses, after_first.misses);
+        assert!(after_second.hits > after_first.hits);
+        assert_eq!(read_replaced, Some(replaced));
+        assert_eq!(read_removed, None);
     }
 }
@@ -1,3 +1,5 @@
+use std::{future::Future, pin::Pin};
+
 use super::*;
 
 pub type ReplicaId = Id;
@@ -6,6 +8,10 @@ pub struct Replica {
     id: ReplicaId,
     store: QuarkStore,
     latest_commit: Commit,
+    offline: Option<OfflineState>,
+    signing_key: Option<SigningKey>,
+    /// The branch new commits are added to, see [`Replica::checkout`].
+    branch: String,
 }
 
 impl Replica {
@@ -15,19 +21,136 @@ impl Replica {
             id,
             store,
             latest_commit,
+            offline: None,
+            signing_key: None,
+            branch: DEFAULT_BRANCH.to_string(),
+        })
+    }
+
+    /// Opens the replica at its latest commit, or clones it if the store has no head for it yet.
+    /// Unlike [`Replica::clone`], the head of an existing replica is left where it is.
+    pub async fn open(id: ReplicaId, store: QuarkStore) -> Result<Self> {
+        let Some(latest_commit) = store.latest_commit_for_replica(id).await? else {
+            return Self::clone(id, store).await;
+        };
+        Ok(Self {
+            id,
+            store,
+            latest_commit,
+            offline: None,
+            signing_key: None,
+            branch: DEFAULT_BRANCH.to_string(),
         })
     }
 
+    /// Creates a replica which commits to the given local store and synchronizes it with the
+    /// upstream whenever the upstream is reachable. Commits are pushed right away while the
+    /// upstream is reachable. Once it is not, they are queued and pushed with the next
+    /// successful [`Replica::sync_upstream`].
+    ///
+    /// The local store keeps the commits until they reached the upstream, so it has to be durable
+    /// if they must survive a restart, see [`QuarkStore::save_snapshot`].
+    pub async fn offline_first(
+        id: ReplicaId,
+        local: QuarkStore,
+        upstream: Upstream,
+        remote_name: impl Into<String>,
+    ) -> Result<Self> {
+        let mut offline = OfflineState::new(id, upstream, remote_name);
+        if let Err(err) = offline.sync(&local).await {
+            log::info!("Upstream of replica {id} is unreachable, starting offline: {err:#}");
+        }
+
+        let latest_commit = match local.latest_commit_for_replica(id).await? {
+            Some(commit) => commit,
+            None => local.clone(id).await?,
+        };
+        Ok(Self {
+            id,
+            store: local,
+            latest_commit,
+            offline: Some(offline),
+            signing_key: None,
+            branch: DEFAULT_BRANCH.to_string(),
+        })
+    }
+
+    /// Synchronizes the local store of an offline-first replica with its upstream. Afterwards
+    /// the commits of other replicas can be merged with [`Replica::merge_with`].
+    pub async fn sync_upstream(&mut self) -> Result<UpstreamSync> {
+        let offline = self
+            .offline
+            .as_mut()
+            .with_context(|| "Replica has no upstream")?;
+        let sync = offline.sync(&self.store).await?;
+
+        if let Some(commit) = self.store.latest_commit_for_replica(self.id).await? {
+            self.latest_commit = commit;
+        }
+        Ok(sync)
+    }
+
+    /// Returns the commits which have not been pushed to the upstream yet.
+    pub fn pending_commits(&self) -> &[CommitId] {
+        self.offline
+            .as_ref()
+            .map_or(&[], |offline| offline.pending())
+    }
+
+    /// Signs all future commits of the replica with the key, after registering its public key in
+    /// the store.
+    pub async fn set_signing_key(&mut self, key: SigningKey) -> Result<()> {
+        self.store
+            .register_public_key(self.id, &key.verifying_key())
+            .await?;
+        self.signing_key = Some(key);
+        Ok(())
+    }
+
     /// Returns the underlying store of the replica.
     pub fn store(&self) -> &QuarkStore {
         &self.store
     }
 
+    /// Closes the replica and returns its store.
+    pub fn into_store(self) -> QuarkStore {
+        self.store
+    }
+
     /// Returns the identifier of the replica.
     pub fn id(&self) -> ReplicaId {
         self.id
     }
 
+    /// Returns the branch the replica currently commits to.
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// Creates a branch which starts at the latest commit of the current branch.
+    pub async fn create_branch(&self, name: &str) -> Result<()> {
+        self.store
+            .create_branch(self.id, name, self.latest_commit.id)
+            .await
+    }
+
+    /// Switches to the given branch, so the latest commit is the head of that branch and new
+    /// commits are added to it.
+    pub async fn checkout(&mut self, name: &str) -> Result<()> {
+        self.latest_commit = self
+            .store
+            .branch_head(self.id, name)
+            .await?
+            .with_context(|| format!("Branch {name} does not exist"))?;
+        self.branch = name.to_string();
+        Ok(())
+    }
+
+    /// Tags the latest commit of the current branch.
+    pub async fn tag(&self, name: &str) -> Result<()> {
+        self.store.create_tag(name, self.latest_commit.id).await
+    }
+
     /// Returns a reference to the latest commit of the replica.
     pub fn latest_commit(&self) -> &Commit {
         &self.latest_commit
@@ -45,14 +168,70 @@ impl Replica {
 
     /// Commits the given object to the store and returns the resulting commit.
     pub async fn commit_object<T: Serialize>(&mut self, object: &T) -> Result<Commit> {
+        self.commit_with(object, CommitMetadata::default()).await
+    }
+
+    /// Like [`Replica::commit_object`], but records the given metadata with the commit. The
+    /// timestamp is taken from the clock of the replica, unless the metadata contains one.
+    pub async fn commit_with<T: Serialize>(
+        &mut self,
+        object: &T,
+        metadata: CommitMetadata,
+    ) -> Result<Commit> {
         let object_ref = self.store.insert(object).await?;
-        self.commit(object_ref, self.next_version()).await
+        self.commit_inner(
+            object_ref,
+            self.next_version()?,
+            &[],
+            Some(T::type_tag()),
+            metadata,
+        )
+        .await
     }
 
     /// Commits the given object reference to the store and returns the resulting commit.
     pub async fn commit(&mut self, object_ref: ObjectRef, version: VectorClock) -> Result<Commit> {
-        let commit = self.store.commit(self.id, version, object_ref).await?;
+        self.commit_inner(object_ref, version, &[], None, CommitMetadata::default())
+            .await
+    }
+
+    async fn commit_inner(
+        &mut self,
+        object_ref: ObjectRef,
+        version: VectorClock,
+        merge_parent_ids: &[CommitId],
+        type_tag: Option<TypeTag>,
+        mut metadata: CommitMetadata,
+    ) -> Result<Commit> {
+        if metadata.timestamp.is_none() {
+            metadata.timestamp = Some(HybridTimestamp::next(self.latest_commit.metadata.timestamp));
+        }
+        let commit = self
+            .store
+            .commit_to_branch(
+                self.id,
+                &self.branch,
+                version,
+                object_ref,
+                CommitOptions {
+                    merge_parent_ids: merge_parent_ids.to_vec(),
+                    type_tag,
+                    metadata,
+                    signing_key: self.signing_key.as_ref(),
+                },
+            )
+            .await?;
         self.latest_commit = commit.clone();
+
+        if let Some(offline) = &mut self.offline {
+            if let Err(err) = offline.enqueue(&self.store, commit.id).await {
+                log::debug!(
+                    "Upstream of replica {} is unreachable, {} commits are queued: {err:#}",
+                    self.id,
+                    offline.pending().len()
+                );
+            }
+        }
         Ok(commit)
     }
 
@@ -61,43 +240,413 @@ impl Replica {
         &mut self,
         other_replica: ReplicaId,
     ) -> Result<(Commit, T)> {
+        let commit_to_merge_with = self.head_to_merge_with(other_replica).await?;
+        self.merge_commit(commit_to_merge_with, format!("Merge with {other_replica}"))
+            .await
+    }
+
+    /// Returns the head of the other replica, after checking that it may be merged.
+    pub(crate) async fn head_to_merge_with(&self, other_replica: ReplicaId) -> Result<Commit> {
         let commit_to_merge_with = self
             .store
             .latest_commit_for_replica(other_replica)
             .await?
             .with_context(|| "Replica has no commits")?;
+        self.store
+            .check_commit(other_replica, &commit_to_merge_with)
+            .await?;
+        Ok(commit_to_merge_with)
+    }
+
+    /// Merges another branch of the replica into the current branch and commits the merged
+    /// object to the current branch.
+    pub async fn merge_branch<T: Serialize + Deserialize + Mergeable>(
+        &mut self,
+        name: &str,
+    ) -> Result<(Commit, T)> {
+        let commit_to_merge_with = self
+            .store
+            .branch_head(self.id, name)
+            .await?
+            .with_context(|| format!("Branch {name} does not exist"))?;
+        self.merge_commit(commit_to_merge_with, format!("Merge branch {name}"))
+            .await
+    }
+
+    async fn merge_commit<T: Serialize + Deserialize + Mergeable>(
+        &mut self,
+        commit_to_merge_with: Commit,
+        message: String,
+    ) -> Result<(Commit, T)> {
+        let preview = self.merge_objects::<T>(commit_to_merge_with).await?;
+
+        let object_ref = self.store.insert(&preview.merged).await?;
+        let commit = self
+            .commit_merge(
+                object_ref,
+                &preview.other_commit,
+                Some(T::type_tag()),
+                message,
+            )
+            .await?;
+        Ok((commit, preview.merged))
+    }
 
-        let other_replica_version = commit_to_merge_with.version;
+    /// Commits the merge of the given commit into the current state, which results in the
+    /// object behind `object_ref`.
+    pub(crate) async fn commit_merge(
+        &mut self,
+        object_ref: ObjectRef,
+        commit_to_merge_with: &Commit,
+        type_tag: Option<TypeTag>,
+        message: String,
+    ) -> Result<Commit> {
+        let version = VectorClock::merge(self.latest_version(), &commit_to_merge_with.version);
+        let timestamp = HybridTimestamp::next(
+            [
+                self.latest_commit.metadata.timestamp,
+                commit_to_merge_with.metadata.timestamp,
+            ]
+            .into_iter()
+            .flatten(),
+        );
+        let metadata = CommitMetadata::new()
+            .with_timestamp(timestamp)
+            .with_message(message);
+        self.commit_inner(
+            object_ref,
+            version,
+            &[commit_to_merge_with.id],
+            type_tag,
+            metadata,
+        )
+        .await
+    }
+
+    /// Merges the heads of all given replicas in one go and commits the result as a single
+    /// commit, which has all merged heads as parents and the join of their versions. Only the
+    /// final object is stored, heads which have already been merged are skipped.
+    pub async fn merge_all<T: Serialize + Deserialize + Mergeable>(
+        &mut self,
+        other_replicas: &[ReplicaId],
+    ) -> Result<(Commit, T)> {
+        let mut merged_object = self
+            .latest_object::<T>()
+            .await?
+            .with_context(|| "Empty object")?;
+        let mut version = self.latest_version().clone();
+        let mut timestamps = vec![self.latest_commit.metadata.timestamp];
+        let mut merged_heads = Vec::new();
+        for &other_replica in other_replicas {
+            if other_replica == self.id {
+                continue;
+            }
+            let commit_to_merge_with = self.head_to_merge_with(other_replica).await?;
+            if commit_to_merge_with.version <= version {
+                continue;
+            }
+            let preview = self
+                .merge_into(merged_object, &version, commit_to_merge_with)
+                .await?;
+            version = VectorClock::merge(&version, &preview.other_commit.version);
+            timestamps.push(preview.other_commit.metadata.timestamp);
+            merged_heads.push(preview.other_commit.id);
+            merged_object = preview.merged;
+        }
+        if merged_heads.is_empty() {
+            return Ok((self.latest_commit.clone(), merged_object));
+        }
 
+        let object_ref = self.store.insert(&merged_object).await?;
+        let names = other_replicas
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        let metadata = CommitMetadata::new()
+            .with_timestamp(HybridTimestamp::next(timestamps.into_iter().flatten()))
+            .with_message(format!("Merge with {}", names.join(", ")));
+        let commit = self
+            .commit_inner(
+                object_ref,
+                version,
+                &merged_heads,
+                Some(T::type_tag()),
+                metadata,
+            )
+            .await?;
+        Ok((commit, merged_object))
+    }
+
+    /// Resolves the current object, the object of the given commit and their common ancestor and
+    /// merges them, without storing anything.
+    pub(crate) async fn merge_objects<T: Deserialize + Mergeable>(
+        &self,
+        commit_to_merge_with: Commit,
+    ) -> Result<MergePreview<T>> {
         let current_object = self
             .latest_object::<T>()
             .await?
             .with_context(|| "Empty object")?;
+        self.merge_into(current_object, self.latest_version(), commit_to_merge_with)
+            .await
+    }
+
+    /// Merges the object of the given commit into `current_object`, which has the given version.
+    async fn merge_into<T: Deserialize + Mergeable>(
+        &self,
+        current_object: T,
+        current_version: &VectorClock,
+        commit_to_merge_with: Commit,
+    ) -> Result<MergePreview<T>> {
         let object_to_merge_with = self
             .store
             .resolve::<T>(commit_to_merge_with.root_ref)
             .await?
             .with_context(|| "LCA object is empty")?;
 
-        let lca = VectorClock::lca(self.latest_version(), &other_replica_version);
-        let lca_commit = self.store.resolve_commit_for_version(lca.clone()).await?;
-        let lca_object = self
+        let lca = VectorClock::lca(current_version, &commit_to_merge_with.version);
+        let (lca_commit, lca_object) = self.merge_base::<T>(lca).await?;
+
+        let merged_object = T::merge(&lca_object, &current_object, &object_to_merge_with);
+        Ok(MergePreview {
+            lca_commit,
+            other_commit: commit_to_merge_with,
+            lca: lca_object,
+            current: current_object,
+            other: object_to_merge_with,
+            merged: merged_object,
+        })
+    }
+
+    /// Returns the commit with the given version and its object. If no commit has exactly this
+    /// version, e.g. because both sides merged different concurrent commits, the greatest
+    /// commits before it are merged into a virtual common ancestor, which is not stored.
+    async fn merge_base<T: Deserialize + Mergeable>(
+        &self,
+        version: VectorClock,
+    ) -> Result<(Option<Commit>, T)> {
+        if let Ok(commit) = self.store.resolve_commit_for_version(version.clone()).await {
+            let object = resolve_commit_object(&self.store, &commit).await?;
+            return Ok((Some(commit), object));
+        }
+        let commits = self.store.commits().await?;
+        let object = virtual_merge_base(&self.store, &commits, &version).await?;
+        Ok((None, object))
+    }
+
+    /// Undoes the changes of the given commit while keeping everything that happened since. The
+    /// current state is merged with the parent of the commit, using the commit itself as the
+    /// common ancestor, and the result is committed like any other change of the replica.
+    pub async fn revert<T: Serialize + Deserialize + Mergeable>(
+        &mut self,
+        commit_id: CommitId,
+    ) -> Result<(Commit, T)> {
+        let commit = self.store.resolve_commit(commit_id).await?;
+        let parent_id = commit
+            .parent_commit_id
+            .with_context(|| format!("Commit {commit_id} has no parent to revert to"))?;
+        let parent = self.store.resolve_commit(parent_id).await?;
+
+        let current_object = self
+            .latest_object::<T>()
+            .await?
+            .with_context(|| "Empty object")?;
+        let reverted_object = self
             .store
-            .resolve::<T>(lca_commit.root_ref)
+            .resolve::<T>(commit.root_ref)
             .await?
-            .with_context(|| "LCA object is empty")?;
+            .with_context(|| "Reverted object is empty")?;
+        let parent_object = self
+            .store
+            .resolve::<T>(parent.root_ref)
+            .await?
+            .with_context(|| "Parent object is empty")?;
 
-        let merged_object = T::merge(&lca_object, &current_object, &object_to_merge_with);
+        let merged_object = T::merge(&reverted_object, &current_object, &parent_object);
 
         let object_ref = self.store.insert(&merged_object).await?;
-        let version = VectorClock::merge(self.latest_version(), &other_replica_version);
-        let commit = self.commit(object_ref, version).await?;
+        let metadata = CommitMetadata::new()
+            .with_message(format!("Revert {commit_id}"))
+            .with_annotation("reverts", commit_id.to_string());
+        let commit = self
+            .commit_inner(
+                object_ref,
+                self.next_version()?,
+                &[],
+                Some(T::type_tag()),
+                metadata,
+            )
+            .await?;
         Ok((commit, merged_object))
     }
 
-    fn next_version(&self) -> VectorClock {
+    fn next_version(&self) -> Result<VectorClock> {
         let mut version = self.latest_commit.version.clone();
-        version.inc(self.id);
-        version
+        version.inc(branch_clock_id(self.id, &self.branch)?);
+        Ok(version)
+    }
+}
+
+/// Merges the greatest commits whose versions are before or equal to the given version, using
+/// their own virtual common ancestors, like the recursive merge strategy of git.
+fn virtual_merge_base<'a, T: Deserialize + Mergeable + 'a>(
+    store: &'a QuarkStore,
+    commits: &'a [Commit],
+    version: &'a VectorClock,
+) -> Pin<Box<dyn Future<Output = Result<T>> + 'a>> {
+    Box::pin(async move {
+        if let Some(commit) = commits.iter().find(|commit| commit.version == *version) {
+            return resolve_commit_object(store, commit).await;
+        }
+        let before = commits
+            .iter()
+            .filter(|commit| commit.version <= *version)
+            .collect::<Vec<_>>();
+        let mut greatest: Vec<&Commit> = Vec::new();
+        for commit in &before {
+            let is_greatest = !before.iter().any(|other| commit.version < other.version);
+            if is_greatest && !greatest.iter().any(|other| other.version == commit.version) {
+                greatest.push(commit);
+            }
+        }
+
+        let (first, rest) = greatest
+            .split_first()
+            .with_context(|| "Commits have no common ancestor")?;
+        let mut merged_version = first.version.clone();
+        let mut merged = resolve_commit_object::<T>(store, first).await?;
+        for commit in rest {
+            let lca = VectorClock::lca(&merged_version, &commit.version);
+            let lca_object = virtual_merge_base::<T>(store, commits, &lca).await?;
+            let object = resolve_commit_object::<T>(store, commit).await?;
+            merged = T::merge(&lca_object, &merged, &object);
+            merged_version = VectorClock::merge(&merged_version, &commit.version);
+        }
+        Ok(merged)
+    })
+}
+
+async fn resolve_commit_object<T: Deserialize>(store: &QuarkStore, commit: &Commit) -> Result<T> {
+    store
+        .resolve::<T>(commit.root_ref)
+        .await?
+        .with_context(|| "LCA object is empty")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_revert_keeps_later_changes() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+
+        let mut replica = Replica::clone(replica_id, store).await.unwrap();
+        let bad = replica
+            .commit_object(&HashSet::from_iter([1u64, 2]))
+            .await
+            .unwrap();
+        replica
+            .commit_object(&HashSet::from_iter([1u64, 2, 3]))
+            .await
+            .unwrap();
+
+        let (commit, reverted) = replica.revert::<HashSet<u64>>(bad.id).await.unwrap();
+        assert_eq!(reverted, HashSet::from_iter([1, 3]));
+        assert_eq!(commit.metadata.message, Some(format!("Revert {}", bad.id)));
+        assert_eq!(
+            replica.latest_object::<HashSet<u64>>().await.unwrap(),
+            Some(reverted)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_all_writes_a_single_commit() {
+        let store = QuarkStore::test();
+        let (replica_a, replica_b, replica_c) = (Id::gen(), Id::gen(), Id::gen());
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(replica_a, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut replica = Replica::clone(replica_a, store).await.unwrap();
+        replica
+            .commit_object(&HashSet::from_iter([1u64, 2]))
+            .await
+            .unwrap();
+
+        let mut heads = Vec::new();
+        for (other, item) in [(replica_b, 3u64), (replica_c, 4)] {
+            let mut version = VectorClock::default();
+            version.inc(other);
+            let root = replica
+                .store()
+                .insert(&HashSet::from_iter([1u64, item]))
+                .await
+                .unwrap();
+            let head = replica.store().commit(other, version, root).await.unwrap();
+            heads.push(head.id);
+        }
+
+        let commits = replica.store().commits().await.unwrap().len();
+        let (commit, merged) = replica
+            .merge_all::<HashSet<u64>>(&[replica_b, replica_c])
+            .await
+            .unwrap();
+        assert_eq!(merged, HashSet::from_iter([1, 2, 3, 4]));
+        assert_eq!(commit.merge_parent_ids, heads);
+        assert_eq!(replica.store().commits().await.unwrap().len(), commits + 1);
+
+        let (again, _) = replica
+            .merge_all::<HashSet<u64>>(&[replica_b, replica_c])
+            .await
+            .unwrap();
+        assert_eq!(again, commit);
+    }
+
+    #[tokio::test]
+    async fn test_merge_all_with_concurrent_heads() {
+        let store = QuarkStore::test();
+        let [replica_id, p, q, a, b, c] = [(); 6].map(|_| Id::gen());
+        let commit = |id: ReplicaId, clock: &[ReplicaId], items: &[u64]| {
+            let mut version = VectorClock::default();
+            clock.iter().for_each(|id| version.inc(*id));
+            let items = HashSet::from_iter(items.iter().copied());
+            let store = &store;
+            async move {
+                let root = store.insert(&items).await.unwrap();
+                store.commit(id, version, root).await.unwrap()
+            }
+        };
+        commit(replica_id, &[], &[1]).await;
+        commit(p, &[p], &[1, 2]).await;
+        commit(q, &[q], &[1, 3]).await;
+        commit(replica_id, &[p, replica_id], &[1, 2, 10]).await;
+        commit(a, &[q, a], &[1, 3, 11]).await;
+        // The common ancestor of the merged heads and `b` contains the changes of `p` and `q`,
+        // but no commit has exactly this version.
+        commit(b, &[b], &[1, 12]).await;
+        commit(b, &[b, p], &[1, 2, 12]).await;
+        commit(b, &[b, p, q], &[2, 3, 12]).await;
+        commit(c, &[c], &[1, 13]).await;
+
+        let mut replica = Replica::open(replica_id, store).await.unwrap();
+        let (commit, merged) = replica.merge_all::<HashSet<u64>>(&[a, b, c]).await.unwrap();
+        assert_eq!(merged, HashSet::from_iter([2, 3, 10, 11, 12, 13]));
+        assert_eq!(commit.merge_parent_ids.len(), 3);
+        for other in [a, b, c] {
+            assert!(replica
+                .store()
+                .latest_commit_for_replica(other)
+                .await
+                .unwrap()
+                .is_some_and(|head| head.version <= commit.version));
+        }
     }
 }
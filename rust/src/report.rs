@@ -0,0 +1,190 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use anyhow::Result;
+
+use crate::{CommitId, QuarkStore, ReplicaId, VectorClock, VersionedStore};
+
+/// How the heads of two replicas relate causally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CausalRelation {
+    Equal,
+    /// The head of the left replica is contained in the head of the right one.
+    Before,
+    /// The head of the right replica is contained in the head of the left one.
+    After,
+    Concurrent,
+}
+
+impl CausalRelation {
+    pub fn between(left: &VectorClock, right: &VectorClock) -> Self {
+        match left.partial_cmp(right) {
+            Some(Ordering::Equal) => CausalRelation::Equal,
+            Some(Ordering::Less) => CausalRelation::Before,
+            Some(Ordering::Greater) => CausalRelation::After,
+            None => CausalRelation::Concurrent,
+        }
+    }
+
+    pub fn reversed(self) -> Self {
+        match self {
+            CausalRelation::Before => CausalRelation::After,
+            CausalRelation::After => CausalRelation::Before,
+            relation => relation,
+        }
+    }
+}
+
+/// The state of a single replica in a [`ConvergenceReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaDivergence {
+    pub replica_id: ReplicaId,
+    pub head: CommitId,
+    pub version: VectorClock,
+    pub root_ref: u64,
+    /// The number of commits in the join of all heads, which the replica has not seen yet.
+    pub behind: u32,
+    /// The number of unseen commits per replica which made them.
+    pub missing: BTreeMap<ReplicaId, u32>,
+}
+
+/// Shows which replicas have not seen which commits yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvergenceReport {
+    pub replicas: Vec<ReplicaDivergence>,
+    /// The causal relation of every pair of replicas, with the left replica ordered first.
+    pub relations: Vec<(ReplicaId, ReplicaId, CausalRelation)>,
+    /// The join of the versions of all heads.
+    pub join: VectorClock,
+    /// True if the heads of all replicas point to the same root ref.
+    pub converged: bool,
+}
+
+impl ConvergenceReport {
+    /// Returns how the head of `left` relates to the head of `right`.
+    pub fn relation(&self, left: ReplicaId, right: ReplicaId) -> Option<CausalRelation> {
+        self.relations.iter().find_map(|(a, b, relation)| {
+            if (*a, *b) == (left, right) {
+                Some(*relation)
+            } else if (*a, *b) == (right, left) {
+                Some(relation.reversed())
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn replica(&self, replica_id: ReplicaId) -> Option<&ReplicaDivergence> {
+        self.replicas
+            .iter()
+            .find(|replica| replica.replica_id == replica_id)
+    }
+}
+
+impl QuarkStore {
+    /// Compares the heads of all replicas which have not been retired.
+    pub async fn convergence_report(&self) -> Result<ConvergenceReport> {
+        let retired = self.retired_replicas().await?;
+        let mut replicas = Vec::new();
+        for (replica_id, head) in self.replica_heads().await? {
+            if retired.contains(&replica_id) {
+                continue;
+            }
+            let commit = self.resolve_commit(head).await?;
+            replicas.push(ReplicaDivergence {
+                replica_id,
+                head,
+                version: commit.version,
+                root_ref: commit.root_ref,
+                behind: 0,
+                missing: BTreeMap::new(),
+            });
+        }
+        replicas.sort_by_key(|replica| replica.replica_id);
+
+        let join = replicas
+            .iter()
+            .fold(VectorClock::default(), |join, replica| {
+                VectorClock::merge(&join, &replica.version)
+            });
+        for replica in &mut replicas {
+            for (origin, timestamp) in join.iter() {
+                let seen = replica.version.time_of(origin).unwrap_or_default();
+                let missing = u32::from(timestamp) - u32::from(seen);
+                if missing > 0 {
+                    replica.missing.insert(origin, missing);
+                }
+            }
+            replica.behind = replica.missing.values().sum();
+        }
+
+        let mut relations = Vec::new();
+        for (ix, left) in replicas.iter().enumerate() {
+            for right in &replicas[ix + 1..] {
+                relations.push((
+                    left.replica_id,
+                    right.replica_id,
+                    CausalRelation::between(&left.version, &right.version),
+                ));
+            }
+        }
+
+        let converged = replicas
+            .windows(2)
+            .all(|pair| pair[0].root_ref == pair[1].root_ref);
+        Ok(ConvergenceReport {
+            replicas,
+            relations,
+            join,
+            converged,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashSet, Id, RefStore};
+
+    #[tokio::test]
+    async fn test_convergence_report() {
+        let store = QuarkStore::test();
+        let (replica_a, replica_b, replica_c) = (Id::gen(), Id::gen(), Id::gen());
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        let other_root = store.insert(&HashSet::from_iter([2u64])).await.unwrap();
+
+        let mut version_a = VectorClock::default();
+        version_a.inc(replica_a);
+        version_a.inc(replica_a);
+        let mut version_b = VectorClock::default();
+        version_b.inc(replica_b);
+        store
+            .commit(replica_a, version_a.clone(), root)
+            .await
+            .unwrap();
+        store
+            .commit(replica_b, version_b, other_root)
+            .await
+            .unwrap();
+        store.commit(replica_c, version_a, root).await.unwrap();
+
+        let report = store.convergence_report().await.unwrap();
+        assert!(!report.converged);
+        assert_eq!(
+            report.relation(replica_a, replica_c),
+            Some(CausalRelation::Equal)
+        );
+        assert_eq!(
+            report.relation(replica_b, replica_a),
+            Some(CausalRelation::Concurrent)
+        );
+        let b = report.replica(replica_b).unwrap();
+        assert_eq!(b.behind, 2);
+        assert_eq!(b.missing.get(&replica_a), Some(&2));
+
+        store.register_replica(replica_b, "b").await.unwrap();
+        store.retire_replica(replica_b).await.unwrap();
+        let report = store.convergence_report().await.unwrap();
+        assert!(report.converged);
+        assert!(report.replicas.iter().all(|replica| replica.behind == 0));
+    }
+}
@@ -0,0 +1,534 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use scylla::serialize::row::SerializeRow;
+
+use crate::{
+    HashSet, Id, ScyllaSession, BRANCH_TABLE_NAME, COMMIT_TABLE_NAME, NAMESPACE_COMMIT_TABLE_NAME,
+    NAMESPACE_REPLICA_TABLE_NAME, OBJECT_TABLE_NAME, REF_TABLE_NAME, REMOTE_HEAD_TABLE_NAME,
+    REPLICA_KEY_TABLE_NAME, REPLICA_MEMBERSHIP_TABLE_NAME, REPLICA_TABLE_NAME,
+    REPLICA_TYPE_TABLE_NAME, ROOT_TYPE_TABLE_NAME, TAG_TABLE_NAME,
+};
+
+pub(crate) const SCHEMA_TABLE_NAME: &str = "schema_migrations";
+
+/// How long a migration which is applied by another process may make no progress, before its
+/// claim is considered stale.
+const MIGRATION_TIMEOUT: Duration = Duration::from_secs(60);
+const MIGRATION_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A single step in the evolution of the store tables.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    steps: fn(&ScyllaSession) -> Vec<MigrationStep>,
+}
+
+impl Migration {
+    /// Returns the steps of the migration for the tables of the given session.
+    pub fn steps(&self, session: &ScyllaSession) -> Vec<MigrationStep> {
+        (self.steps)(session)
+    }
+}
+
+/// A statement of a [`Migration`]. Every step has to be idempotent, as a process which takes
+/// over a migration repeats the step its previous owner crashed in.
+pub enum MigrationStep {
+    /// A statement which is idempotent by itself, e.g. `CREATE TABLE IF NOT EXISTS`.
+    Query(String),
+    /// Adds columns to a store table. CQL has no `ADD IF NOT EXISTS`, so columns which already
+    /// exist are skipped.
+    AddColumns {
+        table: &'static str,
+        columns: &'static [(&'static str, &'static str)],
+    },
+}
+
+impl From<String> for MigrationStep {
+    fn from(query: String) -> Self {
+        MigrationStep::Query(query)
+    }
+}
+
+impl MigrationStep {
+    /// Returns the statement which applies the step, or `None` if there is nothing left to do.
+    async fn statement(&self, session: &ScyllaSession) -> Result<Option<String>> {
+        match self {
+            MigrationStep::Query(query) => Ok(Some(query.clone())),
+            MigrationStep::AddColumns { table, columns } => {
+                let existing = existing_columns(session, table).await?;
+                let missing = columns
+                    .iter()
+                    .filter(|(name, _)| !existing.contains(*name))
+                    .map(|(name, ty)| format!("{name} {ty}"))
+                    .collect::<Vec<_>>();
+                if missing.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(format!(
+                    "ALTER TABLE {} ADD ({})",
+                    session.table_name(table),
+                    missing.join(", ")
+                )))
+            }
+        }
+    }
+}
+
+/// Returns the names of the columns the store table currently has.
+async fn existing_columns(session: &ScyllaSession, table: &str) -> Result<HashSet<String>> {
+    let (keyspace, table) = session.schema_table_name(table);
+    session
+        .query(
+            "SELECT column_name FROM system_schema.columns
+                WHERE keyspace_name = ? AND table_name = ?",
+            (keyspace, table),
+        )
+        .await?
+        .rows_or_empty()
+        .into_iter()
+        .map(|row| {
+            row.columns[0]
+                .as_ref()
+                .and_then(|value| value.clone().into_string())
+                .with_context(|| "Failed to deserialize column name")
+        })
+        .collect()
+}
+
+/// All migrations in the order in which they are applied. New migrations have to be appended
+/// with the next version, already released migrations must never be changed.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create the commit, object, ref and replica tables",
+        steps: |session| {
+            vec![
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (id BIGINT, left BIGINT, right BIGINT, object_ref BIGINT, PRIMARY KEY (id))",
+                    session.table_name(REF_TABLE_NAME)
+                ).into(),
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {} (id BIGINT, object BLOB, PRIMARY KEY (id))",
+                    session.table_name(OBJECT_TABLE_NAME)
+                ).into(),
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (id TEXT, version BLOB, root_ref BIGINT, prev_commit_id TEXT, PRIMARY KEY (id))",
+                    session.table_name(COMMIT_TABLE_NAME)
+                ).into(),
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {} (id TEXT, commit_id TEXT, PRIMARY KEY (id))",
+                    session.table_name(REPLICA_TABLE_NAME)
+                ).into(),
+            ]
+        },
+    },
+    Migration {
+        version: 2,
+        description: "Create the remote head table",
+        steps: |session| {
+            vec![format!(
+                "CREATE TABLE IF NOT EXISTS {}
+                    (namespace TEXT, remote TEXT, replica_id TEXT, commit_id TEXT,
+                    PRIMARY KEY ((namespace), remote, replica_id))",
+                session.table_name(REMOTE_HEAD_TABLE_NAME)
+            )
+            .into()]
+        },
+    },
+    Migration {
+        version: 3,
+        description: "Record the type tag of every replica",
+        steps: |session| {
+            vec![format!(
+                "CREATE TABLE IF NOT EXISTS {}
+                    (namespace TEXT, replica_id TEXT, type_name TEXT, schema_fingerprint BIGINT,
+                    PRIMARY KEY ((namespace), replica_id))",
+                session.table_name(REPLICA_TYPE_TABLE_NAME)
+            )
+            .into()]
+        },
+    },
+    Migration {
+        version: 4,
+        description: "Record the type tag of every commit",
+        steps: |session| {
+            vec![
+                MigrationStep::AddColumns {
+                    table: COMMIT_TABLE_NAME,
+                    columns: &[("type_name", "TEXT"), ("schema_fingerprint", "BIGINT")],
+                },
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (root_ref BIGINT, type_name TEXT, schema_fingerprint BIGINT, PRIMARY KEY (root_ref, type_name))",
+                    session.table_name(ROOT_TYPE_TABLE_NAME)
+                ).into(),
+            ]
+        },
+    },
+    Migration {
+        version: 5,
+        description: "Record the metadata of every commit",
+        steps: |_| {
+            vec![MigrationStep::AddColumns {
+                table: COMMIT_TABLE_NAME,
+                columns: &[
+                    ("author", "TEXT"),
+                    ("wall_time", "BIGINT"),
+                    ("logical_time", "INT"),
+                    ("message", "TEXT"),
+                    ("annotations", "MAP<TEXT, TEXT>"),
+                ],
+            }]
+        },
+    },
+    Migration {
+        version: 6,
+        description: "Record commit signatures and the public keys of replicas",
+        steps: |session| {
+            vec![
+                MigrationStep::AddColumns {
+                    table: COMMIT_TABLE_NAME,
+                    columns: &[("signer", "TEXT"), ("signature", "BLOB")],
+                },
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (namespace TEXT, replica_id TEXT, public_key BLOB, PRIMARY KEY ((namespace), replica_id))",
+                    session.table_name(REPLICA_KEY_TABLE_NAME)
+                ).into(),
+            ]
+        },
+    },
+    Migration {
+        version: 7,
+        description: "Record the membership of every replica",
+        steps: |session| {
+            vec![format!(
+                "CREATE TABLE IF NOT EXISTS {}
+                    (namespace TEXT, replica_id TEXT, name TEXT, aliases SET<TEXT>, retired BOOLEAN,
+                    PRIMARY KEY ((namespace), replica_id))",
+                session.table_name(REPLICA_MEMBERSHIP_TABLE_NAME)
+            )
+            .into()]
+        },
+    },
+    Migration {
+        version: 8,
+        description: "Create the branch and tag tables",
+        steps: |session| {
+            vec![
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (namespace TEXT, replica_id TEXT, name TEXT, commit_id TEXT,
+                        PRIMARY KEY ((namespace), replica_id, name))",
+                    session.table_name(BRANCH_TABLE_NAME)
+                ).into(),
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (namespace TEXT, name TEXT, commit_id TEXT, PRIMARY KEY ((namespace), name))",
+                    session.table_name(TAG_TABLE_NAME)
+                ).into(),
+            ]
+        },
+    },
+    Migration {
+        version: 9,
+        description: "Record the merged heads of every commit",
+        steps: |_| {
+            vec![MigrationStep::AddColumns {
+                table: COMMIT_TABLE_NAME,
+                columns: &[("merge_parent_ids", "LIST<TEXT>")],
+            }]
+        },
+    },
+    Migration {
+        version: 10,
+        description: "Create the commit and replica tables partitioned by namespace",
+        steps: |session| {
+            vec![
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (namespace TEXT, id TEXT, version BLOB, root_ref BIGINT, prev_commit_id TEXT,
+                        type_name TEXT, schema_fingerprint BIGINT, author TEXT, wall_time BIGINT,
+                        logical_time INT, message TEXT, annotations MAP<TEXT, TEXT>, signer TEXT,
+                        signature BLOB, merge_parent_ids LIST<TEXT>, PRIMARY KEY ((namespace), id))",
+                    session.table_name(NAMESPACE_COMMIT_TABLE_NAME)
+                ).into(),
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {}
+                        (namespace TEXT, replica_id TEXT, commit_id TEXT, PRIMARY KEY ((namespace), replica_id))",
+                    session.table_name(NAMESPACE_REPLICA_TABLE_NAME)
+                ).into(),
+            ]
+        },
+    },
+];
+
+/// Returns the schema version the store tables have once all migrations are applied.
+pub fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Returns the migrations which have to be applied to bring a keyspace from the given version to
+/// the latest one.
+pub fn pending_migrations(current_version: u32) -> Result<&'static [Migration]> {
+    if current_version > latest_schema_version() {
+        bail!(
+            "Schema version {current_version} is newer than the latest supported version {}",
+            latest_schema_version()
+        );
+    }
+    let applied = MIGRATIONS
+        .iter()
+        .take_while(|migration| migration.version <= current_version)
+        .count();
+    Ok(&MIGRATIONS[applied..])
+}
+
+/// Returns the version of the latest migration which has been completely applied.
+pub async fn schema_version(session: &ScyllaSession) -> Result<u32> {
+    let schema_table = session.table_name(SCHEMA_TABLE_NAME);
+    let mut version = 0;
+    for row in session
+        .query(format!("SELECT version, applied FROM {schema_table}"), ())
+        .await?
+        .rows_or_empty()
+    {
+        let migration_version = row.columns[0]
+            .as_ref()
+            .and_then(|value| value.as_int())
+            .with_context(|| "Failed to deserialize migration version")?;
+        let applied = row.columns[1]
+            .as_ref()
+            .and_then(|value| value.as_boolean())
+            .unwrap_or(false);
+        if applied {
+            version = version.max(migration_version as u32);
+        }
+    }
+    Ok(version)
+}
+
+/// Applies all pending migrations in order and returns the resulting schema version.
+///
+/// Every migration is claimed with a lightweight transaction, so when several processes set up
+/// the same keyspace concurrently, only one of them applies it while the others wait for it. The
+/// claim records its owner and how many steps have been applied, so if the owner crashes,
+/// another process takes the claim over once it made no progress for [`MIGRATION_TIMEOUT`] and
+/// continues with the remaining steps, which are all idempotent.
+pub async fn migrate(session: &ScyllaSession) -> Result<u32> {
+    let schema_table = session.table_name(SCHEMA_TABLE_NAME);
+    session
+        .query(
+            format!(
+                "CREATE TABLE IF NOT EXISTS {schema_table}
+                    (version INT, description TEXT, applied BOOLEAN, applied_at TIMESTAMP,
+                    claimed_by TEXT, claimed_at TIMESTAMP, applied_steps INT,
+                    PRIMARY KEY (version))"
+            ),
+            (),
+        )
+        .await
+        .with_context(|| "Failed to create schema table")?;
+
+    let owner = Id::gen().to_string();
+    let current_version = schema_version(session).await?;
+    for migration in pending_migrations(current_version)? {
+        let claimed = execute_lwt(
+            session,
+            format!(
+                "INSERT INTO {schema_table}
+                    (version, description, applied, claimed_by, claimed_at, applied_steps)
+                    VALUES (?, ?, false, ?, toTimestamp(now()), 0) IF NOT EXISTS"
+            ),
+            (migration.version as i32, migration.description, &owner),
+        )
+        .await?;
+
+        let applied_steps = if claimed {
+            0
+        } else {
+            match wait_for_migration(session, migration, &owner).await? {
+                Some(applied_steps) => applied_steps,
+                None => continue,
+            }
+        };
+
+        log::info!(
+            "Applying schema migration {}: {}",
+            migration.version,
+            migration.description
+        );
+        let steps = migration.steps(session);
+        for (step, migration_step) in steps.iter().enumerate().skip(applied_steps) {
+            if let Some(statement) = migration_step.statement(session).await? {
+                session.query(statement, ()).await.with_context(|| {
+                    format!("Failed to apply schema migration {}", migration.version)
+                })?;
+            }
+            let still_claimed = execute_lwt(
+                session,
+                format!(
+                    "UPDATE {schema_table} SET applied_steps = ?, claimed_at = toTimestamp(now())
+                        WHERE version = ? IF claimed_by = ?"
+                ),
+                ((step + 1) as i32, migration.version as i32, &owner),
+            )
+            .await?;
+            if !still_claimed {
+                bail!(
+                    "Schema migration {} has been taken over by another process",
+                    migration.version
+                );
+            }
+        }
+        execute_lwt(
+            session,
+            format!(
+                "UPDATE {schema_table} SET applied = true, applied_at = toTimestamp(now())
+                    WHERE version = ? IF claimed_by = ?"
+            ),
+            (migration.version as i32, &owner),
+        )
+        .await?;
+    }
+
+    Ok(latest_schema_version())
+}
+
+/// Waits until another process has applied the migration. If the other process stops making
+/// progress, its claim is taken over and the number of steps it already applied is
+/// returned.
+async fn wait_for_migration(
+    session: &ScyllaSession,
+    migration: &Migration,
+    owner: &str,
+) -> Result<Option<usize>> {
+    let schema_table = session.table_name(SCHEMA_TABLE_NAME);
+    let mut last_progress: Option<((String, i32), Instant)> = None;
+    loop {
+        let row = session
+            .query(
+                format!(
+                    "SELECT applied, claimed_by, applied_steps FROM {schema_table}
+                        WHERE version = ?"
+                ),
+                (migration.version as i32,),
+            )
+            .await?
+            .first_row()
+            .with_context(|| format!("Schema migration {} is not claimed", migration.version))?;
+        let applied = row.columns[0]
+            .as_ref()
+            .and_then(|value| value.as_boolean())
+            .unwrap_or(false);
+        if applied {
+            return Ok(None);
+        }
+        let claimed_by = row.columns[1]
+            .as_ref()
+            .and_then(|value| value.clone().into_string())
+            .unwrap_or_default();
+        let applied_steps = row.columns[2]
+            .as_ref()
+            .and_then(|value| value.as_int())
+            .unwrap_or(0);
+
+        let progress = (claimed_by, applied_steps);
+        match &last_progress {
+            Some((last, since)) if *last == progress => {
+                if since.elapsed() > MIGRATION_TIMEOUT {
+                    let taken_over = execute_lwt(
+                        session,
+                        format!(
+                            "UPDATE {schema_table} SET claimed_by = ?, claimed_at = toTimestamp(now())
+                                WHERE version = ? IF applied = false AND claimed_by = ?"
+                        ),
+                        (owner, migration.version as i32, &progress.0),
+                    )
+                    .await?;
+                    if taken_over {
+                        log::warn!(
+                            "Taking over schema migration {}, which was claimed by {} but made \
+                            no progress",
+                            migration.version,
+                            progress.0
+                        );
+                        return Ok(Some(applied_steps as usize));
+                    }
+                    last_progress = None;
+                }
+            }
+            _ => last_progress = Some((progress, Instant::now())),
+        }
+        tokio::time::sleep(MIGRATION_POLL_INTERVAL).await;
+    }
+}
+
+/// Executes a lightweight transaction and returns whether it has been applied.
+async fn execute_lwt(
+    session: &ScyllaSession,
+    query: String,
+    values: impl SerializeRow,
+) -> Result<bool> {
+    Ok(session
+        .query(query, values)
+        .await?
+        .first_row()
+        .ok()
+        .and_then(|row| row.columns[0].as_ref().and_then(|value| value.as_boolean()))
+        .unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{QuarkStore, QuarkStoreConfig};
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (ix, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, ix + 1);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a running Scylla node, configured with the SCYLLA_* variables"]
+    async fn test_migrations_resume_after_partially_applied_steps() {
+        let mut config = QuarkStoreConfig::from_env().unwrap();
+        config.table_prefix = format!("migrate_{}_", Id::gen()).to_lowercase();
+        let store = QuarkStore::connect(config).await.unwrap();
+        let QuarkStore::Scylla(session) = &store else {
+            unreachable!()
+        };
+
+        // Pretend that the process which applied the last migrations crashed right after their
+        // columns were added, before their progress was recorded.
+        let schema_table = session.table_name(SCHEMA_TABLE_NAME);
+        for migration in &MIGRATIONS[MIGRATIONS.len() - 2..] {
+            session
+                .query(
+                    format!("DELETE FROM {schema_table} WHERE version = ?"),
+                    (migration.version as i32,),
+                )
+                .await
+                .unwrap();
+        }
+        let resumed = migrate(session).await;
+        let version = schema_version(session).await;
+        store.reset_db().await.unwrap();
+        assert_eq!(resumed.unwrap(), latest_schema_version());
+        assert_eq!(version.unwrap(), latest_schema_version());
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        assert_eq!(pending_migrations(0).unwrap().len(), MIGRATIONS.len());
+        assert!(pending_migrations(latest_schema_version())
+            .unwrap()
+            .is_empty());
+        assert!(pending_migrations(latest_schema_version() + 1).is_err());
+    }
+}
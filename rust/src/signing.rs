@@ -0,0 +1,280 @@
+use anyhow::{bail, Context, Result};
+use ed25519_dalek::{Signature, Signer, Verifier};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+use musli::{Decode, Encode};
+
+use crate::{quark::ENCODING, Commit, QuarkStore, ReplicaId};
+
+/// Separates commit signatures from signatures over other data made with the same key.
+const SIGNATURE_DOMAIN: &[u8] = b"mrdt-commit-v1";
+
+/// The Ed25519 signature of the replica which produced a commit.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct CommitSignature {
+    pub signer: ReplicaId,
+    pub bytes: Vec<u8>,
+}
+
+impl CommitSignature {
+    /// Signs the id, version, parents and root ref of the commit.
+    pub fn sign(signer: ReplicaId, commit: &Commit, key: &SigningKey) -> Result<Self> {
+        let message = signed_message(signer, commit)?;
+        Ok(Self {
+            signer,
+            bytes: key.sign(&message).to_bytes().to_vec(),
+        })
+    }
+
+    fn verify(&self, commit: &Commit, key: &VerifyingKey) -> Result<()> {
+        let signature = Signature::from_slice(&self.bytes)?;
+        key.verify(&signed_message(self.signer, commit)?, &signature)?;
+        Ok(())
+    }
+}
+
+fn signed_message(signer: ReplicaId, commit: &Commit) -> Result<Vec<u8>> {
+    let mut message = SIGNATURE_DOMAIN.to_vec();
+    message.extend_from_slice(signer.as_str().as_bytes());
+    message.extend_from_slice(commit.id.as_str().as_bytes());
+    match commit.parent_commit_id {
+        Some(parent) => message.extend_from_slice(parent.as_str().as_bytes()),
+        None => message.extend_from_slice(&[0; 16]),
+    }
+    message.extend_from_slice(&commit.root_ref.to_le_bytes());
+    for merge_parent in &commit.merge_parent_ids {
+        message.extend_from_slice(merge_parent.as_str().as_bytes());
+    }
+    ENCODING.encode(&mut message, &commit.version)?;
+    Ok(message)
+}
+
+/// Determines which commits a store accepts from other replicas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// Unsigned commits are accepted from replicas which have not registered a public key.
+    #[default]
+    Permissive,
+    /// Every commit has to be signed.
+    RequireSigned,
+}
+
+/// The result of checking the signature of a commit against the key registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitVerification {
+    /// The commit carries a valid signature of the given replica.
+    Signed(ReplicaId),
+    Unsigned,
+    /// The signature is invalid or the signer has not registered a public key.
+    Forged(String),
+}
+
+impl QuarkStore {
+    /// Sets the policy that is applied when merging with other replicas and importing commits.
+    pub fn with_signature_policy(mut self, policy: SignaturePolicy) -> Self {
+        match &mut self {
+            QuarkStore::Scylla(session) => session.set_signature_policy(policy),
+            QuarkStore::Memory {
+                signature_policy, ..
+            } => *signature_policy = policy,
+        }
+        self
+    }
+
+    pub fn signature_policy(&self) -> SignaturePolicy {
+        match self {
+            QuarkStore::Scylla(session) => session.signature_policy(),
+            QuarkStore::Memory {
+                signature_policy, ..
+            } => *signature_policy,
+        }
+    }
+
+    /// Registers the public key commits of the replica are verified with. A replica can only
+    /// register a single key, registering a different one afterwards fails.
+    pub async fn register_public_key(
+        &self,
+        replica_id: ReplicaId,
+        key: &VerifyingKey,
+    ) -> Result<()> {
+        self.insert_replica_public_key(replica_id, key.to_bytes())
+            .await?;
+        match self.public_key(replica_id).await? {
+            Some(registered) if registered == *key => Ok(()),
+            _ => bail!("Replica {replica_id} has already registered a different public key"),
+        }
+    }
+
+    pub async fn public_key(&self, replica_id: ReplicaId) -> Result<Option<VerifyingKey>> {
+        self.replica_public_key(replica_id)
+            .await?
+            .map(|bytes| {
+                VerifyingKey::from_bytes(&bytes)
+                    .with_context(|| format!("Public key of replica {replica_id} is invalid"))
+            })
+            .transpose()
+    }
+
+    pub async fn verify_commit(&self, commit: &Commit) -> Result<CommitVerification> {
+        let Some(signature) = &commit.signature else {
+            return Ok(CommitVerification::Unsigned);
+        };
+        let Some(key) = self.public_key(signature.signer).await? else {
+            return Ok(CommitVerification::Forged(format!(
+                "Replica {} has not registered a public key",
+                signature.signer
+            )));
+        };
+        Ok(match signature.verify(commit, &key) {
+            Ok(()) => CommitVerification::Signed(signature.signer),
+            Err(err) => CommitVerification::Forged(format!("{err:#}")),
+        })
+    }
+
+    /// Checks that the commit may be accepted as part of the history of the replica. Forged
+    /// commits and commits signed by another replica are always rejected, unsigned ones if the
+    /// policy requires signatures or the replica has registered a public key.
+    pub async fn check_commit(&self, replica_id: ReplicaId, commit: &Commit) -> Result<()> {
+        match self.verify_commit(commit).await? {
+            CommitVerification::Signed(signer) if signer == replica_id => Ok(()),
+            CommitVerification::Signed(signer) => {
+                bail!(
+                    "Commit {} of replica {replica_id} is forged: it is signed by replica {signer}",
+                    commit.id
+                )
+            }
+            CommitVerification::Forged(reason) => {
+                bail!(
+                    "Commit {} of replica {replica_id} is forged: {reason}",
+                    commit.id
+                )
+            }
+            CommitVerification::Unsigned => {
+                if self.signature_policy() == SignaturePolicy::RequireSigned
+                    || self.public_key(replica_id).await?.is_some()
+                {
+                    bail!("Commit {} of replica {replica_id} is not signed", commit.id);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        Bundle, BundleHead, CommitOptions, HashSet, Id, RefStore, Replica, VectorClock,
+        VersionedStore,
+    };
+
+    #[tokio::test]
+    async fn test_signed_commits() {
+        let store = QuarkStore::test();
+        let (main, peer) = (Id::gen(), Id::gen());
+        let key = SigningKey::generate(&mut OsRng);
+        store
+            .register_public_key(peer, &key.verifying_key())
+            .await
+            .unwrap();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(main, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut replica = Replica::clone(main, store).await.unwrap();
+
+        let mut version = VectorClock::default();
+        version.inc(peer);
+        let peer_root = replica
+            .store()
+            .insert(&HashSet::from_iter([1u64, 2]))
+            .await
+            .unwrap();
+        let signed = replica
+            .store()
+            .commit_with(
+                peer,
+                version.clone(),
+                peer_root,
+                CommitOptions::new().signed_with(&key),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            replica.store().verify_commit(&signed).await.unwrap(),
+            CommitVerification::Signed(peer)
+        );
+
+        // A commit which reuses the signature for a different root is quarantined on import.
+        let mut forged = signed.clone();
+        forged.id = Id::gen();
+        forged.root_ref = root;
+        let bundle = Bundle {
+            heads: vec![BundleHead {
+                replica_id: peer,
+                commit_id: forged.id,
+            }],
+            commits: vec![forged],
+            ..Default::default()
+        };
+        let summary = replica.store().import_bundle(&bundle).await.unwrap();
+        assert_eq!(summary.quarantined_heads, vec![peer]);
+        replica.merge_with::<HashSet<u64>>(peer).await.unwrap();
+
+        // The peer has registered a key, so its commits have to be signed.
+        version.inc(peer);
+        let unsigned = replica
+            .store()
+            .commit(peer, version, peer_root)
+            .await
+            .unwrap();
+        assert!(replica.merge_with::<HashSet<u64>>(peer).await.is_err());
+
+        let strict = QuarkStore::test().with_signature_policy(SignaturePolicy::RequireSigned);
+        assert!(strict.check_commit(Id::gen(), &unsigned).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_commits_signed_by_another_peer_are_forged() {
+        let store = QuarkStore::test();
+        let (main, peer, other) = (Id::gen(), Id::gen(), Id::gen());
+        let peer_key = SigningKey::generate(&mut OsRng);
+        let other_key = SigningKey::generate(&mut OsRng);
+        store
+            .register_public_key(peer, &peer_key.verifying_key())
+            .await
+            .unwrap();
+        store
+            .register_public_key(other, &other_key.verifying_key())
+            .await
+            .unwrap();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(main, VectorClock::default(), root)
+            .await
+            .unwrap();
+        let mut replica = Replica::clone(main, store).await.unwrap();
+
+        // The other peer has a valid key, but signs a commit in the history of the peer.
+        let mut version = VectorClock::default();
+        version.inc(peer);
+        let mut commit = replica.store().commit(peer, version, root).await.unwrap();
+        commit.signature = Some(CommitSignature::sign(other, &commit, &other_key).unwrap());
+        replica.store().insert_commit(&commit).await.unwrap();
+        assert_eq!(
+            replica.store().verify_commit(&commit).await.unwrap(),
+            CommitVerification::Signed(other)
+        );
+        let error = replica
+            .store()
+            .check_commit(peer, &commit)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("forged"));
+        assert!(replica.merge_with::<HashSet<u64>>(peer).await.is_err());
+        assert!(replica.store().check_commit(other, &commit).await.is_ok());
+    }
+}
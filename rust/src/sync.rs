@@ -0,0 +1,224 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+
+use crate::{Bundle, CommitId, HashMap, HashSet, ImportSummary, QuarkStore, ReplicaId};
+
+impl QuarkStore {
+    /// Returns a summary of the history in the store. It is sent to the other side of a sync to
+    /// tell it which commits, and thereby which refs and objects, do not have to be transferred.
+    ///
+    /// For the head of every replica and branch, the summary holds the head and the commits 1, 2,
+    /// 4, 8, ... steps behind it on its first-parent chain, plus the first commit of the chain.
+    /// It therefore grows with the logarithm of the history, while the other side still finds a
+    /// shared commit at most twice as far back as the point where the histories diverged.
+    pub async fn haves(&self) -> Result<HashSet<CommitId>> {
+        let parents = self
+            .commits()
+            .await?
+            .into_iter()
+            .map(|commit| (commit.id, commit.parent_commit_id))
+            .collect::<HashMap<_, _>>();
+
+        let mut tips = Vec::new();
+        for (replica_id, commit_id) in self.replica_heads().await? {
+            tips.push(commit_id);
+            tips.extend(
+                self.branch_heads(replica_id)
+                    .await?
+                    .into_iter()
+                    .map(|(_, commit_id)| commit_id),
+            );
+        }
+
+        let mut visited = HashSet::default();
+        let mut haves = HashSet::default();
+        for tip in tips {
+            let mut next = Some(tip);
+            let mut distance = 0u64;
+            while let Some(commit_id) = next {
+                // The commits behind this one were already sampled from another tip.
+                if !visited.insert(commit_id) {
+                    break;
+                }
+                next = parents.get(&commit_id).copied().flatten();
+                if distance == 0 || distance.is_power_of_two() || next.is_none() {
+                    haves.insert(commit_id);
+                }
+                distance += 1;
+            }
+        }
+        Ok(haves)
+    }
+
+    /// Packs the history of the given replicas, or of all replicas, which is missing on a store
+    /// that has the given commits, together with all of their ancestors. Only the given documents
+    /// are packed if keys are given.
+    pub async fn pack_missing(
+        &self,
+        replica_ids: Option<&[ReplicaId]>,
+        documents: Option<&BTreeSet<String>>,
+        haves: &HashSet<CommitId>,
+    ) -> Result<Bundle> {
+        let known = self.ancestors(haves).await?;
+        self.pack(replica_ids, documents, |commit| known.contains(&commit.id))
+            .await
+    }
+
+    /// Returns the given commits which are in this store, together with all of their ancestors.
+    async fn ancestors(&self, commit_ids: &HashSet<CommitId>) -> Result<HashSet<CommitId>> {
+        let commits = self
+            .commits()
+            .await?
+            .into_iter()
+            .map(|commit| (commit.id, commit))
+            .collect::<HashMap<_, _>>();
+
+        let mut stack = commit_ids
+            .iter()
+            .filter(|commit_id| commits.contains_key(commit_id))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut ancestors = HashSet::default();
+        while let Some(commit_id) = stack.pop() {
+            if !ancestors.insert(commit_id) {
+                continue;
+            }
+            if let Some(commit) = commits.get(&commit_id) {
+                stack.extend(commit.parent_commit_id);
+                stack.extend(commit.merge_parent_ids.iter().copied());
+            }
+        }
+        Ok(ancestors)
+    }
+
+    /// Fetches everything this store is missing from the remote and fast-forwards the heads of
+    /// the replicas. The heads of the remote are recorded under `remote_name`.
+    pub async fn pull(&self, remote_name: &str, remote: &QuarkStore) -> Result<ImportSummary> {
+        let haves = self.haves().await?;
+        let bundle = remote
+            .pack_missing(None, self.partial_documents(), &haves)
+            .await?;
+        let summary = self.import_bundle(&bundle).await?;
+
+        for head in &bundle.heads {
+            self.set_remote_head(remote_name, head.replica_id, head.commit_id)
+                .await?;
+        }
+        log::debug!("Pulled from {remote_name}: {summary:?}");
+        Ok(summary)
+    }
+
+    /// Sends everything the remote is missing to it and fast-forwards the heads of the replicas
+    /// on the remote. The resulting heads of the remote are recorded under `remote_name`.
+    pub async fn push(&self, remote_name: &str, remote: &QuarkStore) -> Result<ImportSummary> {
+        let haves = remote.haves().await?;
+        let bundle = self.pack_missing(None, None, &haves).await?;
+        let summary = remote.import_bundle(&bundle).await?;
+
+        for (replica_id, commit_id) in remote.replica_heads().await? {
+            self.set_remote_head(remote_name, replica_id, commit_id)
+                .await?;
+        }
+        log::debug!("Pushed to {remote_name}: {summary:?}");
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Id, RefStore, VersionedStore};
+
+    async fn commit_list(store: &QuarkStore, replica_id: ReplicaId, list: &[u64]) {
+        let mut version = store
+            .latest_commit_for_replica(replica_id)
+            .await
+            .unwrap()
+            .map(|commit| commit.version)
+            .unwrap_or_default();
+        version.inc(replica_id);
+        let root = store.insert(&list.to_vec()).await.unwrap();
+        store.commit(replica_id, version, root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_push_and_pull() {
+        let local = QuarkStore::test();
+        let remote = QuarkStore::test();
+        let local_replica = Id::gen();
+        let remote_replica = Id::gen();
+
+        commit_list(&local, local_replica, &[1, 2, 3]).await;
+        commit_list(&remote, remote_replica, &[4, 5]).await;
+
+        let pulled = local.pull("origin", &remote).await.unwrap();
+        assert_eq!(pulled.commits_imported, 1);
+        assert_eq!(pulled.updated_heads, vec![remote_replica]);
+        let remote_head = remote
+            .latest_commit_for_replica(remote_replica)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            local.remote_heads("origin").await.unwrap(),
+            vec![(remote_replica, remote_head.id)]
+        );
+
+        commit_list(&local, local_replica, &[1, 2, 3, 6]).await;
+        let pushed = local.push("origin", &remote).await.unwrap();
+        assert_eq!(pushed.commits_imported, 2);
+        assert_eq!(pushed.updated_heads, vec![local_replica]);
+
+        let head = remote
+            .latest_commit_for_replica(local_replica)
+            .await
+            .unwrap()
+            .unwrap();
+        let list: Vec<u64> = remote.resolve(head.root_ref).await.unwrap().unwrap();
+        assert_eq!(list, vec![1, 2, 3, 6]);
+        assert_eq!(local.remote_heads("origin").await.unwrap().len(), 2);
+
+        // Nothing is transferred once both stores are in sync.
+        let bundle = remote
+            .pack_missing(None, None, &local.haves().await.unwrap())
+            .await
+            .unwrap();
+        assert!(bundle.commits.is_empty());
+        assert!(bundle.refs.is_empty());
+        assert!(bundle.objects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_haves_summarize_long_histories() {
+        let local = QuarkStore::test();
+        let remote = QuarkStore::test();
+        let local_replica = Id::gen();
+        let remote_replica = Id::gen();
+
+        for i in 0..100 {
+            commit_list(&local, local_replica, &[i]).await;
+        }
+        let haves = local.haves().await.unwrap();
+        assert_eq!(haves.len(), 9);
+        let pushed = local.push("origin", &remote).await.unwrap();
+        assert_eq!(pushed.commits_imported, 100);
+
+        // Only the commits after the divergence are transferred in either direction.
+        for i in 100..105 {
+            commit_list(&local, local_replica, &[i]).await;
+        }
+        commit_list(&remote, remote_replica, &[0]).await;
+        let pulled = local.pull("origin", &remote).await.unwrap();
+        assert_eq!(pulled.commits_imported, 1);
+        assert_eq!(pulled.updated_heads, vec![remote_replica]);
+        let pushed = local.push("origin", &remote).await.unwrap();
+        assert_eq!(pushed.commits_imported, 5);
+
+        let bundle = local
+            .pack_missing(None, None, &remote.haves().await.unwrap())
+            .await
+            .unwrap();
+        assert!(bundle.commits.is_empty());
+    }
+}
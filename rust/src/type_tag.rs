@@ -0,0 +1,201 @@
+use anyhow::{bail, Result};
+use musli::{Decode, Encode};
+use sha2::{Digest, Sha256};
+
+use crate::{HashSet, MrdtItem};
+
+/// Identifies the type of the object a commit points to, together with a fingerprint of its
+/// schema, so readers can detect that they decode an object with the wrong type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct TypeTag {
+    pub name: String,
+    pub fingerprint: u64,
+}
+
+impl TypeTag {
+    /// Creates a tag whose fingerprint is derived from the name, for types whose encoding is
+    /// fully determined by their name.
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let fingerprint = Self::fingerprint(&name);
+        Self { name, fingerprint }
+    }
+
+    /// Creates a tag whose fingerprint is derived from the given description of the encoded
+    /// structure, see [`ItemName::item_schema`]. The fingerprint is the first eight bytes of the
+    /// SHA-256 hash of the schema, so it is the same on every platform and across Rust releases.
+    pub fn with_schema(name: impl Into<String>, schema: &str) -> Self {
+        Self {
+            name: name.into(),
+            fingerprint: Self::fingerprint(schema),
+        }
+    }
+
+    /// Creates a tag with an explicit fingerprint, which should change whenever the encoding of
+    /// the type changes.
+    pub fn with_fingerprint(name: impl Into<String>, fingerprint: u64) -> Self {
+        Self {
+            name: name.into(),
+            fingerprint,
+        }
+    }
+
+    fn fingerprint(schema: &str) -> u64 {
+        let digest = Sha256::digest(schema.as_bytes());
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    }
+
+    /// Checks that an object which was committed with one of the given tags can be read as
+    /// `T`. Objects without any recorded tag are accepted.
+    pub fn verify<T: Tagged>(root: u64, committed: &[TypeTag]) -> Result<()> {
+        let expected = T::type_tag();
+        if committed.is_empty() || committed.contains(&expected) {
+            return Ok(());
+        }
+
+        match committed.iter().find(|tag| tag.name == expected.name) {
+            Some(tag) => bail!(
+                "Schema mismatch for {}: ref {root} was committed with schema {:x}, but is read with schema {:x}",
+                expected.name,
+                tag.fingerprint,
+                expected.fingerprint
+            ),
+            None => bail!(
+                "Type mismatch: ref {root} was committed as {}, but is read as {}",
+                committed
+                    .iter()
+                    .map(|tag| tag.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                expected.name
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for TypeTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{:x}", self.name, self.fingerprint)
+    }
+}
+
+/// Implemented by all types which can be stored in a [`crate::QuarkStore`], to record their
+/// [`TypeTag`] with every commit.
+pub trait Tagged {
+    fn type_tag() -> TypeTag;
+}
+
+/// Names and describes the items of a collection in its [`TypeTag`]. The name is persisted with
+/// every commit, so it has to stay the same when the type is renamed or moved to another module.
+pub trait ItemName {
+    fn item_name() -> String;
+
+    /// Describes the encoded structure of the item, e.g. `Person { name: String, age: u8 }`. The
+    /// schema fingerprint of a collection is derived from the schema of its items, so it has to
+    /// change whenever a field is added, removed, renamed or changes its type.
+    fn item_schema() -> String;
+}
+
+macro_rules! impl_item_name {
+    ($($ty:ty),*) => {
+        $(
+            impl ItemName for $ty {
+                fn item_name() -> String {
+                    stringify!($ty).to_string()
+                }
+
+                fn item_schema() -> String {
+                    Self::item_name()
+                }
+            }
+        )*
+    };
+}
+
+impl_item_name!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, String);
+
+impl<A: ItemName, B: ItemName> ItemName for (A, B) {
+    fn item_name() -> String {
+        format!("({}, {})", A::item_name(), B::item_name())
+    }
+
+    fn item_schema() -> String {
+        format!("({}, {})", A::item_schema(), B::item_schema())
+    }
+}
+
+impl<T: MrdtItem + ItemName> Tagged for Vec<T> {
+    fn type_tag() -> TypeTag {
+        TypeTag::with_schema(
+            format!("Vec<{}>", T::item_name()),
+            &format!("Vec<{}>", T::item_schema()),
+        )
+    }
+}
+
+impl<T: MrdtItem + ItemName> Tagged for HashSet<T> {
+    fn type_tag() -> TypeTag {
+        TypeTag::with_schema(
+            format!("HashSet<{}>", T::item_name()),
+            &format!("HashSet<{}>", T::item_schema()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_type_tags() {
+        let committed = [<Vec<u64>>::type_tag()];
+        assert!(TypeTag::verify::<Vec<u64>>(1, &[]).is_ok());
+        assert!(TypeTag::verify::<Vec<u64>>(1, &committed).is_ok());
+
+        let error = TypeTag::verify::<HashSet<u64>>(1, &committed).unwrap_err();
+        assert!(error.to_string().starts_with("Type mismatch"));
+
+        let changed = TypeTag::with_fingerprint(committed[0].name.clone(), 42);
+        let error = TypeTag::verify::<Vec<u64>>(1, &[changed]).unwrap_err();
+        assert!(error.to_string().starts_with("Schema mismatch"));
+    }
+
+    #[test]
+    fn test_type_tags_are_stable() {
+        let tag = <Vec<(u64, String)>>::type_tag();
+        assert_eq!(tag.name, "Vec<(u64, String)>");
+        assert_eq!(TypeTag::new("Vec<u64>").fingerprint, 0xc1f5dce7d5fdaf31);
+    }
+
+    #[test]
+    fn test_fingerprint_follows_item_structure() {
+        #[derive(Clone, PartialEq, Eq, Hash, Encode, Decode)]
+        struct Item;
+        impl ItemName for Item {
+            fn item_name() -> String {
+                "Item".to_string()
+            }
+
+            fn item_schema() -> String {
+                "Item { value: u64 }".to_string()
+            }
+        }
+
+        #[derive(Clone, PartialEq, Eq, Hash, Encode, Decode)]
+        struct ChangedItem;
+        impl ItemName for ChangedItem {
+            fn item_name() -> String {
+                "Item".to_string()
+            }
+
+            fn item_schema() -> String {
+                "Item { value: u64, label: String }".to_string()
+            }
+        }
+
+        let tag = <Vec<Item>>::type_tag();
+        let changed = <Vec<ChangedItem>>::type_tag();
+        assert_eq!(tag.name, changed.name);
+        assert_ne!(tag.fingerprint, changed.fingerprint);
+    }
+}
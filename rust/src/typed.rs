@@ -0,0 +1,154 @@
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    Commit, Deserialize, Mergeable, QuarkStore, Replica, ReplicaId, Serialize, Tagged, VectorClock,
+};
+
+/// A [`Replica`] whose commits all contain objects of the type `T`.
+///
+/// The replica keeps the object of its latest commit as working state, so reading it does not
+/// hit the store. The type is recorded in the store when the replica is opened for the first
+/// time, opening it with a different type afterwards fails.
+pub struct TypedReplica<T> {
+    replica: Replica,
+    state: T,
+}
+
+impl<T: Serialize + Deserialize + Mergeable + Clone> TypedReplica<T> {
+    /// Opens the replica with the given id, see [`Replica::open`]. The recorded type of the
+    /// replica is checked before anything is written to the store.
+    pub async fn open(id: ReplicaId, store: QuarkStore) -> Result<Self> {
+        check_type_tag::<T>(&store, id).await?;
+        Self::from_replica(Replica::open(id, store).await?).await
+    }
+
+    /// Wraps an existing replica, after checking that it stores objects of the type `T`.
+    pub async fn from_replica(replica: Replica) -> Result<Self> {
+        check_type_tag::<T>(replica.store(), replica.id()).await?;
+        let state = replica
+            .latest_object::<T>()
+            .await?
+            .with_context(|| format!("Replica {} has no object", replica.id()))?;
+        replica
+            .store()
+            .set_replica_type_tag(replica.id(), &T::type_tag())
+            .await?;
+        Ok(Self { replica, state })
+    }
+
+    pub fn id(&self) -> ReplicaId {
+        self.replica.id()
+    }
+
+    /// Returns the object of the latest commit.
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    pub fn latest_commit(&self) -> &Commit {
+        self.replica.latest_commit()
+    }
+
+    pub fn latest_version(&self) -> &VectorClock {
+        self.replica.latest_version()
+    }
+
+    /// Applies the change to a copy of the current state and commits the result. The working
+    /// state is only replaced once the commit succeeded.
+    pub async fn update(&mut self, change: impl FnOnce(&mut T)) -> Result<Commit> {
+        let mut state = self.state.clone();
+        change(&mut state);
+        let commit = self.replica.commit_object(&state).await?;
+        self.state = state;
+        Ok(commit)
+    }
+
+    /// Merges with another replica, which has to store objects of the same type.
+    pub async fn merge_with(&mut self, other_replica: ReplicaId) -> Result<Commit> {
+        check_type_tag::<T>(self.replica.store(), other_replica).await?;
+        let (commit, state) = self.replica.merge_with::<T>(other_replica).await?;
+        self.state = state;
+        Ok(commit)
+    }
+
+    /// Returns the untyped replica.
+    pub fn into_inner(self) -> Replica {
+        self.replica
+    }
+}
+
+/// Compares the full recorded tag, so a replica whose schema changed is rejected as well.
+async fn check_type_tag<T: Tagged>(store: &QuarkStore, replica_id: ReplicaId) -> Result<()> {
+    let expected = T::type_tag();
+    match store.replica_type_tag(replica_id).await? {
+        Some(tag) if tag != expected => {
+            bail!("Replica {replica_id} stores objects of type {tag}, but was opened as {expected}")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashSet, Id, RefStore, TypeTag, VersionedStore};
+
+    #[tokio::test]
+    async fn test_typed_replica() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+
+        let mut replica = TypedReplica::<HashSet<u64>>::open(replica_id, store)
+            .await
+            .unwrap();
+        replica.update(|set| _ = set.insert(2)).await.unwrap();
+        assert_eq!(replica.state(), &HashSet::from_iter([1, 2]));
+
+        let replica = replica.into_inner();
+        let latest: HashSet<u64> = replica.latest_object().await.unwrap().unwrap();
+        assert_eq!(latest, HashSet::from_iter([1, 2]));
+        assert!(TypedReplica::<Vec<u64>>::from_replica(replica)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_keeps_head_and_checks_fingerprint() {
+        let store = QuarkStore::test();
+        let replica_id = Id::gen();
+        let root = store.insert(&HashSet::from_iter([1u64])).await.unwrap();
+        store
+            .commit(replica_id, VectorClock::default(), root)
+            .await
+            .unwrap();
+
+        let mut replica = TypedReplica::<HashSet<u64>>::open(replica_id, store)
+            .await
+            .unwrap();
+        let head = replica.update(|set| _ = set.insert(2)).await.unwrap();
+        let store = replica.into_inner().into_store();
+
+        let replica = TypedReplica::<HashSet<u64>>::open(replica_id, store)
+            .await
+            .unwrap();
+        assert_eq!(replica.latest_commit(), &head);
+        assert_eq!(replica.state(), &HashSet::from_iter([1, 2]));
+
+        let store = replica.into_inner().into_store();
+        let changed = TypeTag::with_fingerprint(<HashSet<u64>>::type_tag().name, 1);
+        store
+            .set_replica_type_tag(replica_id, &changed)
+            .await
+            .unwrap();
+        let error = TypedReplica::<HashSet<u64>>::open(replica_id, store)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("was opened as"));
+    }
+}
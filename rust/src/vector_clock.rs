@@ -66,12 +66,47 @@ impl VectorClock {
         self.timestamps.get(&id).cloned()
     }
 
+    /// Returns the replicas and their timestamps, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Id, Timestamp)> + '_ {
+        self.timestamps
+            .iter()
+            .map(|(id, timestamp)| (*id, *timestamp))
+    }
+
     pub fn inc(&mut self, id: Id) {
         let new_time = self.time_of(id).unwrap_or_default().inc();
         self.timestamps.insert(id, new_time);
     }
 }
 
+/// Orders vector clocks causally, concurrent clocks are incomparable.
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        use std::cmp::Ordering;
+
+        let mut less = false;
+        let mut greater = false;
+        for id in self.timestamps.keys().chain(other.timestamps.keys()) {
+            match self
+                .time_of(*id)
+                .unwrap_or_default()
+                .cmp(&other.time_of(*id).unwrap_or_default())
+            {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
+            }
+        }
+
+        match (less, greater) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+}
+
 impl From<&[(Id, Timestamp)]> for VectorClock {
     fn from(timestamps: &[(Id, Timestamp)]) -> Self {
         let mut map = fxhash::FxHashMap::default();
@@ -117,6 +152,24 @@ mod tests {
         assert_eq!(lca.time_of(id2), Some(Timestamp::from(3)));
     }
 
+    #[test]
+    fn test_causal_order() {
+        let id1 = Id::gen();
+        let id2 = Id::gen();
+
+        let vc1 = VectorClock::from([(id1, Timestamp::from(1))].as_slice());
+        let vc2 =
+            VectorClock::from([(id1, Timestamp::from(2)), (id2, Timestamp::from(1))].as_slice());
+        let vc3 = VectorClock::from([(id2, Timestamp::from(2))].as_slice());
+
+        assert!(VectorClock::default() < vc1);
+        assert!(vc1 < vc2);
+        assert!(vc2 > vc1);
+        assert!(vc1 <= vc1.clone());
+        assert_eq!(vc2.partial_cmp(&vc3), None);
+        assert_eq!(vc1.partial_cmp(&vc3), None);
+    }
+
     #[test]
     fn test_sum() {
         let id1 = Id::gen();